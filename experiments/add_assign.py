@@ -1,9 +1,9 @@
 #!/bin/python
 
 # Run this to visually explain the current algorithm for all assign operators.
+# (Two-stage reduction, see CAT(KERNEL_NAME, _down) in vec_arithmetic.cl)
 
 from colorama import Back
-import math
 
 def index_print(array: list, highlights: list):
     colors = [Back.GREEN, Back.RED]
@@ -23,26 +23,54 @@ def index_print(array: list, highlights: list):
     print("]")
 
 
-data = []
+# Simulates one pass of the kernel. Every work-group reduces its part of the
+# input into one element of the output.
+def reduce_pass(data: list, groups: int, local_size: int) -> list:
+    global_size = groups * local_size
+    output = []
 
-for ix in range(0,10):
-    data.append(5)
+    for group in range(0, groups):
+        # Every work-item accumulates with a stride of the global size.
+        scratch = []
+        for lid in range(0, local_size):
+            acc = 0
+            for i in range(group * local_size + lid, len(data), global_size):
+                acc += data[i]
+            scratch.append(acc)
 
-print(data)
-print("------------------")
+        print("group", group, "after accumulation:")
+        print(scratch)
+
+        # Tree reduction in local memory. (Separated by barriers)
+        width = local_size
+        while width > 1:
+            step = (width + 1) // 2
+
+            for lid in range(0, width // 2):
+                index_print(scratch, [lid, lid + step])
+                scratch[lid] += scratch[lid + step]
+
+            width = step
 
+        output.append(scratch[0])
 
-for k in range(0, int(math.sqrt(len(data))) + 1, 1):
-    for ix in range(0, len(data), 2 << k):
-        io = ix + (1 << k)
+    return output
 
-        index_print(data, [ix, io])
 
-        if io < len(data):
-            data[ix] += data[io]
-            data[io] = 1
+data = []
 
+for ix in range(0, 23):
+    data.append(5)
 
-    print("------------------")
+local_size = 3
+groups = min((len(data) + local_size - 1) // local_size, local_size)
 
 print(data)
+print("------------------")
+
+partial = reduce_pass(data, groups, local_size)
+print("------------------")
+print(partial)
+print("------------------")
+
+print(reduce_pass(partial, 1, local_size))
@@ -0,0 +1,109 @@
+#include "helpers.h"
+
+// BLAS level 1 routines, which don't fit into the operator-generic kernels.
+//
+// The *_down kernels are the first stage of a reduction, same as
+// CAT(KERNEL_NAME, _down) in reduce.h. Their partial results are ACC_T and
+// reduced by add_down_acc.
+
+// Sums acc of every work-item over the work-group into scratch[0].
+inline void blas1_sum(ACC_T acc, __local ACC_T *scratch)
+{
+	SIZE_T lid = get_local_id(0);
+
+	scratch[lid] = acc;
+	barrier(CLK_LOCAL_MEM_FENCE);
+
+	for (SIZE_T width = get_local_size(0); width > 1;) {
+		SIZE_T step = (width + 1) / 2;
+
+		if (lid < width / 2) {
+			scratch[lid] += scratch[lid + step];
+		}
+		barrier(CLK_LOCAL_MEM_FENCE);
+
+		width = step;
+	}
+}
+
+// Sum of x * y.
+__kernel void dot_down(__global const TYPE_T *x, __global const TYPE_T *y,
+		       SIZE_T w, __global ACC_T *output,
+		       __local ACC_T *scratch)
+{
+	ACC_T acc = (ACC_T)0;
+
+	for (SIZE_T i = get_global_id(0); i < w; i += get_global_size(0)) {
+		acc += (ACC_T)x[i] * (ACC_T)y[i];
+	}
+
+	blas1_sum(acc, scratch);
+
+	if (get_local_id(0) == 0) {
+		output[get_group_id(0)] = scratch[0];
+	}
+}
+
+// Sum of (x / scale)^2. With the largest absolute value as scale, the squares
+// can neither overflow nor all underflow.
+__kernel void nrm2_down(TYPE_T scale, __global const TYPE_T *x, SIZE_T w,
+			__global ACC_T *output, __local ACC_T *scratch)
+{
+	ACC_T acc = (ACC_T)0;
+
+	for (SIZE_T i = get_global_id(0); i < w; i += get_global_size(0)) {
+		ACC_T scaled = (ACC_T)x[i] / (ACC_T)scale;
+		acc += scaled * scaled;
+	}
+
+	blas1_sum(acc, scratch);
+
+	if (get_local_id(0) == 0) {
+		output[get_group_id(0)] = scratch[0];
+	}
+}
+
+// Every work-item writes the largest absolute value of its elements and its
+// index to output[get_global_id(0)], which are then reduced by max_arg_down.
+// Ties go to the lower index and NaNs are never picked.
+__kernel void iamax_down(__global const TYPE_T *x, SIZE_T w,
+			 __global TYPE_T *output, __global SIZE_T *idx_output)
+{
+	TYPE_T acc = (TYPE_T)-INFINITY;
+	SIZE_T idx = (SIZE_T)-1;
+
+	// The indices only grow, so ties already go to the lower index.
+	for (SIZE_T i = get_global_id(0); i < w; i += get_global_size(0)) {
+		TYPE_T value = fabs(x[i]);
+
+		if (value > acc) {
+			acc = value;
+			idx = i;
+		}
+	}
+
+	output[get_global_id(0)] = acc;
+	idx_output[get_global_id(0)] = idx;
+}
+
+// y = a * x + y
+__kernel void axpy(TYPE_T a, __global const TYPE_T *x, __global TYPE_T *y,
+		   SIZE_T w)
+{
+	for (SIZE_T i = get_global_id(0); i < w; i += get_global_size(0)) {
+		y[i] = a * x[i] + y[i];
+	}
+}
+
+// Plane rotation of the points (x, y).
+__kernel void rot(TYPE_T c, TYPE_T s, __global TYPE_T *x, __global TYPE_T *y,
+		  SIZE_T w)
+{
+	for (SIZE_T i = get_global_id(0); i < w; i += get_global_size(0)) {
+		TYPE_T xi = x[i];
+		TYPE_T yi = y[i];
+
+		x[i] = c * xi + s * yi;
+		y[i] = c * yi - s * xi;
+	}
+}
@@ -0,0 +1,120 @@
+#include "helpers.h"
+
+// BLAS level 2 routines. All matrices are stored in row-major order.
+
+// y = alpha * a * x + beta * y, where a is m x n.
+//
+// Every work-group computes one element of y, so its work-items read
+// neighbouring elements of the row. (See group_op in device.rs)
+// With beta = 0, y is never read.
+__kernel void gemv(SIZE_T n, TYPE_T alpha, __global const TYPE_T *a,
+		   __global const TYPE_T *x, TYPE_T beta, __global TYPE_T *y,
+		   __local ACC_T *scratch)
+{
+	SIZE_T row = get_group_id(0);
+	SIZE_T lid = get_local_id(0);
+	ACC_T acc = (ACC_T)0;
+
+	for (SIZE_T i = lid; i < n; i += get_local_size(0)) {
+		acc += (ACC_T)a[row * n + i] * (ACC_T)x[i];
+	}
+
+	scratch[lid] = acc;
+	barrier(CLK_LOCAL_MEM_FENCE);
+
+	for (SIZE_T width = get_local_size(0); width > 1;) {
+		SIZE_T step = (width + 1) / 2;
+
+		if (lid < width / 2) {
+			scratch[lid] += scratch[lid + step];
+		}
+		barrier(CLK_LOCAL_MEM_FENCE);
+
+		width = step;
+	}
+
+	if (lid == 0) {
+		ACC_T result = (ACC_T)alpha * scratch[0];
+
+		if (beta != (TYPE_T)0) {
+			result += (ACC_T)beta * (ACC_T)y[row];
+		}
+
+		y[row] = (TYPE_T)result;
+	}
+}
+
+// y = alpha * a^T * x + beta * y, where a is m x w.
+//
+// Every work-item computes one element of y, so neighbouring work-items read
+// neighbouring elements of every row. With beta = 0, y is never read.
+__kernel void gemv_t(SIZE_T m, TYPE_T alpha, __global const TYPE_T *a,
+		     __global const TYPE_T *x, TYPE_T beta, __global TYPE_T *y,
+		     SIZE_T w)
+{
+	for (SIZE_T col = get_global_id(0); col < w; col += get_global_size(0)) {
+		ACC_T acc = (ACC_T)0;
+
+		for (SIZE_T row = 0; row < m; row++) {
+			acc += (ACC_T)a[row * w + col] * (ACC_T)x[row];
+		}
+
+		ACC_T result = (ACC_T)alpha * acc;
+
+		if (beta != (TYPE_T)0) {
+			result += (ACC_T)beta * (ACC_T)y[col];
+		}
+
+		y[col] = (TYPE_T)result;
+	}
+}
+
+// a = alpha * x * y^T + a, where a is m x n.
+//
+// Runs on a two-dimensional grid, where dimension 0 is the column and
+// dimension 1 the row. (See tiled_op in device.rs)
+__kernel void ger(SIZE_T m, SIZE_T n, TYPE_T alpha, __global const TYPE_T *x,
+		  __global const TYPE_T *y, __global TYPE_T *a)
+{
+	SIZE_T col = get_global_id(0);
+	SIZE_T row = get_global_id(1);
+
+	if (row < m && col < n) {
+		a[row * n + col] += alpha * x[row] * y[col];
+	}
+}
+
+// Solves op(a) * x = b in place, where a is an n x n triangular matrix and x
+// holds b. upper tells which triangle of a is used and unit_diag treats its
+// diagonal as all ones, without reading it.
+//
+// Every column of op(a) depends on the previous ones, so this runs as a single
+// work-group, which can wait for its own writes to x with a barrier.
+__kernel void trsv(SIZE_T n, SIZE_T upper, SIZE_T trans, SIZE_T unit_diag,
+		   __global const TYPE_T *a, __global TYPE_T *x)
+{
+	SIZE_T lid = get_local_id(0);
+
+	// op(a) is lower triangular, so the solve runs forwards.
+	bool forward = (upper != 0) == (trans != 0);
+
+	for (SIZE_T s = 0; s < n; s++) {
+		SIZE_T j = forward ? s : n - 1 - s;
+
+		if (lid == 0 && !unit_diag) {
+			x[j] /= a[j * n + j];
+		}
+		barrier(CLK_GLOBAL_MEM_FENCE);
+
+		TYPE_T xj = x[j];
+
+		// Eliminate x[j] from all rows which are still unsolved.
+		for (SIZE_T r = s + 1 + lid; r < n; r += get_local_size(0)) {
+			SIZE_T i = forward ? r : n - 1 - r;
+			TYPE_T aij = trans ? a[j * n + i] : a[i * n + j];
+
+			x[i] -= aij * xj;
+		}
+		barrier(CLK_GLOBAL_MEM_FENCE);
+	}
+}
@@ -0,0 +1,66 @@
+#include "helpers.h"
+
+// BLAS level 3 routines. All matrices are stored in row-major order.
+//
+// The kernels run on a grid of square work-groups (See tiled_op in device.rs),
+// where dimension 0 is the column and dimension 1 the row. The local tiles have
+// a padding column, so their stride is get_local_size(0) + 1.
+
+// c = alpha * op(a) * op(b) + beta * c
+//
+// op(a) is m x k and op(b) is k x n. If trans_a is set, a is stored as k x m
+// and transposed while it is loaded, same for trans_b. With beta = 0, c is never
+// read, so it may contain anything. (Even NaNs)
+__kernel void gemm(SIZE_T m, SIZE_T n, SIZE_T k, TYPE_T alpha,
+		   __global const TYPE_T *a, SIZE_T trans_a,
+		   __global const TYPE_T *b, SIZE_T trans_b, TYPE_T beta,
+		   __global TYPE_T *c, __local TYPE_T *tile_a,
+		   __local TYPE_T *tile_b)
+{
+	SIZE_T tile = get_local_size(0);
+	SIZE_T stride = tile + 1;
+
+	SIZE_T lcol = get_local_id(0);
+	SIZE_T lrow = get_local_id(1);
+	SIZE_T col = get_global_id(0);
+	SIZE_T row = get_global_id(1);
+
+	ACC_T acc = (ACC_T)0;
+
+	for (SIZE_T t = 0; t < k; t += tile) {
+		// Every work-item loads one element of both tiles. Elements
+		// outside of the matrices are zero, so they don't change the sum.
+		SIZE_T ka = t + lcol;
+		SIZE_T kb = t + lrow;
+
+		TYPE_T value_a = (TYPE_T)0;
+		if (row < m && ka < k) {
+			value_a = trans_a ? a[ka * m + row] : a[row * k + ka];
+		}
+
+		TYPE_T value_b = (TYPE_T)0;
+		if (kb < k && col < n) {
+			value_b = trans_b ? b[col * k + kb] : b[kb * n + col];
+		}
+
+		tile_a[lrow * stride + lcol] = value_a;
+		tile_b[lrow * stride + lcol] = value_b;
+		barrier(CLK_LOCAL_MEM_FENCE);
+
+		for (SIZE_T p = 0; p < tile; p++) {
+			acc += (ACC_T)tile_a[lrow * stride + p] *
+			       (ACC_T)tile_b[p * stride + lcol];
+		}
+		barrier(CLK_LOCAL_MEM_FENCE);
+	}
+
+	if (row < m && col < n) {
+		ACC_T result = (ACC_T)alpha * acc;
+
+		if (beta != (TYPE_T)0) {
+			result += (ACC_T)beta * (ACC_T)c[row * n + col];
+		}
+
+		c[row * n + col] = (TYPE_T)result;
+	}
+}
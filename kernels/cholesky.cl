@@ -0,0 +1,38 @@
+#include "helpers.h"
+
+// Steps of the Cholesky decomposition of the n x n row-major matrix a, whose
+// lower triangle is overwritten by L. For every column k, chol_diag, lu_scale
+// (lu.cl) and chol_update run in this order. (See try_cholesky in linalg.rs)
+
+// Replaces the diagonal element of column k by its square root. If it isn't
+// positive, the matrix isn't positive definite and the first such column is
+// stored in status as k + 1. Only the first work-item does anything.
+__kernel void chol_diag(SIZE_T k, __global TYPE_T *a, __global SIZE_T *status,
+			SIZE_T w)
+{
+	if (get_global_id(0) != 0) {
+		return;
+	}
+
+	TYPE_T d = a[k * w + k];
+
+	// Also catches NaN.
+	if (!(d > (TYPE_T)0) && status[0] == 0) {
+		status[0] = k + 1;
+	}
+
+	a[k * w + k] = sqrt(d);
+}
+
+// Subtracts the outer product of column k of L with itself from the lower
+// triangle of the remaining lower right block. Runs on a two-dimensional grid
+// over that block. (See tiled_op in device.rs)
+__kernel void chol_update(SIZE_T n, SIZE_T k, __global TYPE_T *a)
+{
+	SIZE_T col = k + 1 + get_global_id(0);
+	SIZE_T row = k + 1 + get_global_id(1);
+
+	if (row < n && col <= row) {
+		a[row * n + col] -= a[row * n + k] * a[col * n + k];
+	}
+}
@@ -0,0 +1,134 @@
+#include "helpers.h"
+
+// Eigenvalue solvers for symmetric n x n row-major matrices.
+// (See try_eigh and try_power_iteration in linalg.rs)
+
+// Applies the Jacobi rotation which zeroes a[p, q] and a[q, p], so
+// a = J^T * a * J, and accumulates it into the eigenvectors, v = v * J.
+//
+// The columns and rows have to be rotated one after another, so this runs as a
+// single work-group, which can wait for its own writes with a barrier.
+__kernel void jacobi_rotate(SIZE_T n, SIZE_T p, SIZE_T q, __global TYPE_T *a,
+			    __global TYPE_T *v)
+{
+	SIZE_T lid = get_local_id(0);
+	ACC_T apq = (ACC_T)a[p * n + q];
+
+	if (apq == (ACC_T)0) {
+		return;
+	}
+
+	// The smaller of the two angles, which is more stable.
+	ACC_T theta = ((ACC_T)a[q * n + q] - (ACC_T)a[p * n + p]) / (2 * apq);
+	ACC_T t = (theta >= (ACC_T)0 ? (ACC_T)1 : (ACC_T)-1) /
+		  (fabs(theta) + sqrt(theta * theta + (ACC_T)1));
+	ACC_T c = (ACC_T)1 / sqrt(t * t + (ACC_T)1);
+	ACC_T s = t * c;
+	barrier(CLK_GLOBAL_MEM_FENCE);
+
+	// a * J and v * J
+	for (SIZE_T k = lid; k < n; k += get_local_size(0)) {
+		ACC_T akp = (ACC_T)a[k * n + p];
+		ACC_T akq = (ACC_T)a[k * n + q];
+		ACC_T vkp = (ACC_T)v[k * n + p];
+		ACC_T vkq = (ACC_T)v[k * n + q];
+
+		a[k * n + p] = (TYPE_T)(c * akp - s * akq);
+		a[k * n + q] = (TYPE_T)(s * akp + c * akq);
+		v[k * n + p] = (TYPE_T)(c * vkp - s * vkq);
+		v[k * n + q] = (TYPE_T)(s * vkp + c * vkq);
+	}
+	barrier(CLK_GLOBAL_MEM_FENCE);
+
+	// J^T * a
+	for (SIZE_T k = lid; k < n; k += get_local_size(0)) {
+		ACC_T apk = (ACC_T)a[p * n + k];
+		ACC_T aqk = (ACC_T)a[q * n + k];
+
+		a[p * n + k] = (TYPE_T)(c * apk - s * aqk);
+		a[q * n + k] = (TYPE_T)(s * apk + c * aqk);
+	}
+	barrier(CLK_GLOBAL_MEM_FENCE);
+
+	// Rounding leaves tiny values behind.
+	if (lid == 0) {
+		a[p * n + q] = (TYPE_T)0;
+		a[q * n + p] = (TYPE_T)0;
+	}
+}
+
+// Sums scratch over the work-group into scratch[0].
+inline void eigen_reduce(__local ACC_T *scratch)
+{
+	SIZE_T lid = get_local_id(0);
+
+	barrier(CLK_LOCAL_MEM_FENCE);
+
+	for (SIZE_T width = get_local_size(0); width > 1;) {
+		SIZE_T step = (width + 1) / 2;
+
+		if (lid < width / 2) {
+			scratch[lid] += scratch[lid + step];
+		}
+		barrier(CLK_LOCAL_MEM_FENCE);
+
+		width = step;
+	}
+}
+
+// One step of the power iteration, after y = a * x. Replaces the unit vector x
+// by y / |y|, with the sign of x^T * y, so that it doesn't flip for negative
+// eigenvalues. result gets the Rayleigh quotient x^T * y and the distance
+// between the old and the new x. If y is zero, x is an eigenvector of the
+// eigenvalue zero already and stays as it is. Runs as a single work-group.
+__kernel void power_normalize(SIZE_T n, __global TYPE_T *x,
+			      __global const TYPE_T *y, __global TYPE_T *result,
+			      __local ACC_T *dots, __local ACC_T *norms)
+{
+	SIZE_T lid = get_local_id(0);
+	ACC_T dot = (ACC_T)0;
+	ACC_T norm = (ACC_T)0;
+
+	for (SIZE_T i = lid; i < n; i += get_local_size(0)) {
+		dot += (ACC_T)x[i] * (ACC_T)y[i];
+		norm += (ACC_T)y[i] * (ACC_T)y[i];
+	}
+
+	dots[lid] = dot;
+	norms[lid] = norm;
+	eigen_reduce(dots);
+	eigen_reduce(norms);
+
+	dot = dots[0];
+
+	// The whole work-group returns here, so no barrier is skipped.
+	if (norms[0] == (ACC_T)0) {
+		if (lid == 0) {
+			result[0] = (TYPE_T)0;
+			result[1] = (TYPE_T)0;
+		}
+		return;
+	}
+
+	ACC_T scale = (dot >= (ACC_T)0 ? (ACC_T)1 : (ACC_T)-1) / sqrt(norms[0]);
+	barrier(CLK_LOCAL_MEM_FENCE);
+
+	// Every work-item only touches its own elements of x.
+	ACC_T diff = (ACC_T)0;
+
+	for (SIZE_T i = lid; i < n; i += get_local_size(0)) {
+		ACC_T value = (ACC_T)y[i] * scale;
+		ACC_T d = value - (ACC_T)x[i];
+
+		diff += d * d;
+		x[i] = (TYPE_T)value;
+	}
+
+	norms[lid] = diff;
+	eigen_reduce(norms);
+
+	if (lid == 0) {
+		result[0] = (TYPE_T)dot;
+		result[1] = (TYPE_T)sqrt(norms[0]);
+	}
+}
@@ -0,0 +1,44 @@
+#include "helpers.h"
+
+// Steps of the Gauss-Jordan elimination of the augmented n x w row-major
+// matrix a = [A | I], which leaves the inverse of A in its right half. For
+// every column k, lu_pivot, lu_swap (lu.cl), gj_scale and gj_eliminate run in
+// this order. (See try_inverse in linalg.rs)
+//
+// Column k is never written while it is being eliminated, so it keeps stale
+// values, but it isn't read by any later step either.
+
+// Divides row k by the pivot, if its absolute value is larger than threshold.
+// Otherwise the matrix counts as singular and the first such column is stored
+// in status as k + 1.
+__kernel void gj_scale(SIZE_T k, ACC_T threshold, __global TYPE_T *a,
+		       __global SIZE_T *status, SIZE_T w)
+{
+	ACC_T pivot = (ACC_T)a[k * w + k];
+
+	// Also catches NaN.
+	if (!(fabs(pivot) > threshold)) {
+		if (get_global_id(0) == 0 && status[0] == 0) {
+			status[0] = k + 1;
+		}
+		return;
+	}
+
+	for (SIZE_T j = k + 1 + get_global_id(0); j < w;
+	     j += get_global_size(0)) {
+		a[k * w + j] = (TYPE_T)((ACC_T)a[k * w + j] / pivot);
+	}
+}
+
+// Subtracts multiples of row k from all other rows, right of column k. Runs on
+// a two-dimensional grid over those columns and all rows. (See tiled_op in
+// device.rs)
+__kernel void gj_eliminate(SIZE_T n, SIZE_T w, SIZE_T k, __global TYPE_T *a)
+{
+	SIZE_T col = k + 1 + get_global_id(0);
+	SIZE_T row = get_global_id(1);
+
+	if (row < n && row != k && col < w) {
+		a[row * w + col] -= a[row * w + k] * a[k * w + col];
+	}
+}
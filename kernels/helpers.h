@@ -5,6 +5,10 @@
 #define TYPE_T float
 #endif
 
+#ifndef ACC_T
+#define ACC_T TYPE_T
+#endif
+
 #define SIZE_T unsigned long
 
 #ifndef OPERATOR
@@ -0,0 +1,101 @@
+#include "helpers.h"
+
+// Steps of the LU decomposition with partial pivoting of the n x n row-major
+// matrix a, which is overwritten by L (below the diagonal, without its unit
+// diagonal) and U. For every column k, lu_pivot, lu_swap, lu_scale and
+// lu_update run in this order. (See try_lu in linalg.rs)
+
+// Picks the row with the largest absolute value in column k, starting at row k,
+// and stores it in piv[k]. Ties go to the lower row and NaNs are never picked.
+// a has n rows of width w, which allows an augmented matrix. Runs as a single
+// work-group.
+__kernel void lu_pivot(SIZE_T n, SIZE_T w, SIZE_T k, __global const TYPE_T *a,
+		       __global SIZE_T *piv, __local ACC_T *values,
+		       __local SIZE_T *indices)
+{
+	SIZE_T lid = get_local_id(0);
+	ACC_T best = (ACC_T)-1;
+	SIZE_T idx = k;
+
+	for (SIZE_T i = k + lid; i < n; i += get_local_size(0)) {
+		ACC_T value = fabs((ACC_T)a[i * w + k]);
+
+		if (value > best) {
+			best = value;
+			idx = i;
+		}
+	}
+
+	values[lid] = best;
+	indices[lid] = idx;
+	barrier(CLK_LOCAL_MEM_FENCE);
+
+	for (SIZE_T width = get_local_size(0); width > 1;) {
+		SIZE_T step = (width + 1) / 2;
+
+		if (lid < width / 2) {
+			SIZE_T other = lid + step;
+
+			if (values[other] > values[lid] ||
+			    (values[other] == values[lid] &&
+			     indices[other] < indices[lid])) {
+				values[lid] = values[other];
+				indices[lid] = indices[other];
+			}
+		}
+		barrier(CLK_LOCAL_MEM_FENCE);
+
+		width = step;
+	}
+
+	if (lid == 0) {
+		piv[k] = indices[0];
+	}
+}
+
+// Swaps the whole rows k and piv[k], including the part of L which is already
+// done.
+__kernel void lu_swap(SIZE_T k, __global const SIZE_T *piv, __global TYPE_T *a,
+		      SIZE_T w)
+{
+	SIZE_T p = piv[k];
+
+	if (p == k) {
+		return;
+	}
+
+	for (SIZE_T j = get_global_id(0); j < w; j += get_global_size(0)) {
+		TYPE_T tmp = a[k * w + j];
+		a[k * w + j] = a[p * w + j];
+		a[p * w + j] = tmp;
+	}
+}
+
+// Divides column k below the diagonal by the pivot, which gives column k of L.
+// A zero pivot means that the whole column is zero already, so it is skipped.
+__kernel void lu_scale(SIZE_T k, __global TYPE_T *a, SIZE_T w)
+{
+	TYPE_T pivot = a[k * w + k];
+
+	if (pivot == (TYPE_T)0) {
+		return;
+	}
+
+	for (SIZE_T i = k + 1 + get_global_id(0); i < w;
+	     i += get_global_size(0)) {
+		a[i * w + k] /= pivot;
+	}
+}
+
+// Subtracts the outer product of column k of L and row k of U from the
+// remaining lower right block. Runs on a two-dimensional grid over that block.
+// (See tiled_op in device.rs)
+__kernel void lu_update(SIZE_T n, SIZE_T k, __global TYPE_T *a)
+{
+	SIZE_T col = k + 1 + get_global_id(0);
+	SIZE_T row = k + 1 + get_global_id(1);
+
+	if (row < n && col < n) {
+		a[row * n + col] -= a[row * n + k] * a[k * n + col];
+	}
+}
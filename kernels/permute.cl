@@ -0,0 +1,48 @@
+#include "helpers.h"
+
+// Transposes a batch of rows x cols matrices, which are embedded into a larger
+// tensor. (See permute in device.rs)
+//
+// Element (r, c) of batch b is read from
+//   input[batch_in[b] + r * in_stride + c]
+// and written to
+//   output[batch_out[b] + c * out_stride + r].
+//
+// The work-groups load a whole tile into local memory along the input rows and
+// write it back along the output rows, so both the reads and the writes are
+// coalesced. The tile has a padding column (stride tile + 1) to avoid bank
+// conflicts when it is read column by column.
+__kernel void permute(SIZE_T rows, SIZE_T cols, SIZE_T in_stride,
+		      SIZE_T out_stride, __global const SIZE_T *batch_in,
+		      __global const SIZE_T *batch_out,
+		      __global const TYPE_T *input, __global TYPE_T *output,
+		      __local TYPE_T *tile)
+{
+	SIZE_T size = get_local_size(0);
+	SIZE_T stride = size + 1;
+
+	SIZE_T lcol = get_local_id(0);
+	SIZE_T lrow = get_local_id(1);
+	SIZE_T col0 = get_group_id(0) * size;
+	SIZE_T row0 = get_group_id(1) * size;
+	SIZE_T b = get_global_id(2);
+
+	SIZE_T row = row0 + lrow;
+	SIZE_T col = col0 + lcol;
+
+	if (row < rows && col < cols) {
+		tile[lrow * stride + lcol] =
+			input[batch_in[b] + row * in_stride + col];
+	}
+	barrier(CLK_LOCAL_MEM_FENCE);
+
+	// Swap the roles of the local ids, so neighbouring work-items write
+	// neighbouring elements of the output.
+	row = row0 + lcol;
+	col = col0 + lrow;
+
+	if (row < rows && col < cols) {
+		output[batch_out[b] + col * out_stride + row] =
+			tile[lcol * stride + lrow];
+	}
+}
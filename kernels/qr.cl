@@ -0,0 +1,108 @@
+#include "helpers.h"
+
+// Steps of the Householder QR decomposition of the m x n row-major matrix a.
+// For every column k, qr_house computes the reflector H_k = I - tau * v * v^T
+// and qr_apply applies it to the remaining columns. Afterwards a holds R on and
+// above the diagonal and v below it, without its leading one. (See try_qr in
+// linalg.rs)
+
+// Computes the reflector of column k, which zeroes it below the diagonal, and
+// stores tau in tau[k]. Runs as a single work-group.
+__kernel void qr_house(SIZE_T m, SIZE_T n, SIZE_T k, __global TYPE_T *a,
+		       __global TYPE_T *tau, __local ACC_T *scratch)
+{
+	SIZE_T lid = get_local_id(0);
+	ACC_T alpha = (ACC_T)a[k * n + k];
+	ACC_T acc = (ACC_T)0;
+
+	for (SIZE_T i = k + 1 + lid; i < m; i += get_local_size(0)) {
+		ACC_T value = (ACC_T)a[i * n + k];
+
+		acc += value * value;
+	}
+
+	scratch[lid] = acc;
+	barrier(CLK_LOCAL_MEM_FENCE);
+
+	for (SIZE_T width = get_local_size(0); width > 1;) {
+		SIZE_T step = (width + 1) / 2;
+
+		if (lid < width / 2) {
+			scratch[lid] += scratch[lid + step];
+		}
+		barrier(CLK_LOCAL_MEM_FENCE);
+
+		width = step;
+	}
+
+	ACC_T sigma = scratch[0];
+
+	// The column is zero below the diagonal already, so H_k = I.
+	if (sigma == (ACC_T)0) {
+		if (lid == 0) {
+			tau[k] = (TYPE_T)0;
+		}
+		return;
+	}
+
+	// The sign of beta avoids cancellation in alpha - beta.
+	ACC_T norm = sqrt(alpha * alpha + sigma);
+	ACC_T beta = alpha >= (ACC_T)0 ? -norm : norm;
+	ACC_T scale = (ACC_T)1 / (alpha - beta);
+
+	for (SIZE_T i = k + 1 + lid; i < m; i += get_local_size(0)) {
+		a[i * n + k] = (TYPE_T)((ACC_T)a[i * n + k] * scale);
+	}
+
+	if (lid == 0) {
+		a[k * n + k] = (TYPE_T)beta;
+		tau[k] = (TYPE_T)((beta - alpha) / beta);
+	}
+}
+
+// Applies H_k, which is stored in column k of a, to the columns of the m x w
+// matrix c, starting at column start. Every work-group updates one column.
+// (See group_op in device.rs)
+__kernel void qr_apply(SIZE_T m, SIZE_T n, SIZE_T k, __global const TYPE_T *a,
+		       __global const TYPE_T *tau, __global TYPE_T *c, SIZE_T w,
+		       SIZE_T start, __local ACC_T *scratch)
+{
+	SIZE_T col = start + get_group_id(0);
+	SIZE_T lid = get_local_id(0);
+	ACC_T t = (ACC_T)tau[k];
+
+	if (t == (ACC_T)0) {
+		return;
+	}
+
+	// v^T * c
+	ACC_T acc = (ACC_T)0;
+
+	for (SIZE_T i = k + lid; i < m; i += get_local_size(0)) {
+		ACC_T v = i == k ? (ACC_T)1 : (ACC_T)a[i * n + k];
+
+		acc += v * (ACC_T)c[i * w + col];
+	}
+
+	scratch[lid] = acc;
+	barrier(CLK_LOCAL_MEM_FENCE);
+
+	for (SIZE_T width = get_local_size(0); width > 1;) {
+		SIZE_T step = (width + 1) / 2;
+
+		if (lid < width / 2) {
+			scratch[lid] += scratch[lid + step];
+		}
+		barrier(CLK_LOCAL_MEM_FENCE);
+
+		width = step;
+	}
+
+	ACC_T factor = t * scratch[0];
+
+	for (SIZE_T i = k + lid; i < m; i += get_local_size(0)) {
+		ACC_T v = i == k ? (ACC_T)1 : (ACC_T)a[i * n + k];
+
+		c[i * w + col] = (TYPE_T)((ACC_T)c[i * w + col] - v * factor);
+	}
+}
@@ -0,0 +1,80 @@
+// Has to be included once for every KERNEL_NAME which needs a reduction.
+#include "helpers.h"
+
+#ifndef REDUCE
+#warning "Missing reduction!"
+#define REDUCE(a, b) ((a) + (b))
+#endif
+
+#ifndef IDENTITY
+#warning "Missing identity!"
+#define IDENTITY ((TYPE_T)0)
+#endif
+
+// Reduces acc of every work-item over the work-group and returns the result to
+// all of them.
+//
+// Tree reduction in local memory, which also works for local sizes that are not
+// a power of two.
+inline ACC_T CAT(KERNEL_NAME, _group)(ACC_T acc, __local ACC_T *scratch)
+{
+	SIZE_T lid = get_local_id(0);
+
+	scratch[lid] = acc;
+	barrier(CLK_LOCAL_MEM_FENCE);
+
+	for (SIZE_T width = get_local_size(0); width > 1;) {
+		SIZE_T step = (width + 1) / 2;
+
+		if (lid < width / 2) {
+			scratch[lid] = REDUCE(scratch[lid], scratch[lid + step]);
+		}
+		barrier(CLK_LOCAL_MEM_FENCE);
+
+		width = step;
+	}
+
+	return scratch[0];
+}
+
+// First stage of a reduction. Every work-group reduces its part of rhs into
+// output[get_group_id(0)], so the partial results of all work-groups have to be
+// reduced again by CAT(KERNEL_NAME, _down_acc) with a single work-group.
+//
+// Everything is accumulated as ACC_T, including the partial results, so that
+// they can't overflow T.
+__kernel void CAT(KERNEL_NAME, _down)(__global const TYPE_T *rhs, SIZE_T w_rhs,
+				      __global ACC_T *output,
+				      __local ACC_T *scratch)
+{
+	ACC_T acc = IDENTITY;
+
+	for (SIZE_T i = get_global_id(0); i < w_rhs; i += get_global_size(0)) {
+		acc = REDUCE(acc, (ACC_T)rhs[i]);
+	}
+
+	acc = CAT(KERNEL_NAME, _group)(acc, scratch);
+
+	if (get_local_id(0) == 0) {
+		output[get_group_id(0)] = acc;
+	}
+}
+
+// Second stage of a reduction, which reduces the partial results of the first
+// one. The result stays ACC_T as well and is only narrowed on the host.
+__kernel void CAT(KERNEL_NAME, _down_acc)(__global const ACC_T *rhs,
+					  SIZE_T w_rhs, __global ACC_T *output,
+					  __local ACC_T *scratch)
+{
+	ACC_T acc = IDENTITY;
+
+	for (SIZE_T i = get_global_id(0); i < w_rhs; i += get_global_size(0)) {
+		acc = REDUCE(acc, rhs[i]);
+	}
+
+	acc = CAT(KERNEL_NAME, _group)(acc, scratch);
+
+	if (get_local_id(0) == 0) {
+		output[get_group_id(0)] = acc;
+	}
+}
@@ -1,35 +1,57 @@
 
 #include "helpers.h"
 
-__kernel void KERNEL_NAME(__constant TYPE_T *rhs, SIZE_T w_rhs,
-			  __constant TYPE_T *lhs, SIZE_T w_lhs,
+// Spread over the whole NDRange. The global size is rounded up to a multiple of the
+// local size, so every work-item has to check its index.
+__kernel void KERNEL_NAME(__global const TYPE_T *rhs, SIZE_T w_rhs,
+			  __global const TYPE_T *lhs, SIZE_T w_lhs,
 			  __global TYPE_T *output)
 {
-	for (SIZE_T i = get_local_id(0); i < min(w_rhs, w_lhs);
-	     i += get_local_size(0)) {
+	for (SIZE_T i = get_global_id(0); i < min(w_rhs, w_lhs);
+	     i += get_global_size(0)) {
 		output[i] = lhs[i] OPERATOR rhs[i];
 	}
 }
 
-__kernel void CAT(KERNEL_NAME, _down)(__global TYPE_T *rhs, SIZE_T w_rhs)
+// Same as KERNEL_NAME, but writes the result back into lhs.
+__kernel void CAT(KERNEL_NAME, _assign)(__global const TYPE_T *rhs, SIZE_T w_rhs,
+					__global TYPE_T *lhs, SIZE_T w_lhs)
 {
-	for (SIZE_T k = 0; k < (SIZE_T)native_sqrt(w_rhs) + 1; k++) {
-		SIZE_T correction = 2 << k;
+	for (SIZE_T i = get_global_id(0); i < min(w_rhs, w_lhs);
+	     i += get_global_size(0)) {
+		lhs[i] = lhs[i] OPERATOR rhs[i];
+	}
+}
 
-		for (SIZE_T ix = correction * get_local_id(0); ix < w_rhs - 1;
-		     ix += correction * get_local_size(0)) {
-			SIZE_T io = 0;
+// Same as KERNEL_NAME, but with a scalar rhs. (Saves uploading a broadcast vector)
+__kernel void CAT(KERNEL_NAME, _scalar)(TYPE_T rhs, __global const TYPE_T *lhs,
+					SIZE_T w_lhs, __global TYPE_T *output)
+{
+	for (SIZE_T i = get_global_id(0); i < w_lhs; i += get_global_size(0)) {
+		output[i] = lhs[i] OPERATOR rhs;
+	}
+}
 
-			if (correction == 2) {
-				io = ix + 1;
-			} else {
-				io = ix + (1 << k);
-			}
+// Same as CAT(KERNEL_NAME, _scalar), but with swapped operands.
+__kernel void CAT(KERNEL_NAME, _rscalar)(TYPE_T rhs, __global const TYPE_T *lhs,
+					 SIZE_T w_lhs, __global TYPE_T *output)
+{
+	for (SIZE_T i = get_global_id(0); i < w_lhs; i += get_global_size(0)) {
+		output[i] = rhs OPERATOR lhs[i];
+	}
+}
 
-			if (io < w_rhs) {
-				rhs[ix] = rhs[ix] OPERATOR rhs[io];
-			}
-		}
-		print_array(rhs, w_rhs);
+// Same as CAT(KERNEL_NAME, _scalar), but writes the result back into lhs.
+__kernel void CAT(KERNEL_NAME, _assign_scalar)(TYPE_T rhs, __global TYPE_T *lhs,
+					       SIZE_T w_lhs)
+{
+	for (SIZE_T i = get_global_id(0); i < w_lhs; i += get_global_size(0)) {
+		lhs[i] = lhs[i] OPERATOR rhs;
 	}
 }
+
+#ifdef IDENTITY
+#undef REDUCE
+#define REDUCE(a, b) ((a) OPERATOR (b))
+#include "reduce.h"
+#endif
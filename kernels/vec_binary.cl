@@ -0,0 +1,13 @@
+#include "helpers.h"
+
+// Applies the function OPERATOR to every pair of elements of lhs and rhs.
+// (Same arguments as KERNEL_NAME in vec_arithmetic.cl)
+__kernel void KERNEL_NAME(__global const TYPE_T *rhs, SIZE_T w_rhs,
+			  __global const TYPE_T *lhs, SIZE_T w_lhs,
+			  __global TYPE_T *output)
+{
+	for (SIZE_T i = get_global_id(0); i < min(w_rhs, w_lhs);
+	     i += get_global_size(0)) {
+		output[i] = OPERATOR(lhs[i], rhs[i]);
+	}
+}
@@ -0,0 +1,16 @@
+#include "helpers.h"
+
+// Reductions over the absolute values of all elements. The partial results of
+// the first stage are positive already, so the second stage can use the same
+// kernel.
+#ifndef add_abs
+#define add_abs(a, b) ((a) + fabs(b))
+#endif
+
+#ifndef max_abs
+#define max_abs(a, b) fmax((a), fabs(b))
+#endif
+
+#undef REDUCE
+#define REDUCE(a, b) OPERATOR(a, b)
+#include "reduce.h"
@@ -0,0 +1,66 @@
+#include "helpers.h"
+
+// Reductions with a function (e.g. fmin) as OPERATOR.
+#undef REDUCE
+#define REDUCE(a, b) OPERATOR(a, b)
+#include "reduce.h"
+
+// Replaces value and index with other and other_index, if OPERATOR picks other.
+// Ties go to the lower index and NaNs are never picked.
+#undef ARG_PICK
+#define ARG_PICK(value, index, other, other_index)                 \
+	do {                                                        \
+		TYPE_T picked = OPERATOR(value, other);             \
+		if (picked != value ||                              \
+		    (picked == other && (other_index) < index)) {   \
+			value = other;                              \
+			index = other_index;                        \
+		}                                                   \
+	} while (0)
+
+// Same as CAT(KERNEL_NAME, _down), but also reduces the index of the picked
+// element. The first stage uses the position in rhs as index, the second one the
+// indices in idx_rhs which were written by the first stage.
+__kernel void CAT(KERNEL_NAME, _arg_down)(__global const TYPE_T *rhs,
+					  __global const SIZE_T *idx_rhs,
+					  SIZE_T w_rhs, SIZE_T first,
+					  __global TYPE_T *output,
+					  __global SIZE_T *idx_output,
+					  __local TYPE_T *scratch,
+					  __local SIZE_T *idx_scratch)
+{
+	SIZE_T lid = get_local_id(0);
+	TYPE_T acc = IDENTITY;
+	SIZE_T idx = (SIZE_T)-1;
+
+	for (SIZE_T i = get_global_id(0); i < w_rhs; i += get_global_size(0)) {
+		ARG_PICK(acc, idx, rhs[i], first ? i : idx_rhs[i]);
+	}
+
+	scratch[lid] = acc;
+	idx_scratch[lid] = idx;
+	barrier(CLK_LOCAL_MEM_FENCE);
+
+	for (SIZE_T width = get_local_size(0); width > 1;) {
+		SIZE_T step = (width + 1) / 2;
+
+		if (lid < width / 2) {
+			acc = scratch[lid];
+			idx = idx_scratch[lid];
+
+			ARG_PICK(acc, idx, scratch[lid + step],
+				 idx_scratch[lid + step]);
+
+			scratch[lid] = acc;
+			idx_scratch[lid] = idx;
+		}
+		barrier(CLK_LOCAL_MEM_FENCE);
+
+		width = step;
+	}
+
+	if (lid == 0) {
+		output[get_group_id(0)] = scratch[0];
+		idx_output[get_group_id(0)] = idx_scratch[0];
+	}
+}
@@ -0,0 +1,89 @@
+#include "helpers.h"
+
+// Merges the count, mean and m2 (sum of squared differences to the mean) of part
+// b into part a. (Chan et al.)
+#undef WELFORD_MERGE
+#define WELFORD_MERGE(n_a, mean_a, m2_a, n_b, mean_b, m2_b)            \
+	do {                                                            \
+		if ((n_b) != 0) {                                       \
+			ACC_T n_ab = (ACC_T)(n_a) + (ACC_T)(n_b);       \
+			ACC_T delta = (mean_b) - (mean_a);              \
+			mean_a += delta * ((ACC_T)(n_b) / n_ab);        \
+			m2_a += (m2_b) + delta * delta *                \
+					 ((ACC_T)(n_a) * (ACC_T)(n_b) / \
+					  n_ab);                        \
+			n_a += (n_b);                                   \
+		}                                                       \
+	} while (0)
+
+// One stage of a variance reduction with Welford's algorithm. Every work-group
+// writes the count, mean and variance (m2 / count) of its part of rhs to
+// position get_group_id(0) of the outputs. The variance is stored instead of m2,
+// because it doesn't grow with the count. The partial results are ACC_T, so
+// neither the variance can overflow T nor the rounded means distort the merge.
+//
+// The first stage reads single elements from rhs, the second one the partial
+// results of the first stage from mean_rhs, n_rhs and var_rhs.
+__kernel void welford_down(__global const TYPE_T *rhs,
+			   __global const ACC_T *mean_rhs,
+			   __global const SIZE_T *n_rhs,
+			   __global const ACC_T *var_rhs, SIZE_T w_rhs,
+			   SIZE_T first, __global ACC_T *output,
+			   __global SIZE_T *n_output,
+			   __global ACC_T *var_output, __local ACC_T *scratch,
+			   __local SIZE_T *n_scratch, __local ACC_T *m2_scratch)
+{
+	SIZE_T lid = get_local_id(0);
+
+	SIZE_T n = 0;
+	ACC_T mean = (ACC_T)0;
+	ACC_T m2 = (ACC_T)0;
+
+	for (SIZE_T i = get_global_id(0); i < w_rhs; i += get_global_size(0)) {
+		if (first) {
+			ACC_T value = (ACC_T)rhs[i];
+			ACC_T delta = value - mean;
+
+			n++;
+			mean += delta / (ACC_T)n;
+			m2 += delta * (value - mean);
+		} else {
+			WELFORD_MERGE(n, mean, m2, n_rhs[i], mean_rhs[i],
+				      var_rhs[i] * (ACC_T)n_rhs[i]);
+		}
+	}
+
+	scratch[lid] = mean;
+	n_scratch[lid] = n;
+	m2_scratch[lid] = m2;
+	barrier(CLK_LOCAL_MEM_FENCE);
+
+	for (SIZE_T width = get_local_size(0); width > 1;) {
+		SIZE_T step = (width + 1) / 2;
+
+		if (lid < width / 2) {
+			n = n_scratch[lid];
+			mean = scratch[lid];
+			m2 = m2_scratch[lid];
+
+			WELFORD_MERGE(n, mean, m2, n_scratch[lid + step],
+				      scratch[lid + step], m2_scratch[lid + step]);
+
+			scratch[lid] = mean;
+			n_scratch[lid] = n;
+			m2_scratch[lid] = m2;
+		}
+		barrier(CLK_LOCAL_MEM_FENCE);
+
+		width = step;
+	}
+
+	if (lid == 0) {
+		n = n_scratch[0];
+
+		output[get_group_id(0)] = scratch[0];
+		n_output[get_group_id(0)] = n;
+		var_output[get_group_id(0)] =
+			n ? m2_scratch[0] / (ACC_T)n : (ACC_T)0;
+	}
+}
@@ -0,0 +1,15 @@
+#include "helpers.h"
+
+// Functions which are used as operators, but are not builtins.
+#ifndef recip
+#define recip(x) ((TYPE_T)1 / (x))
+#endif
+
+// Applies the function OPERATOR to every element of lhs.
+__kernel void KERNEL_NAME(__global const TYPE_T *lhs, SIZE_T w_lhs,
+			  __global TYPE_T *output)
+{
+	for (SIZE_T i = get_global_id(0); i < w_lhs; i += get_global_size(0)) {
+		output[i] = OPERATOR(lhs[i]);
+	}
+}
@@ -0,0 +1,538 @@
+use std::sync::Arc;
+
+use crate::device::{self, KernelArg};
+use crate::host::{self, HostPrm};
+use crate::loader::{Backend, KernelLoader};
+use crate::vec2d::Vec2D;
+use crate::{Matrix, MatrixError};
+
+pub mod test;
+
+// BLAS level 1 routines.
+impl<T> Matrix<Vec<T>>
+where
+    T: HostPrm,
+{
+    // Checks the operands of a BLAS routine and returns the loader.
+    fn blas_check(&self, rhs_len: usize) -> Result<Arc<KernelLoader>, MatrixError> {
+        if self.A.len() != rhs_len {
+            return Err(MatrixError::SizeMismatch {
+                lhs: self.A.len(),
+                rhs: rhs_len,
+            });
+        }
+
+        if self.A.is_empty() {
+            return Err(MatrixError::Empty);
+        }
+
+        self.loader.clone().ok_or(MatrixError::NoLoader)
+    }
+
+    /// Fallible version of `dot`.
+    pub fn try_dot(&self, y: &Matrix<Vec<T>>) -> Result<Matrix<T>, MatrixError> {
+        let loader = self.blas_check(y.A.len())?;
+
+        let result = match &loader.backend {
+            Backend::OpenCl { queue, program, .. } => {
+                let buffer_x = device::upload(queue, &self.A)?;
+                let buffer_y = device::upload(queue, &y.A)?;
+
+                device::fused_reduce(
+                    &loader,
+                    queue,
+                    program,
+                    "dot_down",
+                    &[KernelArg::Buffer(&buffer_x), KernelArg::Buffer(&buffer_y)],
+                    self.A.len(),
+                    "add_down_acc",
+                )?
+            }
+            Backend::Host => host::dot(&self.A, &y.A),
+        };
+
+        Ok(Matrix {
+            loader: self.loader.clone(),
+            A: T::from_f64(result),
+        })
+    }
+
+    /// Dot product of self and y.
+    pub fn dot(&self, y: &Matrix<Vec<T>>) -> Matrix<T> {
+        self.try_dot(y).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `axpy`.
+    pub fn try_axpy(&mut self, a: T, x: &Matrix<Vec<T>>) -> Result<(), MatrixError> {
+        let loader = self.blas_check(x.A.len())?;
+
+        let (queue, program) = match &loader.backend {
+            Backend::OpenCl { queue, program, .. } => (queue, program),
+            Backend::Host => {
+                host::axpy(a, &x.A, &mut self.A);
+                return Ok(());
+            }
+        };
+
+        let buffer_x = device::upload(queue, &x.A)?;
+        let buffer_y = device::upload(queue, &self.A)?;
+
+        device::fused_op(
+            &loader,
+            queue,
+            program,
+            "axpy",
+            &[
+                KernelArg::Scalar(a),
+                KernelArg::Buffer(&buffer_x),
+                KernelArg::Buffer(&buffer_y),
+            ],
+            self.A.len(),
+        )?;
+
+        device::download_into(&buffer_y, &mut self.A)
+    }
+
+    /// self = a * x + self
+    pub fn axpy(&mut self, a: T, x: &Matrix<Vec<T>>) {
+        self.try_axpy(a, x).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `scal`.
+    pub fn try_scal(&mut self, a: T) -> Result<(), MatrixError> {
+        self.assign_scalar_op(a, "mul_assign_scalar")
+    }
+
+    /// self = a * self
+    pub fn scal(&mut self, a: T) {
+        self.try_scal(a).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `copy`.
+    pub fn try_copy(&mut self, x: &Matrix<Vec<T>>) -> Result<(), MatrixError> {
+        self.blas_check(x.A.len())?;
+
+        // Both are in host memory already, so there is nothing to do on the device.
+        self.A.copy_from_slice(&x.A);
+        Ok(())
+    }
+
+    /// self = x
+    pub fn copy(&mut self, x: &Matrix<Vec<T>>) {
+        self.try_copy(x).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `swap`.
+    pub fn try_swap(&mut self, y: &mut Matrix<Vec<T>>) -> Result<(), MatrixError> {
+        self.blas_check(y.A.len())?;
+
+        // Both are in host memory already, so swapping the vectors is enough.
+        std::mem::swap(&mut self.A, &mut y.A);
+        Ok(())
+    }
+
+    /// Exchanges the elements of self and y.
+    pub fn swap(&mut self, y: &mut Matrix<Vec<T>>) {
+        self.try_swap(y).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `asum`.
+    pub fn try_asum(&self) -> Result<Matrix<T>, MatrixError> {
+        self.try_norm_l1()
+    }
+
+    /// Sum of the absolute values of all elements. (Same as `norm_l1`)
+    pub fn asum(&self) -> Matrix<T> {
+        self.try_asum().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `nrm2`.
+    pub fn try_nrm2(&self) -> Result<Matrix<T>, MatrixError> {
+        self.try_norm_l2()
+    }
+
+    /// Euclidean norm of all elements. (Same as `norm_l2`)
+    pub fn nrm2(&self) -> Matrix<T> {
+        self.try_nrm2().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `iamax`.
+    pub fn try_iamax(&self) -> Result<usize, MatrixError> {
+        self.arg_down_op("iamax_down")
+    }
+
+    /// Index of the largest absolute value. (The first one, if there are several)
+    ///
+    /// NaNs are ignored. If all elements are NaN, the index is 0.
+    pub fn iamax(&self) -> usize {
+        self.try_iamax().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `rot`.
+    pub fn try_rot(&mut self, y: &mut Matrix<Vec<T>>, c: T, s: T) -> Result<(), MatrixError> {
+        let loader = self.blas_check(y.A.len())?;
+
+        let (queue, program) = match &loader.backend {
+            Backend::OpenCl { queue, program, .. } => (queue, program),
+            Backend::Host => {
+                host::rot(c, s, &mut self.A, &mut y.A);
+                return Ok(());
+            }
+        };
+
+        let buffer_x = device::upload(queue, &self.A)?;
+        let buffer_y = device::upload(queue, &y.A)?;
+
+        device::fused_op(
+            &loader,
+            queue,
+            program,
+            "rot",
+            &[
+                KernelArg::Scalar(c),
+                KernelArg::Scalar(s),
+                KernelArg::Buffer(&buffer_x),
+                KernelArg::Buffer(&buffer_y),
+            ],
+            self.A.len(),
+        )?;
+
+        device::download_into(&buffer_x, &mut self.A)?;
+        device::download_into(&buffer_y, &mut y.A)
+    }
+
+    /// Rotates every point (self, y) by the angle with cosine c and sine s.
+    ///
+    /// self = c * self + s * y, y = c * y - s * self
+    pub fn rot(&mut self, y: &mut Matrix<Vec<T>>, c: T, s: T) {
+        self.try_rot(y, c, s).unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+// BLAS level 2 routines, with the vector as output.
+impl<T> Matrix<Vec<T>>
+where
+    T: HostPrm,
+{
+    /// Fallible version of `gemv`.
+    pub fn try_gemv(
+        &mut self,
+        alpha: T,
+        a: &Matrix<Vec2D<T>>,
+        trans: bool,
+        x: &Matrix<Vec<T>>,
+        beta: T,
+    ) -> Result<(), MatrixError> {
+        let (m, n) = a.A.shape();
+        let (rows, cols) = if trans { (n, m) } else { (m, n) };
+
+        // Check for common invocation errors.
+        if cols != x.A.len() {
+            return Err(MatrixError::SizeMismatch {
+                lhs: cols,
+                rhs: x.A.len(),
+            });
+        }
+
+        let loader = self.blas_check(rows)?;
+
+        if a.A.is_empty() {
+            return Err(MatrixError::Empty);
+        }
+
+        let (queue, program) = match &loader.backend {
+            Backend::OpenCl { queue, program, .. } => (queue, program),
+            Backend::Host => {
+                host::gemv(
+                    (m, n),
+                    alpha,
+                    (a.A.as_slice(), trans),
+                    &x.A,
+                    beta,
+                    &mut self.A,
+                );
+                return Ok(());
+            }
+        };
+
+        let buffer_a = device::upload(queue, a.A.as_slice())?;
+        let buffer_x = device::upload(queue, &x.A)?;
+
+        // The kernels never read y with beta = 0, so it doesn't have to be uploaded.
+        let buffer_y = match beta.to_f64() == 0.0 {
+            true => device::new_buffer(queue, rows)?,
+            false => device::upload(queue, &self.A)?,
+        };
+
+        if trans {
+            device::fused_op(
+                &loader,
+                queue,
+                program,
+                "gemv_t",
+                &[
+                    KernelArg::Size(m),
+                    KernelArg::Scalar(alpha),
+                    KernelArg::Buffer(&buffer_a),
+                    KernelArg::Buffer(&buffer_x),
+                    KernelArg::Scalar(beta),
+                    KernelArg::Buffer(&buffer_y),
+                ],
+                n,
+            )?;
+        } else {
+            device::group_op(
+                &loader,
+                queue,
+                program,
+                "gemv",
+                &[
+                    KernelArg::Size(n),
+                    KernelArg::Scalar(alpha),
+                    KernelArg::Buffer(&buffer_a),
+                    KernelArg::Buffer(&buffer_x),
+                    KernelArg::Scalar(beta),
+                    KernelArg::Buffer(&buffer_y),
+                ],
+                m,
+                &[loader.kernel_type.get_type().acc_size()],
+            )?;
+        }
+
+        device::download_into(&buffer_y, &mut self.A)
+    }
+
+    /// General matrix-vector multiplication.
+    ///
+    /// self = alpha * op(a) * x + beta * self, where op transposes a if trans is set.
+    /// With beta = 0, self is only written.
+    pub fn gemv(
+        &mut self,
+        alpha: T,
+        a: &Matrix<Vec2D<T>>,
+        trans: bool,
+        x: &Matrix<Vec<T>>,
+        beta: T,
+    ) {
+        self.try_gemv(alpha, a, trans, x, beta)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+// BLAS level 2 routines, with the matrix as output.
+impl<T> Matrix<Vec2D<T>>
+where
+    T: HostPrm,
+{
+    /// Fallible version of `ger`.
+    pub fn try_ger(
+        &mut self,
+        alpha: T,
+        x: &Matrix<Vec<T>>,
+        y: &Matrix<Vec<T>>,
+    ) -> Result<(), MatrixError> {
+        let (m, n) = self.A.shape();
+
+        // Check for common invocation errors.
+        if (x.A.len(), y.A.len()) != (m, n) {
+            return Err(MatrixError::ShapeMismatch {
+                lhs: (m, n),
+                rhs: (x.A.len(), y.A.len()),
+            });
+        }
+
+        if self.A.is_empty() {
+            return Err(MatrixError::Empty);
+        }
+
+        let loader = self.loader.clone().ok_or(MatrixError::NoLoader)?;
+
+        let (queue, program) = match &loader.backend {
+            Backend::OpenCl { queue, program, .. } => (queue, program),
+            Backend::Host => {
+                host::ger(alpha, &x.A, &y.A, self.A.as_mut_slice());
+                return Ok(());
+            }
+        };
+
+        let buffer_x = device::upload(queue, &x.A)?;
+        let buffer_y = device::upload(queue, &y.A)?;
+        let buffer_a = device::upload(queue, self.A.as_slice())?;
+
+        device::tiled_op(
+            &loader,
+            queue,
+            program,
+            "ger",
+            &[
+                KernelArg::Size(m),
+                KernelArg::Size(n),
+                KernelArg::Scalar(alpha),
+                KernelArg::Buffer(&buffer_x),
+                KernelArg::Buffer(&buffer_y),
+                KernelArg::Buffer(&buffer_a),
+            ],
+            (1, m, n),
+            0,
+        )?;
+
+        device::download_into(&buffer_a, self.A.as_mut_slice())
+    }
+
+    /// Rank-1 update. (self = alpha * x * y^T + self)
+    pub fn ger(&mut self, alpha: T, x: &Matrix<Vec<T>>, y: &Matrix<Vec<T>>) {
+        self.try_ger(alpha, x, y)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `matvec`.
+    pub fn try_matvec(&self, x: &Matrix<Vec<T>>) -> Result<Matrix<Vec<T>>, MatrixError> {
+        let mut out = Matrix {
+            loader: self.loader.clone(),
+            A: vec![T::from_f64(0.0); self.A.rows()],
+        };
+
+        out.try_gemv(T::from_f64(1.0), self, false, x, T::from_f64(0.0))?;
+        Ok(out)
+    }
+
+    /// Matrix-vector product of self and x.
+    pub fn matvec(&self, x: &Matrix<Vec<T>>) -> Matrix<Vec<T>> {
+        self.try_matvec(x).unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+// BLAS level 3 routines.
+impl<T> Matrix<Vec2D<T>>
+where
+    T: HostPrm,
+{
+    /// Fallible version of `gemm`.
+    pub fn try_gemm(
+        &mut self,
+        alpha: T,
+        a: &Matrix<Vec2D<T>>,
+        trans_a: bool,
+        b: &Matrix<Vec2D<T>>,
+        trans_b: bool,
+        beta: T,
+    ) -> Result<(), MatrixError> {
+        let op = |a: &Vec2D<T>, trans: bool| match trans {
+            true => (a.cols(), a.rows()),
+            false => a.shape(),
+        };
+        let ((m, k), (kb, n)) = (op(&a.A, trans_a), op(&b.A, trans_b));
+
+        // Check for common invocation errors.
+        if k != kb {
+            return Err(MatrixError::ShapeMismatch {
+                lhs: (m, k),
+                rhs: (kb, n),
+            });
+        }
+
+        if self.A.shape() != (m, n) {
+            return Err(MatrixError::ShapeMismatch {
+                lhs: self.A.shape(),
+                rhs: (m, n),
+            });
+        }
+
+        if m == 0 || n == 0 {
+            return Err(MatrixError::Empty);
+        }
+
+        let loader = self.loader.clone().ok_or(MatrixError::NoLoader)?;
+
+        // With k = 0 self is only scaled by beta, and a and b have nothing to upload.
+        let (queue, program) = match &loader.backend {
+            Backend::OpenCl { queue, program, .. } if k > 0 => (queue, program),
+            _ => {
+                host::gemm(
+                    (m, n, k),
+                    alpha,
+                    (a.A.as_slice(), trans_a),
+                    (b.A.as_slice(), trans_b),
+                    beta,
+                    self.A.as_mut_slice(),
+                );
+                return Ok(());
+            }
+        };
+
+        let buffer_a = device::upload(queue, a.A.as_slice())?;
+        let buffer_b = device::upload(queue, b.A.as_slice())?;
+
+        // The kernel never reads c with beta = 0, so it doesn't have to be uploaded.
+        let buffer_c = match beta.to_f64() == 0.0 {
+            true => device::new_buffer(queue, m * n)?,
+            false => device::upload(queue, self.A.as_slice())?,
+        };
+
+        device::tiled_op(
+            &loader,
+            queue,
+            program,
+            "gemm",
+            &[
+                KernelArg::Size(m),
+                KernelArg::Size(n),
+                KernelArg::Size(k),
+                KernelArg::Scalar(alpha),
+                KernelArg::Buffer(&buffer_a),
+                KernelArg::Size(trans_a as usize),
+                KernelArg::Buffer(&buffer_b),
+                KernelArg::Size(trans_b as usize),
+                KernelArg::Scalar(beta),
+                KernelArg::Buffer(&buffer_c),
+            ],
+            (1, m, n),
+            2,
+        )?;
+
+        device::download_into(&buffer_c, self.A.as_mut_slice())
+    }
+
+    /// General matrix multiplication.
+    ///
+    /// self = alpha * op(a) * op(b) + beta * self, where op transposes its matrix if
+    /// trans_a or trans_b is set. With beta = 0, self is only written.
+    pub fn gemm(
+        &mut self,
+        alpha: T,
+        a: &Matrix<Vec2D<T>>,
+        trans_a: bool,
+        b: &Matrix<Vec2D<T>>,
+        trans_b: bool,
+        beta: T,
+    ) {
+        self.try_gemm(alpha, a, trans_a, b, trans_b, beta)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `matmul`.
+    pub fn try_matmul(&self, rhs: &Matrix<Vec2D<T>>) -> Result<Matrix<Vec2D<T>>, MatrixError> {
+        let mut out = Matrix {
+            loader: self.loader.clone(),
+            A: Vec2D::zeros(self.A.rows(), rhs.A.cols()),
+        };
+
+        out.try_gemm(T::from_f64(1.0), self, false, rhs, false, T::from_f64(0.0))?;
+        Ok(out)
+    }
+
+    /// Matrix product of self and rhs. (`*` multiplies elementwise)
+    pub fn matmul(&self, rhs: &Matrix<Vec2D<T>>) -> Matrix<Vec2D<T>> {
+        self.try_matmul(rhs).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `dot`.
+    pub fn try_dot(&self, rhs: &Matrix<Vec2D<T>>) -> Result<Matrix<Vec2D<T>>, MatrixError> {
+        self.try_matmul(rhs)
+    }
+
+    /// Matrix product of self and rhs. (Same as `matmul`)
+    pub fn dot(&self, rhs: &Matrix<Vec2D<T>>) -> Matrix<Vec2D<T>> {
+        self.try_dot(rhs).unwrap_or_else(|e| panic!("{}", e))
+    }
+}
@@ -0,0 +1,389 @@
+#[cfg(test)]
+mod blas_tests {
+    use log::info;
+    use std::time::Instant;
+
+    use crate::host::HostPrm;
+    use crate::linalg::triangular_solve;
+    use crate::vec2d::Vec2D;
+    use crate::vector::test::matrix_tests::{check, loader, setup, timer_end, values};
+    use crate::{Matrix, MatrixError};
+
+    fn blas1<T: HostPrm>(host: bool, tolerance: f64) {
+        setup();
+        let start = Instant::now();
+
+        let loader = loader::<T>(host);
+
+        let mut rng = oorandom::Rand32::new(10);
+        let mut random = |len: usize| Matrix {
+            loader: Some(loader.clone()),
+            A: (0..len)
+                .map(|_| T::from_f64(rng.rand_float() as f64 * 4.0 - 2.0))
+                .collect::<Vec<T>>(),
+        };
+
+        for len in [1, 3, 1000, 10007, 100_003] {
+            let (x, y) = (random(len), random(len));
+            let (xv, yv) = (values(&x.A), values(&y.A));
+
+            check(
+                "dot",
+                &[x.dot(&y).A.to_f64()],
+                &[xv.iter().zip(&yv).map(|(x, y)| x * y).sum()],
+                tolerance,
+            );
+            // Only the result of asum can overflow, which is fine.
+            let asum: f64 = xv.iter().map(|a| a.abs()).sum();
+            if T::from_f64(asum).to_f64().is_finite() {
+                check("asum", &[x.asum().A.to_f64()], &[asum], tolerance);
+            } else {
+                assert!(x.asum().A.to_f64().is_infinite());
+            }
+            check(
+                "nrm2",
+                &[x.nrm2().A.to_f64()],
+                &[xv.iter().map(|a| a * a).sum::<f64>().sqrt()],
+                tolerance,
+            );
+
+            let max = xv.iter().fold(0.0, |a: f64, b| a.max(b.abs()));
+            assert_eq!(x.iamax(), xv.iter().position(|a| a.abs() == max).unwrap());
+
+            // axpy with a = 0.5, which is exact.
+            let mut z = y.clone();
+            z.axpy(T::from_f64(0.5), &x);
+            let expected = (0..len).map(|i| 0.5 * xv[i] + yv[i]).collect::<Vec<f64>>();
+            check("axpy", &values(&z.A), &expected, tolerance);
+
+            z.scal(T::from_f64(2.0));
+            let expected = (0..len).map(|i| xv[i] + 2.0 * yv[i]).collect::<Vec<f64>>();
+            check("scal", &values(&z.A), &expected, tolerance);
+
+            // A rotation by 90 degrees.
+            let (mut rx, mut ry) = (x.clone(), y.clone());
+            rx.rot(&mut ry, T::from_f64(0.0), T::from_f64(1.0));
+            assert_eq!(rx.A, y.A);
+            let expected = xv.iter().map(|a| -a).collect::<Vec<f64>>();
+            check("rot", &values(&ry.A), &expected, tolerance);
+
+            rx.copy(&x);
+            assert_eq!(rx.A, x.A);
+
+            rx.swap(&mut z);
+            assert_eq!(z.A, x.A);
+        }
+
+        // NaNs are ignored, like in argmax.
+        let nan = T::from_f64(f64::NAN);
+        let vector = |a: Vec<T>| Matrix {
+            loader: Some(loader.clone()),
+            A: a,
+        };
+        assert_eq!(
+            vector(vec![nan, T::from_f64(-3.0), T::from_f64(1.0)]).iamax(),
+            1
+        );
+        assert_eq!(vector(vec![nan; 3]).iamax(), 0);
+
+        let (x, y) = (random(3), random(4));
+        assert!(matches!(
+            x.try_dot(&y),
+            Err(MatrixError::SizeMismatch { lhs: 3, rhs: 4 })
+        ));
+
+        timer_end(start);
+    }
+
+    #[test]
+    fn blas1_f16() {
+        blas1::<half::f16>(false, 1e-2);
+    }
+
+    #[test]
+    fn blas1_f32() {
+        blas1::<f32>(false, 1e-4);
+    }
+
+    #[test]
+    fn blas1_f64() {
+        blas1::<f64>(false, 1e-10);
+    }
+
+    #[test]
+    fn blas1_host_f32() {
+        blas1::<f32>(true, 1e-4);
+    }
+
+    fn blas2<T: HostPrm>(host: bool, tolerance: f64) {
+        setup();
+        let start = Instant::now();
+
+        let loader = loader::<T>(host);
+
+        let mut rng = oorandom::Rand32::new(11);
+        let mut random = |len: usize| {
+            (0..len)
+                .map(|_| T::from_f64(rng.rand_float() as f64 * 4.0 - 2.0))
+                .collect::<Vec<T>>()
+        };
+        let vector = |a: Vec<T>| Matrix {
+            loader: Some(loader.clone()),
+            A: a,
+        };
+
+        for (m, n) in [(1, 1), (3, 1000), (1000, 3), (129, 67)] {
+            let a = Matrix {
+                loader: Some(loader.clone()),
+                A: Vec2D::new(m, n, random(m * n)).unwrap(),
+            };
+            let av = values(a.A.as_slice());
+            let (x, xt) = (vector(random(n)), vector(random(m)));
+            let (xv, xtv) = (values(&x.A), values(&xt.A));
+
+            let expected = (0..m)
+                .map(|row| (0..n).map(|col| av[row * n + col] * xv[col]).sum())
+                .collect::<Vec<f64>>();
+            check("matvec", &values(&a.matvec(&x).A), &expected, tolerance);
+
+            // alpha = 0.5 and beta = 2 with a transposed.
+            let mut y = vector(random(n));
+            let expected = (0..n)
+                .map(|col| {
+                    let acc: f64 = (0..m).map(|row| av[row * n + col] * xtv[row]).sum();
+                    0.5 * acc + 2.0 * y.A[col].to_f64()
+                })
+                .collect::<Vec<f64>>();
+            y.gemv(T::from_f64(0.5), &a, true, &xt, T::from_f64(2.0));
+            check("gemv_t", &values(&y.A), &expected, tolerance);
+
+            let mut b = a.clone();
+            let expected = (0..m * n)
+                .map(|i| av[i] + 0.5 * xtv[i / n] * xv[i % n])
+                .collect::<Vec<f64>>();
+            b.ger(T::from_f64(0.5), &xt, &x);
+            check("ger", &values(b.A.as_slice()), &expected, tolerance);
+        }
+
+        // Small elements off the diagonal keep the triangular systems well
+        // conditioned, even with a unit diagonal.
+        let n = 50;
+        let small = random(n * n)
+            .iter()
+            .map(|a| T::from_f64(a.to_f64() / n as f64))
+            .collect();
+        let mut a = Matrix {
+            loader: Some(loader.clone()),
+            A: Vec2D::new(n, n, small).unwrap(),
+        };
+        for i in 0..n {
+            a.A[(i, i)] = T::from_f64(2.0);
+        }
+        let av = values(a.A.as_slice());
+
+        for upper in [false, true] {
+            for trans in [false, true] {
+                for unit_diag in [false, true] {
+                    let b = vector(random(n));
+                    let x = triangular_solve(&a, &[(upper, trans, unit_diag)], b.A.clone());
+                    let xv = values(&x.unwrap());
+
+                    // op(a) * x has to give b again.
+                    let product = (0..n)
+                        .map(|row| {
+                            (0..n)
+                                .map(|col| {
+                                    let (i, j) = if trans { (col, row) } else { (row, col) };
+                                    let value = match (i == j, upper == (i < j)) {
+                                        (true, _) if unit_diag => 1.0,
+                                        (true, _) | (false, true) => av[i * n + j],
+                                        _ => 0.0,
+                                    };
+                                    value * xv[col]
+                                })
+                                .sum()
+                        })
+                        .collect::<Vec<f64>>();
+                    check("trsv", &values(&b.A), &product, tolerance);
+                }
+            }
+        }
+
+        let a = Matrix {
+            loader: Some(loader.clone()),
+            A: Vec2D::new(3, 4, random(12)).unwrap(),
+        };
+        assert!(matches!(
+            a.try_matvec(&vector(random(3))),
+            Err(MatrixError::SizeMismatch { lhs: 4, rhs: 3 })
+        ));
+
+        let mut b = a.clone();
+        assert!(matches!(
+            b.try_ger(T::from_f64(1.0), &vector(random(4)), &vector(random(3))),
+            Err(MatrixError::ShapeMismatch {
+                lhs: (3, 4),
+                rhs: (4, 3)
+            })
+        ));
+
+        timer_end(start);
+    }
+
+    #[test]
+    fn blas2_f16() {
+        blas2::<half::f16>(false, 1e-2);
+    }
+
+    #[test]
+    fn blas2_f32() {
+        blas2::<f32>(false, 1e-4);
+    }
+
+    #[test]
+    fn blas2_f64() {
+        blas2::<f64>(false, 1e-10);
+    }
+
+    #[test]
+    fn blas2_host_f32() {
+        blas2::<f32>(true, 1e-4);
+    }
+
+    // Naive product of op(a) (m x k) and op(b) (k x n).
+    fn naive_gemm(
+        (m, n, k): (usize, usize, usize),
+        (a, trans_a): (&[f64], bool),
+        (b, trans_b): (&[f64], bool),
+    ) -> Vec<f64> {
+        let mut c = vec![0.0; m * n];
+
+        for row in 0..m {
+            for col in 0..n {
+                for p in 0..k {
+                    let value_a = if trans_a {
+                        a[p * m + row]
+                    } else {
+                        a[row * k + p]
+                    };
+                    let value_b = if trans_b {
+                        b[col * k + p]
+                    } else {
+                        b[p * n + col]
+                    };
+                    c[row * n + col] += value_a * value_b;
+                }
+            }
+        }
+        c
+    }
+
+    fn blas3<T: HostPrm>(host: bool, tolerance: f64) {
+        setup();
+        let start = Instant::now();
+
+        let loader = loader::<T>(host);
+        info!("Tile size: {}", loader.tile_size());
+
+        let mut rng = oorandom::Rand32::new(12);
+        let mut random = |rows: usize, cols: usize| Matrix {
+            loader: Some(loader.clone()),
+            A: Vec2D::new(
+                rows,
+                cols,
+                (0..rows * cols)
+                    .map(|_| T::from_f64(rng.rand_float() as f64 * 4.0 - 2.0))
+                    .collect(),
+            )
+            .unwrap(),
+        };
+
+        // Sizes which are multiples of the tile size and sizes which aren't.
+        for (m, n, k) in [(1, 1, 1), (17, 33, 9), (64, 64, 64), (100, 37, 129)] {
+            let (a, b) = (random(m, k), random(k, n));
+            let (av, bv) = (values(a.A.as_slice()), values(b.A.as_slice()));
+
+            let product = naive_gemm((m, n, k), (&av, false), (&bv, false));
+            check(
+                "matmul",
+                &values(a.matmul(&b).A.as_slice()),
+                &product,
+                tolerance,
+            );
+            check("dot", &values(a.dot(&b).A.as_slice()), &product, tolerance);
+
+            // alpha = 0.5 and beta = 2, with transposed operands.
+            for (trans_a, trans_b) in [(true, false), (false, true), (true, true)] {
+                let a = if trans_a { random(k, m) } else { a.clone() };
+                let b = if trans_b { random(n, k) } else { b.clone() };
+                let (av, bv) = (values(a.A.as_slice()), values(b.A.as_slice()));
+
+                let mut c = random(m, n);
+                let expected = naive_gemm((m, n, k), (&av, trans_a), (&bv, trans_b))
+                    .iter()
+                    .zip(values(c.A.as_slice()))
+                    .map(|(ab, c)| 0.5 * ab + 2.0 * c)
+                    .collect::<Vec<f64>>();
+
+                c.gemm(T::from_f64(0.5), &a, trans_a, &b, trans_b, T::from_f64(2.0));
+                check("gemm", &values(c.A.as_slice()), &expected, tolerance);
+            }
+        }
+
+        // Without inner dimension, only c is scaled.
+        let mut c = random(3, 4);
+        let expected = values(c.A.as_slice())
+            .iter()
+            .map(|c| 2.0 * c)
+            .collect::<Vec<f64>>();
+        c.gemm(
+            T::from_f64(1.0),
+            &random(3, 0),
+            false,
+            &random(0, 4),
+            false,
+            T::from_f64(2.0),
+        );
+        check("gemm k=0", &values(c.A.as_slice()), &expected, tolerance);
+
+        let (a, b) = (random(3, 4), random(3, 4));
+        assert!(matches!(
+            a.try_matmul(&b),
+            Err(MatrixError::ShapeMismatch {
+                lhs: (3, 4),
+                rhs: (3, 4)
+            })
+        ));
+
+        let mut c = random(3, 4);
+        assert!(matches!(
+            c.try_gemm(T::from_f64(1.0), &a, false, &b, true, T::from_f64(0.0)),
+            Err(MatrixError::ShapeMismatch {
+                lhs: (3, 4),
+                rhs: (3, 3)
+            })
+        ));
+
+        timer_end(start);
+    }
+
+    #[test]
+    fn blas3_f16() {
+        blas3::<half::f16>(false, 1e-2);
+    }
+
+    #[test]
+    fn blas3_f32() {
+        blas3::<f32>(false, 1e-4);
+    }
+
+    #[test]
+    fn blas3_f64() {
+        blas3::<f64>(false, 1e-10);
+    }
+
+    #[test]
+    fn blas3_host_f32() {
+        blas3::<f32>(true, 1e-4);
+    }
+}
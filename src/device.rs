@@ -0,0 +1,1210 @@
+use std::cell::{Cell, OnceCell};
+use std::fmt::Debug;
+use std::ops;
+
+use ocl::builders::KernelBuilder;
+use ocl::{Buffer, Kernel, OclPrm, Program, Queue};
+
+use crate::expr::{BinaryOp, Expr};
+use crate::host::{self, HostPrm};
+use crate::loader::{Backend, KernelLoader, TypeMap};
+use crate::{Matrix, MatrixError};
+
+pub mod test;
+
+/// Creates an uninitialized buffer.
+pub(crate) fn new_buffer<T: OclPrm>(queue: &Queue, len: usize) -> Result<Buffer<T>, MatrixError> {
+    Buffer::<T>::builder()
+        .len(len)
+        .queue(queue.clone())
+        .build()
+        .map_err(MatrixError::BufferError)
+}
+
+/// Creates a buffer and writes the slice to it.
+pub(crate) fn upload<T: OclPrm>(queue: &Queue, data: &[T]) -> Result<Buffer<T>, MatrixError> {
+    let buffer = new_buffer(queue, data.len())?;
+
+    buffer
+        .write(data)
+        .enq()
+        .map_err(MatrixError::TransferError)?;
+
+    Ok(buffer)
+}
+
+/// Reads the whole buffer back into host memory.
+pub(crate) fn download<T: OclPrm>(buffer: &Buffer<T>) -> Result<Vec<T>, MatrixError> {
+    let mut result = vec![T::default(); buffer.len()];
+    download_into(buffer, &mut result)?;
+
+    Ok(result)
+}
+
+/// Reads the whole buffer into an existing slice of the same length.
+pub(crate) fn download_into<T: OclPrm>(
+    buffer: &Buffer<T>,
+    data: &mut [T],
+) -> Result<(), MatrixError> {
+    buffer
+        .read(data)
+        .len(buffer.len())
+        .enq()
+        .map_err(MatrixError::TransferError)
+}
+
+/// Runs a `KERNEL_NAME` kernel of vec_arithmetic.cl or vec_binary.cl.
+/// (output = lhs @ rhs)
+pub(crate) fn basic_op<T: HostPrm>(
+    loader: &KernelLoader,
+    queue: &Queue,
+    program: &Program,
+    lhs: &Buffer<T>,
+    rhs: &Buffer<T>,
+    kernel_name: &str,
+) -> Result<Buffer<T>, MatrixError> {
+    let buffer_output = new_buffer(queue, lhs.len())?;
+
+    let kernel = Kernel::builder()
+        .program(program)
+        .name(kernel_name)
+        .queue(queue.clone())
+        .global_work_size(loader.global_work_size(lhs.len()))
+        .local_work_size(loader.local_work_size)
+        .arg(rhs)
+        .arg(rhs.len() as u64)
+        .arg(lhs)
+        .arg(lhs.len() as u64)
+        .arg(&buffer_output)
+        .build()
+        .map_err(MatrixError::KernelError)?;
+
+    unsafe {
+        kernel.enq().map_err(MatrixError::KernelError)?;
+    }
+
+    Ok(buffer_output)
+}
+
+/// Runs a `KERNEL_NAME` kernel of vec_unary.cl. (output = OPERATOR(lhs))
+pub(crate) fn unary_op<T: HostPrm>(
+    loader: &KernelLoader,
+    queue: &Queue,
+    program: &Program,
+    lhs: &Buffer<T>,
+    kernel_name: &str,
+) -> Result<Buffer<T>, MatrixError> {
+    let buffer_output = new_buffer(queue, lhs.len())?;
+
+    let kernel = Kernel::builder()
+        .program(program)
+        .name(kernel_name)
+        .queue(queue.clone())
+        .global_work_size(loader.global_work_size(lhs.len()))
+        .local_work_size(loader.local_work_size)
+        .arg(lhs)
+        .arg(lhs.len() as u64)
+        .arg(&buffer_output)
+        .build()
+        .map_err(MatrixError::KernelError)?;
+
+    unsafe {
+        kernel.enq().map_err(MatrixError::KernelError)?;
+    }
+
+    Ok(buffer_output)
+}
+
+/// Runs a `CAT(KERNEL_NAME, _assign)` kernel of vec_arithmetic.cl. (lhs @= rhs)
+pub(crate) fn assign_op<T: HostPrm>(
+    loader: &KernelLoader,
+    queue: &Queue,
+    program: &Program,
+    lhs: &Buffer<T>,
+    rhs: &Buffer<T>,
+    kernel_name: &str,
+) -> Result<(), MatrixError> {
+    let kernel = Kernel::builder()
+        .program(program)
+        .name(kernel_name)
+        .queue(queue.clone())
+        .global_work_size(loader.global_work_size(lhs.len()))
+        .local_work_size(loader.local_work_size)
+        .arg(rhs)
+        .arg(rhs.len() as u64)
+        .arg(lhs)
+        .arg(lhs.len() as u64)
+        .build()
+        .map_err(MatrixError::KernelError)?;
+
+    unsafe {
+        kernel.enq().map_err(MatrixError::KernelError)?;
+    }
+
+    Ok(())
+}
+
+/// Runs a `CAT(KERNEL_NAME, _scalar)` or `CAT(KERNEL_NAME, _rscalar)` kernel of
+/// vec_arithmetic.cl. (output = lhs @ rhs or output = rhs @ lhs)
+pub(crate) fn scalar_op<T: HostPrm>(
+    loader: &KernelLoader,
+    queue: &Queue,
+    program: &Program,
+    lhs: &Buffer<T>,
+    rhs: T,
+    kernel_name: &str,
+) -> Result<Buffer<T>, MatrixError> {
+    let buffer_output = new_buffer(queue, lhs.len())?;
+
+    let kernel = Kernel::builder()
+        .program(program)
+        .name(kernel_name)
+        .queue(queue.clone())
+        .global_work_size(loader.global_work_size(lhs.len()))
+        .local_work_size(loader.local_work_size)
+        .arg(rhs)
+        .arg(lhs)
+        .arg(lhs.len() as u64)
+        .arg(&buffer_output)
+        .build()
+        .map_err(MatrixError::KernelError)?;
+
+    unsafe {
+        kernel.enq().map_err(MatrixError::KernelError)?;
+    }
+
+    Ok(buffer_output)
+}
+
+/// Runs a `CAT(KERNEL_NAME, _assign_scalar)` kernel of vec_arithmetic.cl. (lhs @= rhs)
+pub(crate) fn assign_scalar_op<T: HostPrm>(
+    loader: &KernelLoader,
+    queue: &Queue,
+    program: &Program,
+    lhs: &Buffer<T>,
+    rhs: T,
+    kernel_name: &str,
+) -> Result<(), MatrixError> {
+    let kernel = Kernel::builder()
+        .program(program)
+        .name(kernel_name)
+        .queue(queue.clone())
+        .global_work_size(loader.global_work_size(lhs.len()))
+        .local_work_size(loader.local_work_size)
+        .arg(rhs)
+        .arg(lhs)
+        .arg(lhs.len() as u64)
+        .build()
+        .map_err(MatrixError::KernelError)?;
+
+    unsafe {
+        kernel.enq().map_err(MatrixError::KernelError)?;
+    }
+
+    Ok(())
+}
+
+/// A buffer of accumulators (ACC_T), which are f64 for f64 and f32 otherwise.
+pub(crate) enum AccBuffer {
+    F32(Buffer<f32>),
+    F64(Buffer<f64>),
+}
+
+impl AccBuffer {
+    /// Creates an uninitialized buffer with the accumulator type of the loader.
+    pub(crate) fn new(
+        loader: &KernelLoader,
+        queue: &Queue,
+        len: usize,
+    ) -> Result<AccBuffer, MatrixError> {
+        Ok(match loader.kernel_type.get_type() {
+            TypeMap::F64 => AccBuffer::F64(new_buffer(queue, len)?),
+            TypeMap::F16 | TypeMap::F32 => AccBuffer::F32(new_buffer(queue, len)?),
+        })
+    }
+
+    /// Reads the whole buffer back into host memory, widened to f64.
+    pub(crate) fn download(&self) -> Result<Vec<f64>, MatrixError> {
+        match self {
+            AccBuffer::F32(a) => Ok(download(a)?.into_iter().map(f64::from).collect()),
+            AccBuffer::F64(a) => download(a),
+        }
+    }
+}
+
+/// A kernel argument of the fused kernels, which is either a buffer, a scalar, a
+/// scalar or buffer of the accumulator type (ACC_T), a size (SIZE_T) or a buffer
+/// of sizes.
+pub(crate) enum KernelArg<'a, T: HostPrm> {
+    Buffer(&'a Buffer<T>),
+    Scalar(T),
+    AccScalar(f64),
+    AccBuffer(&'a AccBuffer),
+    Size(usize),
+    Sizes(&'a Buffer<u64>),
+}
+
+fn set_args<'b, T: HostPrm>(
+    loader: &KernelLoader,
+    builder: &mut KernelBuilder<'b>,
+    args: &[KernelArg<'b, T>],
+) {
+    for arg in args {
+        match arg {
+            KernelArg::Buffer(a) => builder.arg(*a),
+            KernelArg::Scalar(a) => builder.arg(*a),
+            KernelArg::AccScalar(a) => match loader.kernel_type.get_type() {
+                TypeMap::F64 => builder.arg(*a),
+                TypeMap::F16 | TypeMap::F32 => builder.arg(*a as f32),
+            },
+            KernelArg::AccBuffer(a) => match a {
+                AccBuffer::F32(b) => builder.arg(b),
+                AccBuffer::F64(b) => builder.arg(b),
+            },
+            KernelArg::Size(a) => builder.arg(*a as u64),
+            KernelArg::Sizes(a) => builder.arg(*a),
+        };
+    }
+}
+
+/// Size in bytes of a local array with one accumulator per work-item. The
+/// accumulators (ACC_T) can be larger than T.
+fn acc_scratch(loader: &KernelLoader) -> usize {
+    loader.local_work_size.to_len().max(1) * loader.kernel_type.get_type().acc_size()
+}
+
+/// Reduces a whole buffer with a `CAT(KERNEL_NAME, _down)` kernel.
+///
+/// The first pass leaves one partial result per work-group, which are then reduced
+/// by the `CAT(KERNEL_NAME, _down_acc)` kernel in a second pass with a single
+/// work-group. Returns the accumulator (ACC_T), which the caller narrows to T.
+pub(crate) fn reduce<T: HostPrm>(
+    loader: &KernelLoader,
+    queue: &Queue,
+    program: &Program,
+    input: &Buffer<T>,
+    kernel_name: &str,
+) -> Result<f64, MatrixError> {
+    fused_reduce(
+        loader,
+        queue,
+        program,
+        kernel_name,
+        &[KernelArg::Buffer(input)],
+        input.len(),
+        &format!("{}_acc", kernel_name),
+    )
+}
+
+/// Same as `reduce`, but the first pass runs `first_kernel` with the arguments
+/// (args..., len, output, scratch), which can combine several inputs before
+/// reducing them. Its partial results are ACC_T, which the second pass reduces
+/// with the `CAT(KERNEL_NAME, _down_acc)` kernel `second_kernel`.
+pub(crate) fn fused_reduce<T: HostPrm>(
+    loader: &KernelLoader,
+    queue: &Queue,
+    program: &Program,
+    first_kernel: &str,
+    args: &[KernelArg<T>],
+    len: usize,
+    second_kernel: &str,
+) -> Result<f64, MatrixError> {
+    let local = loader.local_work_size.to_len().max(1);
+
+    // Never more groups than the second pass can reduce in one work-group.
+    let groups = len.div_ceil(local).min(local);
+
+    let buffer_partial = AccBuffer::new(loader, queue, groups)?;
+    let buffer_output = AccBuffer::new(loader, queue, 1)?;
+
+    let second_args = [KernelArg::AccBuffer(&buffer_partial)];
+
+    let passes = [
+        (first_kernel, args, len, &buffer_partial, groups),
+        (second_kernel, &second_args[..], groups, &buffer_output, 1),
+    ];
+
+    for (pass_kernel, pass_args, pass_len, pass_output, pass_groups) in passes {
+        let mut builder = Kernel::builder();
+        builder
+            .program(program)
+            .name(pass_kernel)
+            .queue(queue.clone())
+            .global_work_size(pass_groups * local)
+            .local_work_size(local);
+
+        set_args(loader, &mut builder, pass_args);
+        set_args(
+            loader,
+            &mut builder,
+            &[
+                KernelArg::<T>::Size(pass_len),
+                KernelArg::AccBuffer(pass_output),
+            ],
+        );
+
+        let kernel = builder
+            .arg_local::<u8>(acc_scratch(loader))
+            .build()
+            .map_err(MatrixError::KernelError)?;
+
+        unsafe {
+            kernel.enq().map_err(MatrixError::KernelError)?;
+        }
+    }
+
+    // Read the output from device memory.
+    Ok(buffer_output.download()?[0])
+}
+
+/// Runs `kernel_name` once over all elements with the arguments (args..., len).
+///
+/// Used by kernels which work in place on their buffer arguments.
+pub(crate) fn fused_op<T: HostPrm>(
+    loader: &KernelLoader,
+    queue: &Queue,
+    program: &Program,
+    kernel_name: &str,
+    args: &[KernelArg<T>],
+    len: usize,
+) -> Result<(), MatrixError> {
+    let mut builder = Kernel::builder();
+    builder
+        .program(program)
+        .name(kernel_name)
+        .queue(queue.clone())
+        .global_work_size(loader.global_work_size(len))
+        .local_work_size(loader.local_work_size);
+
+    set_args(loader, &mut builder, args);
+
+    let kernel = builder
+        .arg(len as u64)
+        .build()
+        .map_err(MatrixError::KernelError)?;
+
+    unsafe {
+        kernel.enq().map_err(MatrixError::KernelError)?;
+    }
+
+    Ok(())
+}
+
+/// Runs `kernel_name` with one work-group per group and the arguments
+/// (args..., local arrays). `scratch` holds the size in bytes of one element of
+/// every local array, which has one element per work-item.
+pub(crate) fn group_op<T: HostPrm>(
+    loader: &KernelLoader,
+    queue: &Queue,
+    program: &Program,
+    kernel_name: &str,
+    args: &[KernelArg<T>],
+    groups: usize,
+    scratch: &[usize],
+) -> Result<(), MatrixError> {
+    let local = loader.local_work_size.to_len().max(1);
+
+    let mut builder = Kernel::builder();
+    builder
+        .program(program)
+        .name(kernel_name)
+        .queue(queue.clone())
+        .global_work_size(groups * local)
+        .local_work_size(local);
+
+    set_args(loader, &mut builder, args);
+
+    for size in scratch {
+        builder.arg_local::<u8>(local * size);
+    }
+
+    let kernel = builder.build().map_err(MatrixError::KernelError)?;
+
+    unsafe {
+        kernel.enq().map_err(MatrixError::KernelError)?;
+    }
+
+    Ok(())
+}
+
+/// Runs trsv of blas2.cl, which solves op(a) * x = b in place of the buffer x.
+pub(crate) fn trsv<T: HostPrm>(
+    loader: &KernelLoader,
+    queue: &Queue,
+    program: &Program,
+    a: &Buffer<T>,
+    (upper, trans, unit_diag): (bool, bool, bool),
+    x: &Buffer<T>,
+) -> Result<(), MatrixError> {
+    group_op(
+        loader,
+        queue,
+        program,
+        "trsv",
+        &[
+            KernelArg::Size(x.len()),
+            KernelArg::Size(upper as usize),
+            KernelArg::Size(trans as usize),
+            KernelArg::Size(unit_diag as usize),
+            KernelArg::Buffer(a),
+            KernelArg::Buffer(x),
+        ],
+        1,
+        &[],
+    )
+}
+
+/// Runs `kernel_name` on a rows x cols grid of square tiles with the arguments
+/// (args..., tiles x local tile), once for every batch.
+///
+/// Dimension 0 of the work-items is the column, dimension 1 the row and dimension 2
+/// the batch. Every local tile has room for tile x (tile + 1) elements, where the
+/// padding column avoids bank conflicts of column accesses.
+/// (See KernelLoader::tile_size)
+pub(crate) fn tiled_op<T: HostPrm>(
+    loader: &KernelLoader,
+    queue: &Queue,
+    program: &Program,
+    kernel_name: &str,
+    args: &[KernelArg<T>],
+    (batches, rows, cols): (usize, usize, usize),
+    tiles: usize,
+) -> Result<(), MatrixError> {
+    let tile = loader.tile_size();
+
+    let mut builder = Kernel::builder();
+    builder
+        .program(program)
+        .name(kernel_name)
+        .queue(queue.clone())
+        .global_work_size((
+            cols.max(1).div_ceil(tile) * tile,
+            rows.max(1).div_ceil(tile) * tile,
+            batches.max(1),
+        ))
+        .local_work_size((tile, tile, 1));
+
+    set_args(loader, &mut builder, args);
+
+    for _ in 0..tiles {
+        builder.arg_local::<T>(tile * (tile + 1));
+    }
+
+    let kernel = builder.build().map_err(MatrixError::KernelError)?;
+
+    unsafe {
+        kernel.enq().map_err(MatrixError::KernelError)?;
+    }
+
+    Ok(())
+}
+
+/// Checks that a tensor of `shape` has len elements and that axes is a
+/// permutation of its axes.
+pub(crate) fn permute_check(
+    len: usize,
+    shape: &[usize],
+    axes: &[usize],
+) -> Result<(), MatrixError> {
+    if shape.iter().product::<usize>() != len {
+        return Err(MatrixError::SizeMismatch {
+            lhs: shape.iter().product(),
+            rhs: len,
+        });
+    }
+
+    let mut sorted = axes.to_vec();
+    sorted.sort_unstable();
+
+    if shape.is_empty() || !sorted.iter().copied().eq(0..shape.len()) {
+        return Err(MatrixError::InvalidAxes {
+            axes: axes.to_vec(),
+            dims: shape.len(),
+        });
+    }
+
+    if len == 0 {
+        return Err(MatrixError::Empty);
+    }
+
+    Ok(())
+}
+
+/// Moves the axes of a row-major tensor of `shape`, so that axis i of the output
+/// is axis axes[i] of the input. (Check with permute_check first)
+///
+/// The permutation runs as a batch of transposes between the innermost axis of the
+/// input and the axis which becomes the innermost axis of the output. All other
+/// axes only move whole tiles, so their offsets are computed here.
+pub(crate) fn permute<T: HostPrm>(
+    loader: &KernelLoader,
+    queue: &Queue,
+    program: &Program,
+    input: &Buffer<T>,
+    shape: &[usize],
+    axes: &[usize],
+) -> Result<Buffer<T>, MatrixError> {
+    let dims = shape.len();
+    let in_strides = host::strides(shape);
+
+    // Stride in the output of every input axis.
+    let out_shape = axes.iter().map(|a| shape[*a]).collect::<Vec<usize>>();
+    let mut out_strides = vec![0; dims];
+    for (axis, stride) in axes.iter().zip(host::strides(&out_shape)) {
+        out_strides[*axis] = stride;
+    }
+
+    let inner = dims - 1;
+    let outer = axes[dims - 1];
+
+    // If the innermost axis stays, every batch is a single row which is copied.
+    let (rows, in_stride, out_stride) = match inner == outer {
+        true => (1, 0, 1),
+        false => (shape[outer], in_strides[outer], out_strides[inner]),
+    };
+
+    let mut batches = vec![(0u64, 0u64)];
+    for axis in (0..dims).filter(|a| *a != inner && *a != outer) {
+        let (stride_in, stride_out) = (in_strides[axis] as u64, out_strides[axis] as u64);
+
+        batches = batches
+            .iter()
+            .flat_map(|(b_in, b_out)| {
+                (0..shape[axis] as u64).map(move |i| (b_in + i * stride_in, b_out + i * stride_out))
+            })
+            .collect();
+    }
+
+    let (batch_in, batch_out): (Vec<u64>, Vec<u64>) = batches.into_iter().unzip();
+    let buffer_batch_in = upload(queue, &batch_in)?;
+    let buffer_batch_out = upload(queue, &batch_out)?;
+
+    let buffer_output = new_buffer(queue, input.len())?;
+
+    tiled_op(
+        loader,
+        queue,
+        program,
+        "permute",
+        &[
+            KernelArg::Size(rows),
+            KernelArg::Size(shape[inner]),
+            KernelArg::Size(in_stride),
+            KernelArg::Size(out_stride),
+            KernelArg::Sizes(&buffer_batch_in),
+            KernelArg::Sizes(&buffer_batch_out),
+            KernelArg::Buffer(input),
+            KernelArg::Buffer(&buffer_output),
+        ],
+        (batch_in.len(), rows, shape[inner]),
+        1,
+    )?;
+
+    Ok(buffer_output)
+}
+
+/// Same as `reduce`, but runs a `CAT(KERNEL_NAME, _arg_down)` kernel and returns
+/// the index of the picked element.
+///
+/// Returns None if there is no element to pick. (Only NaNs)
+pub(crate) fn reduce_arg<T: HostPrm>(
+    loader: &KernelLoader,
+    queue: &Queue,
+    program: &Program,
+    input: &Buffer<T>,
+    kernel_name: &str,
+) -> Result<Option<usize>, MatrixError> {
+    let local = loader.local_work_size.to_len().max(1);
+    let groups = input.len().div_ceil(local).min(local);
+
+    let buffer_partial = new_buffer(queue, groups)?;
+    let buffer_output = new_buffer::<T>(queue, 1)?;
+
+    let idx_partial = Buffer::<u64>::builder()
+        .len(groups)
+        .queue(queue.clone())
+        .build()
+        .map_err(MatrixError::BufferError)?;
+    let idx_output = Buffer::<u64>::builder()
+        .len(1)
+        .queue(queue.clone())
+        .build()
+        .map_err(MatrixError::BufferError)?;
+
+    // The first pass doesn't read its input indices, so any buffer will do.
+    let passes = [
+        (
+            input,
+            &idx_partial,
+            input.len(),
+            1u64,
+            &buffer_partial,
+            &idx_partial,
+            groups,
+        ),
+        (
+            &buffer_partial,
+            &idx_partial,
+            groups,
+            0u64,
+            &buffer_output,
+            &idx_output,
+            1,
+        ),
+    ];
+
+    for (pass_input, pass_idx, pass_len, first, pass_output, pass_idx_output, pass_groups) in passes
+    {
+        let kernel = Kernel::builder()
+            .program(program)
+            .name(kernel_name)
+            .queue(queue.clone())
+            .global_work_size(pass_groups * local)
+            .local_work_size(local)
+            .arg(pass_input)
+            .arg(pass_idx)
+            .arg(pass_len as u64)
+            .arg(first)
+            .arg(pass_output)
+            .arg(pass_idx_output)
+            .arg_local::<T>(local)
+            .arg_local::<u64>(local)
+            .build()
+            .map_err(MatrixError::KernelError)?;
+
+        unsafe {
+            kernel.enq().map_err(MatrixError::KernelError)?;
+        }
+    }
+
+    let mut result = vec![0u64; 1];
+
+    idx_output
+        .read(&mut result)
+        .len(1)
+        .enq()
+        .map_err(MatrixError::TransferError)?;
+
+    // The index stays at its initial value (SIZE_T)-1, if nothing was picked.
+    Ok(usize::try_from(result[0]).ok().filter(|a| *a < input.len()))
+}
+
+/// Index of the largest absolute value. (See iamax_down in blas1.cl)
+///
+/// Returns None if there is no element to pick. (Only NaNs)
+pub(crate) fn iamax<T: HostPrm>(
+    loader: &KernelLoader,
+    queue: &Queue,
+    program: &Program,
+    input: &Buffer<T>,
+) -> Result<Option<usize>, MatrixError> {
+    let local = loader.local_work_size.to_len().max(1);
+    let items = input.len().div_ceil(local).min(local) * local;
+
+    let new_indices = |len: usize| {
+        Buffer::<u64>::builder()
+            .len(len)
+            .queue(queue.clone())
+            .build()
+            .map_err(MatrixError::BufferError)
+    };
+
+    // One partial result per work-item.
+    let buffer_partial = new_buffer::<T>(queue, items)?;
+    let idx_partial = new_indices(items)?;
+    let buffer_output = new_buffer::<T>(queue, 1)?;
+    let idx_output = new_indices(1)?;
+
+    let kernel = Kernel::builder()
+        .program(program)
+        .name("iamax_down")
+        .queue(queue.clone())
+        .global_work_size(items)
+        .local_work_size(local)
+        .arg(input)
+        .arg(input.len() as u64)
+        .arg(&buffer_partial)
+        .arg(&idx_partial)
+        .build()
+        .map_err(MatrixError::KernelError)?;
+
+    unsafe {
+        kernel.enq().map_err(MatrixError::KernelError)?;
+    }
+
+    // Second stage of max_arg_down, which reads the indices of the partial results.
+    let kernel = Kernel::builder()
+        .program(program)
+        .name("max_arg_down")
+        .queue(queue.clone())
+        .global_work_size(local)
+        .local_work_size(local)
+        .arg(&buffer_partial)
+        .arg(&idx_partial)
+        .arg(items as u64)
+        .arg(0u64)
+        .arg(&buffer_output)
+        .arg(&idx_output)
+        .arg_local::<T>(local)
+        .arg_local::<u64>(local)
+        .build()
+        .map_err(MatrixError::KernelError)?;
+
+    unsafe {
+        kernel.enq().map_err(MatrixError::KernelError)?;
+    }
+
+    let mut result = vec![0u64; 1];
+
+    idx_output
+        .read(&mut result)
+        .len(1)
+        .enq()
+        .map_err(MatrixError::TransferError)?;
+
+    Ok(usize::try_from(result[0]).ok().filter(|a| *a < input.len()))
+}
+
+/// Computes the mean and the population variance of a buffer with a two-stage
+/// reduction. (See welford_down in vec_stats.cl)
+///
+/// Both are returned as accumulators (ACC_T), which the caller narrows to T.
+pub(crate) fn welford<T: HostPrm>(
+    loader: &KernelLoader,
+    queue: &Queue,
+    program: &Program,
+    input: &Buffer<T>,
+) -> Result<(f64, f64), MatrixError> {
+    let local = loader.local_work_size.to_len().max(1);
+    let groups = input.len().div_ceil(local).min(local);
+
+    let mean_partial = AccBuffer::new(loader, queue, groups)?;
+    let n_partial = new_buffer::<u64>(queue, groups)?;
+    let var_partial = AccBuffer::new(loader, queue, groups)?;
+
+    let mean_output = AccBuffer::new(loader, queue, 1)?;
+    let n_output = new_buffer::<u64>(queue, 1)?;
+    let var_output = AccBuffer::new(loader, queue, 1)?;
+
+    // The first pass only reads single elements from input and the second pass
+    // only the partial results, so the unused buffers don't matter.
+    let passes = [
+        (&mean_partial, &n_partial, &var_partial, input.len(), 1),
+        (&mean_partial, &n_partial, &var_partial, groups, 0),
+    ];
+    let outputs = [
+        (&mean_partial, &n_partial, &var_partial, groups),
+        (&mean_output, &n_output, &var_output, 1),
+    ];
+
+    for ((pass_mean, pass_n, pass_var, pass_len, first), (mean, n, var, pass_groups)) in
+        passes.into_iter().zip(outputs)
+    {
+        let mut builder = Kernel::builder();
+        builder
+            .program(program)
+            .name("welford_down")
+            .queue(queue.clone())
+            .global_work_size(pass_groups * local)
+            .local_work_size(local);
+
+        set_args(
+            loader,
+            &mut builder,
+            &[
+                KernelArg::Buffer(input),
+                KernelArg::AccBuffer(pass_mean),
+                KernelArg::Sizes(pass_n),
+                KernelArg::AccBuffer(pass_var),
+                KernelArg::Size(pass_len),
+                KernelArg::Size(first),
+                KernelArg::AccBuffer(mean),
+                KernelArg::Sizes(n),
+                KernelArg::AccBuffer(var),
+            ],
+        );
+
+        let kernel = builder
+            .arg_local::<u8>(acc_scratch(loader))
+            .arg_local::<u64>(local)
+            .arg_local::<u8>(acc_scratch(loader))
+            .build()
+            .map_err(MatrixError::KernelError)?;
+
+        unsafe {
+            kernel.enq().map_err(MatrixError::KernelError)?;
+        }
+    }
+
+    Ok((mean_output.download()?[0], var_output.download()?[0]))
+}
+
+/// A vector which is kept in device memory.
+///
+/// The host copy is only downloaded when it is accessed, so chains of operations
+/// on `Matrix<DeviceVec<T>>` never leave the device. On the host backend there
+/// is only the host copy.
+pub struct DeviceVec<T: HostPrm> {
+    len: usize,
+    buffer: OnceCell<Buffer<T>>,
+    host: OnceCell<Vec<T>>,
+    // Set if the host copy was changed and the buffer is outdated.
+    host_dirty: Cell<bool>,
+}
+
+impl<T: HostPrm> DeviceVec<T> {
+    pub(crate) fn from_buffer(buffer: Buffer<T>) -> DeviceVec<T> {
+        DeviceVec {
+            len: buffer.len(),
+            buffer: OnceCell::from(buffer),
+            host: OnceCell::new(),
+            host_dirty: Cell::new(false),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Checks if the host copy is available without a download.
+    pub fn is_downloaded(&self) -> bool {
+        self.host.get().is_some()
+    }
+
+    /// Returns the device buffer and uploads the host copy first, if necessary.
+    pub(crate) fn buffer(&self, queue: &Queue) -> Result<&Buffer<T>, MatrixError> {
+        if let Some(buffer) = self.buffer.get() {
+            if self.host_dirty.get()
+                && let Some(host) = self.host.get()
+            {
+                buffer
+                    .write(host)
+                    .enq()
+                    .map_err(MatrixError::TransferError)?;
+                self.host_dirty.set(false);
+            }
+
+            return Ok(buffer);
+        }
+
+        let buffer = upload(queue, self.try_host()?)?;
+        Ok(self.buffer.get_or_init(|| buffer))
+    }
+
+    /// Returns the host copy and downloads it first, if necessary.
+    pub fn try_host(&self) -> Result<&[T], MatrixError> {
+        if let Some(host) = self.host.get() {
+            return Ok(host);
+        }
+
+        let buffer = self
+            .buffer
+            .get()
+            .expect("DeviceVec without buffer or host copy (bug)");
+
+        let host = download(buffer)?;
+        Ok(self.host.get_or_init(|| host))
+    }
+
+    /// Same as `try_host`, but panics if the download fails.
+    pub fn host(&self) -> &[T] {
+        self.try_host().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Mutable access to the host copy. The buffer is updated before it is used next.
+    pub fn host_mut(&mut self) -> Result<&mut [T], MatrixError> {
+        self.try_host()?;
+
+        if self.buffer.get().is_some() {
+            self.host_dirty.set(true);
+        }
+
+        Ok(self.host.get_mut().expect("host copy after download (bug)"))
+    }
+}
+
+impl<T: HostPrm> From<Vec<T>> for DeviceVec<T> {
+    /// Only creates the host copy. It is uploaded by the first operation on the device.
+    fn from(data: Vec<T>) -> DeviceVec<T> {
+        DeviceVec {
+            len: data.len(),
+            buffer: OnceCell::new(),
+            host: OnceCell::from(data),
+            host_dirty: Cell::new(false),
+        }
+    }
+}
+
+impl<T: HostPrm> Clone for DeviceVec<T> {
+    /// Copies the buffer on the device, without downloading it.
+    fn clone(&self) -> DeviceVec<T> {
+        let buffer = OnceCell::new();
+
+        if !self.host_dirty.get()
+            && let Some(src) = self.buffer.get()
+            && let Some(queue) = src.default_queue()
+        {
+            let copy = new_buffer(queue, self.len)
+                .and_then(|dst| {
+                    src.copy(&dst, None, None)
+                        .enq()
+                        .map_err(MatrixError::TransferError)?;
+                    Ok(dst)
+                })
+                .unwrap_or_else(|e| panic!("{}", e));
+
+            let _ = buffer.set(copy);
+        }
+
+        let host = match self.host.get() {
+            Some(a) => OnceCell::from(a.clone()),
+            // Only the buffer exists, so it has to be copied.
+            None if buffer.get().is_none() => OnceCell::from(self.host().to_vec()),
+            None => OnceCell::new(),
+        };
+
+        DeviceVec {
+            len: self.len,
+            buffer,
+            host,
+            host_dirty: Cell::new(false),
+        }
+    }
+}
+
+impl<T> Debug for Matrix<DeviceVec<T>>
+where
+    T: HostPrm,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.A.try_host().map_err(|_| std::fmt::Error)?)
+    }
+}
+
+impl<T> Matrix<Vec<T>>
+where
+    T: HostPrm,
+{
+    /// Copies the matrix to the device. (Does nothing on the host backend)
+    pub fn to_device(&self) -> Result<Matrix<DeviceVec<T>>, MatrixError> {
+        let loader = self.loader.clone().ok_or(MatrixError::NoLoader)?;
+
+        let data = match &loader.backend {
+            Backend::OpenCl { queue, .. } => DeviceVec::from_buffer(upload(queue, &self.A)?),
+            Backend::Host => DeviceVec::from(self.A.clone()),
+        };
+
+        Ok(Matrix {
+            loader: self.loader.clone(),
+            A: data,
+        })
+    }
+}
+
+impl<T> Matrix<DeviceVec<T>>
+where
+    T: HostPrm,
+{
+    /// Copies the matrix back into host memory.
+    pub fn to_host(&self) -> Result<Matrix<Vec<T>>, MatrixError> {
+        Ok(Matrix {
+            loader: self.loader.clone(),
+            A: self.A.try_host()?.to_vec(),
+        })
+    }
+
+    fn basic_op(
+        &self,
+        rhs: &DeviceVec<T>,
+        kernel_name: &str,
+    ) -> Result<Matrix<DeviceVec<T>>, MatrixError> {
+        // Check for common invocation errors.
+        if self.A.len() != rhs.len() {
+            return Err(MatrixError::SizeMismatch {
+                lhs: self.A.len(),
+                rhs: rhs.len(),
+            });
+        }
+
+        if self.A.is_empty() {
+            return Err(MatrixError::Empty);
+        }
+
+        let loader = self.loader.clone().ok_or(MatrixError::NoLoader)?;
+
+        let data = match &loader.backend {
+            Backend::OpenCl { queue, program, .. } => DeviceVec::from_buffer(basic_op(
+                &loader,
+                queue,
+                program,
+                self.A.buffer(queue)?,
+                rhs.buffer(queue)?,
+                kernel_name,
+            )?),
+            Backend::Host => DeviceVec::from(host::basic_op(
+                self.A.try_host()?,
+                rhs.try_host()?,
+                kernel_name,
+            )),
+        };
+
+        Ok(Matrix {
+            loader: self.loader.clone(),
+            A: data,
+        })
+    }
+
+    fn down_op(&self, kernel_name: &str) -> Result<Matrix<T>, MatrixError> {
+        if self.A.is_empty() {
+            return Err(MatrixError::Empty);
+        }
+
+        let loader = self.loader.clone().ok_or(MatrixError::NoLoader)?;
+
+        let result = match &loader.backend {
+            Backend::OpenCl { queue, program, .. } => {
+                reduce(&loader, queue, program, self.A.buffer(queue)?, kernel_name)?
+            }
+            Backend::Host => host::down_op(self.A.try_host()?, kernel_name),
+        };
+
+        Ok(Matrix {
+            loader: self.loader.clone(),
+            A: T::from_f64(result),
+        })
+    }
+
+    /// Fallible version of `&self + rhs`.
+    pub fn try_add(&self, rhs: &Matrix<DeviceVec<T>>) -> Result<Matrix<DeviceVec<T>>, MatrixError> {
+        self.basic_op(&rhs.A, "add")
+    }
+
+    /// Fallible version of `&self - rhs`.
+    pub fn try_sub(&self, rhs: &Matrix<DeviceVec<T>>) -> Result<Matrix<DeviceVec<T>>, MatrixError> {
+        self.basic_op(&rhs.A, "sub")
+    }
+
+    /// Fallible version of `&self * rhs`.
+    pub fn try_mul(&self, rhs: &Matrix<DeviceVec<T>>) -> Result<Matrix<DeviceVec<T>>, MatrixError> {
+        self.basic_op(&rhs.A, "mul")
+    }
+
+    /// Fallible version of `&self / rhs`.
+    pub fn try_div(&self, rhs: &Matrix<DeviceVec<T>>) -> Result<Matrix<DeviceVec<T>>, MatrixError> {
+        self.basic_op(&rhs.A, "div")
+    }
+
+    /// Sums up all elements.
+    pub fn try_sum(&self) -> Result<Matrix<T>, MatrixError> {
+        self.down_op("add_down")
+    }
+
+    /// Multiplies all elements.
+    pub fn try_product(&self) -> Result<Matrix<T>, MatrixError> {
+        self.down_op("mul_down")
+    }
+
+    /// Fallible version of `permute`.
+    pub fn try_permute(
+        &self,
+        shape: &[usize],
+        axes: &[usize],
+    ) -> Result<Matrix<DeviceVec<T>>, MatrixError> {
+        permute_check(self.A.len(), shape, axes)?;
+
+        let loader = self.loader.clone().ok_or(MatrixError::NoLoader)?;
+
+        let data = match &loader.backend {
+            Backend::OpenCl { queue, program, .. } => DeviceVec::from_buffer(permute(
+                &loader,
+                queue,
+                program,
+                self.A.buffer(queue)?,
+                shape,
+                axes,
+            )?),
+            Backend::Host => DeviceVec::from(host::permute(self.A.try_host()?, shape, axes)),
+        };
+
+        Ok(Matrix {
+            loader: self.loader.clone(),
+            A: data,
+        })
+    }
+
+    /// Reorders the axes of the row-major tensor of `shape`, so that axis i of the
+    /// result is axis axes[i] of self. The result stays on the device.
+    pub fn permute(&self, shape: &[usize], axes: &[usize]) -> Matrix<DeviceVec<T>> {
+        self.try_permute(shape, axes)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+// Implementation of Expr = Matrix<DeviceVec<T>> @ Matrix<DeviceVec<T>> (See Expr)
+macro_rules! device_oper_impl {
+    ($op: ident, $fn: ident, $variant: ident) => {
+        impl<'a, T> ops::$op<&'a Matrix<DeviceVec<T>>> for &'a Matrix<DeviceVec<T>>
+        where
+            T: HostPrm,
+        {
+            type Output = Expr<'a, T>;
+
+            fn $fn(self, rhs: &'a Matrix<DeviceVec<T>>) -> Self::Output {
+                Expr::binary(Expr::Device(self), BinaryOp::$variant, Expr::Device(rhs))
+            }
+        }
+    };
+}
+
+device_oper_impl!(Add, add, Add);
+device_oper_impl!(Sub, sub, Sub);
+device_oper_impl!(Mul, mul, Mul);
+device_oper_impl!(Div, div, Div);
+
+// Implementation of Expr = Matrix<DeviceVec<T>> @ T
+macro_rules! device_scalar_oper_impl {
+    ($op: ident, $fn: ident, $variant: ident) => {
+        impl<'a, T> ops::$op<T> for &'a Matrix<DeviceVec<T>>
+        where
+            T: HostPrm,
+        {
+            type Output = Expr<'a, T>;
+
+            fn $fn(self, rhs: T) -> Self::Output {
+                Expr::binary(Expr::Device(self), BinaryOp::$variant, Expr::Scalar(rhs))
+            }
+        }
+    };
+}
+
+device_scalar_oper_impl!(Add, add, Add);
+device_scalar_oper_impl!(Sub, sub, Sub);
+device_scalar_oper_impl!(Mul, mul, Mul);
+device_scalar_oper_impl!(Div, div, Div);
+
+// Implementation of Expr = T @ Matrix<DeviceVec<T>>
+macro_rules! device_rscalar_oper_impl {
+    ($t: ty) => {
+        device_rscalar_oper_impl!($t, Add, add, Add);
+        device_rscalar_oper_impl!($t, Sub, sub, Sub);
+        device_rscalar_oper_impl!($t, Mul, mul, Mul);
+        device_rscalar_oper_impl!($t, Div, div, Div);
+    };
+    ($t: ty, $op: ident, $fn: ident, $variant: ident) => {
+        impl<'a> ops::$op<&'a Matrix<DeviceVec<$t>>> for $t {
+            type Output = Expr<'a, $t>;
+
+            fn $fn(self, rhs: &'a Matrix<DeviceVec<$t>>) -> Self::Output {
+                Expr::binary(Expr::Scalar(self), BinaryOp::$variant, Expr::Device(rhs))
+            }
+        }
+    };
+}
+
+device_rscalar_oper_impl!(half::f16);
+device_rscalar_oper_impl!(f32);
+device_rscalar_oper_impl!(f64);
@@ -0,0 +1,77 @@
+#[cfg(test)]
+mod device_tests {
+    use log::info;
+    use std::time::Instant;
+
+    use crate::device::DeviceVec;
+    use crate::loader::Backend;
+    use crate::vector::test::matrix_tests::{loader, setup, timer_end};
+    use crate::Matrix;
+
+    fn device_chain(host: bool) {
+        setup();
+        let start = Instant::now();
+
+        let loader = loader::<f32>(host);
+        let on_device = matches!(loader.backend, Backend::OpenCl { .. });
+
+        let mut rng = oorandom::Rand32::new(10);
+        let mut random = |len: usize| Matrix {
+            loader: Some(loader.clone()),
+            A: (0..len)
+                .map(|_| (rng.rand_u32() % 10) as f32)
+                .collect::<Vec<f32>>(),
+        };
+
+        let (a, b, c) = (random(1000), random(1000), random(1000));
+
+        let da = a.to_device().unwrap();
+        let db = b.to_device().unwrap();
+        let dc = Matrix {
+            loader: Some(loader.clone()),
+            A: DeviceVec::from(c.A.clone()),
+        };
+
+        // Nothing is downloaded until the host copy is accessed.
+        let result = ((&da + &db) * &dc - 1.0).eval_device().unwrap();
+        assert_eq!(result.A.is_downloaded(), !on_device);
+
+        let expected = ((&a + &b) * &c - 1.0).eval().unwrap();
+        assert_eq!(result.A.host(), &expected.A[..]);
+        assert!(result.A.is_downloaded());
+
+        assert_eq!(result.try_sum().unwrap().A, expected.try_sum().unwrap().A);
+
+        // Changes to the host copy have to reach the device.
+        let mut changed = result.clone();
+        changed.A.host_mut().unwrap()[0] = 1000.0;
+
+        let doubled: Matrix<DeviceVec<f32>> = (&changed + &changed).into();
+        info!("{:?}", doubled.to_host().unwrap().A[..4].to_vec());
+        assert_eq!(doubled.A.host()[0], 2000.0);
+        assert_eq!(doubled.A.host()[1], expected.A[1] * 2.0);
+
+        // The clone is independent of the original.
+        assert_eq!(result.A.host()[0], expected.A[0]);
+
+        // The permuted matrix stays on the device as well.
+        let permuted = result.permute(&[10, 100], &[1, 0]);
+        assert_eq!(permuted.A.is_downloaded(), !on_device);
+        assert_eq!(permuted.A.host()[1], expected.A[100]);
+
+        let err = da.try_add(&random(3).to_device().unwrap()).unwrap_err();
+        info!("{}", err);
+
+        timer_end(start);
+    }
+
+    #[test]
+    fn device_chain_f32() {
+        device_chain(false);
+    }
+
+    #[test]
+    fn device_chain_host_f32() {
+        device_chain(true);
+    }
+}
@@ -0,0 +1,407 @@
+use std::ops;
+use std::sync::Arc;
+
+use ocl::{Buffer, Kernel, Queue};
+
+use crate::device::{self, DeviceVec};
+use crate::host::{self, HostPrm};
+use crate::loader::{Backend, KernelLoader};
+use crate::{Matrix, MatrixError};
+
+pub mod test;
+
+/// Template of all fused kernels. (See Expr::shape)
+const EXPR_TEMPLATE: &str = include_str!("../kernels/expr.cl.in");
+
+/// The elementwise operators which can be fused.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl BinaryOp {
+    fn c_str(&self) -> &str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+        }
+    }
+
+    // Name of the matching kernel in vec_arithmetic.cl.
+    fn kernel_name(&self) -> &str {
+        match self {
+            BinaryOp::Add => "add",
+            BinaryOp::Sub => "sub",
+            BinaryOp::Mul => "mul",
+            BinaryOp::Div => "div",
+        }
+    }
+}
+
+/// A lazily evaluated chain of elementwise operations.
+///
+/// The operators of `&Matrix<Vec<T>>` and `&Matrix<DeviceVec<T>>` build an Expr.
+/// Nothing is computed until `eval`, `eval_device` or a conversion into a Matrix,
+/// which runs the whole expression as one generated kernel. The kernel is compiled
+/// once for every shape of expression and reused afterwards, even with different
+/// operands. A single operator runs the built-in kernel instead.
+///
+/// ```ignore
+/// let d = (&a + &b * &c - 2.0).eval()?;
+/// let e: Matrix<Vec<f32>> = (2.0 * &d).into();
+/// ```
+pub enum Expr<'a, T: HostPrm> {
+    Host(&'a Matrix<Vec<T>>),
+    Device(&'a Matrix<DeviceVec<T>>),
+    Scalar(T),
+    Binary(Box<Expr<'a, T>>, BinaryOp, Box<Expr<'a, T>>),
+}
+
+// A kernel argument of a fused kernel.
+enum Operand<T: HostPrm> {
+    Buffer(Buffer<T>),
+    Scalar(T),
+}
+
+impl<'a, T: HostPrm> Expr<'a, T> {
+    pub(crate) fn binary(lhs: Expr<'a, T>, op: BinaryOp, rhs: Expr<'a, T>) -> Expr<'a, T> {
+        Expr::Binary(Box::new(lhs), op, Box::new(rhs))
+    }
+
+    // The length of all vectors in the expression.
+    fn len(&self) -> Result<Option<usize>, MatrixError> {
+        match self {
+            Expr::Host(a) => Ok(Some(a.A.len())),
+            Expr::Device(a) => Ok(Some(a.A.len())),
+            Expr::Scalar(_) => Ok(None),
+            Expr::Binary(lhs, _, rhs) => match (lhs.len()?, rhs.len()?) {
+                (Some(l), Some(r)) if l != r => Err(MatrixError::SizeMismatch { lhs: l, rhs: r }),
+                (l, r) => Ok(l.or(r)),
+            },
+        }
+    }
+
+    // The loader of the first vector in the expression.
+    fn loader(&self) -> Option<Arc<KernelLoader>> {
+        match self {
+            Expr::Host(a) => a.loader.clone(),
+            Expr::Device(a) => a.loader.clone(),
+            Expr::Scalar(_) => None,
+            Expr::Binary(lhs, _, rhs) => lhs.loader().or_else(|| rhs.loader()),
+        }
+    }
+
+    /// Generates the OpenCL C expression for one element and appends the matching
+    /// parameters to `arguments`. All leaves are collected into `operands` in the
+    /// order of the parameters.
+    ///
+    /// Expressions of the same shape generate the same code, independent of the
+    /// values of their operands.
+    pub(crate) fn shape<'b>(
+        &'b self,
+        arguments: &mut String,
+        operands: &mut Vec<&'b Expr<'a, T>>,
+    ) -> String {
+        let idx = operands.len();
+
+        match self {
+            Expr::Host(_) | Expr::Device(_) => {
+                arguments.push_str(&format!("__global const TYPE_T *v{}, ", idx));
+                operands.push(self);
+                format!("v{}[i]", idx)
+            }
+            Expr::Scalar(_) => {
+                arguments.push_str(&format!("TYPE_T s{}, ", idx));
+                operands.push(self);
+                format!("s{}", idx)
+            }
+            Expr::Binary(lhs, op, rhs) => {
+                let lhs = lhs.shape(arguments, operands);
+                let rhs = rhs.shape(arguments, operands);
+                format!("({} {} {})", lhs, op.c_str(), rhs)
+            }
+        }
+    }
+
+    // Evaluates the expression operator by operator on the host.
+    fn eval_host(&self, len: usize) -> Result<Vec<T>, MatrixError> {
+        match self {
+            Expr::Host(a) => Ok(a.A.clone()),
+            Expr::Device(a) => Ok(a.A.try_host()?.to_vec()),
+            Expr::Scalar(a) => Ok(vec![*a; len]),
+            Expr::Binary(lhs, op, rhs) => Ok(host::basic_op(
+                &lhs.eval_host(len)?,
+                &rhs.eval_host(len)?,
+                op.kernel_name(),
+            )),
+        }
+    }
+
+    // The buffer of a vector in the expression.
+    fn leaf_buffer(&self, queue: &Queue) -> Result<Option<Buffer<T>>, MatrixError> {
+        match self {
+            Expr::Host(a) => Ok(Some(device::upload(queue, &a.A)?)),
+            Expr::Device(a) => Ok(Some(a.A.buffer(queue)?.clone())),
+            Expr::Scalar(_) | Expr::Binary(..) => Ok(None),
+        }
+    }
+
+    // Runs the whole expression as a single fused kernel.
+    fn eval_buffer(&self, loader: &KernelLoader, len: usize) -> Result<Buffer<T>, MatrixError> {
+        let Backend::OpenCl {
+            context,
+            queue,
+            program,
+            compiler,
+        } = &loader.backend
+        else {
+            panic!("Fused kernel on the host backend (bug)");
+        };
+
+        // A single operator doesn't need a generated kernel.
+        if let Expr::Binary(lhs, op, rhs) = self {
+            let name = op.kernel_name();
+
+            match (&**lhs, &**rhs) {
+                (Expr::Scalar(l), r) => {
+                    if let Some(r) = r.leaf_buffer(queue)? {
+                        let name = format!("{}_rscalar", name);
+                        return device::scalar_op(loader, queue, program, &r, *l, &name);
+                    }
+                }
+                (l, Expr::Scalar(r)) => {
+                    if let Some(l) = l.leaf_buffer(queue)? {
+                        let name = format!("{}_scalar", name);
+                        return device::scalar_op(loader, queue, program, &l, *r, &name);
+                    }
+                }
+                (l, r) => {
+                    if let (Some(l), Some(r)) = (l.leaf_buffer(queue)?, r.leaf_buffer(queue)?) {
+                        return device::basic_op(loader, queue, program, &l, &r, name);
+                    }
+                }
+            }
+        }
+
+        let mut arguments = String::new();
+        let mut leaves = Vec::new();
+        let expression = self.shape(&mut arguments, &mut leaves);
+
+        let key = format!("fused {}{}", arguments, expression);
+        let program = compiler
+            .get_or_compile(context, &key, || {
+                format!(
+                    concat!(
+                        "#define KERNEL_NAME fused\n",
+                        "#define ARGUMENTS {}\n",
+                        "#define EXPRESSION {}\n{}"
+                    ),
+                    arguments, expression, EXPR_TEMPLATE
+                )
+            })
+            .map_err(MatrixError::CompileError)?;
+
+        let operands = leaves
+            .iter()
+            .map(|a| match a {
+                Expr::Scalar(a) => Ok(Operand::Scalar(*a)),
+                a => Ok(Operand::Buffer(a.leaf_buffer(queue)?.unwrap())),
+            })
+            .collect::<Result<Vec<_>, MatrixError>>()?;
+
+        let buffer_output = device::new_buffer(queue, len)?;
+
+        let mut builder = Kernel::builder();
+        builder
+            .program(&program)
+            .name("fused")
+            .queue(queue.clone())
+            .global_work_size(loader.global_work_size(len))
+            .local_work_size(loader.local_work_size);
+
+        for operand in &operands {
+            match operand {
+                Operand::Buffer(a) => builder.arg(a),
+                Operand::Scalar(a) => builder.arg(*a),
+            };
+        }
+
+        let kernel = builder
+            .arg(len as u64)
+            .arg(&buffer_output)
+            .build()
+            .map_err(MatrixError::KernelError)?;
+
+        unsafe {
+            kernel.enq().map_err(MatrixError::KernelError)?;
+        }
+
+        Ok(buffer_output)
+    }
+
+    // Checks the expression and returns its loader and length.
+    fn prepare(&self) -> Result<(Arc<KernelLoader>, usize), MatrixError> {
+        let len = self.len()?.ok_or(MatrixError::Empty)?;
+
+        if len == 0 {
+            return Err(MatrixError::Empty);
+        }
+
+        Ok((self.loader().ok_or(MatrixError::NoLoader)?, len))
+    }
+
+    /// Evaluates the expression into host memory.
+    pub fn eval(&self) -> Result<Matrix<Vec<T>>, MatrixError> {
+        let (loader, len) = self.prepare()?;
+
+        let data = match &loader.backend {
+            Backend::OpenCl { .. } => device::download(&self.eval_buffer(&loader, len)?)?,
+            Backend::Host => self.eval_host(len)?,
+        };
+
+        Ok(Matrix {
+            loader: Some(loader),
+            A: data,
+        })
+    }
+
+    /// Evaluates the expression, but keeps the result on the device.
+    pub fn eval_device(&self) -> Result<Matrix<DeviceVec<T>>, MatrixError> {
+        let (loader, len) = self.prepare()?;
+
+        let data = match &loader.backend {
+            Backend::OpenCl { .. } => DeviceVec::from_buffer(self.eval_buffer(&loader, len)?),
+            Backend::Host => DeviceVec::from(self.eval_host(len)?),
+        };
+
+        Ok(Matrix {
+            loader: Some(loader),
+            A: data,
+        })
+    }
+}
+
+impl<'a, T: HostPrm> From<&'a Matrix<Vec<T>>> for Expr<'a, T> {
+    fn from(a: &'a Matrix<Vec<T>>) -> Expr<'a, T> {
+        Expr::Host(a)
+    }
+}
+
+impl<'a, T: HostPrm> From<&'a Matrix<DeviceVec<T>>> for Expr<'a, T> {
+    fn from(a: &'a Matrix<DeviceVec<T>>) -> Expr<'a, T> {
+        Expr::Device(a)
+    }
+}
+
+impl<T: HostPrm> From<Expr<'_, T>> for Matrix<Vec<T>> {
+    fn from(a: Expr<'_, T>) -> Matrix<Vec<T>> {
+        a.eval().unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+impl<T: HostPrm> From<Expr<'_, T>> for Matrix<DeviceVec<T>> {
+    fn from(a: Expr<'_, T>) -> Matrix<DeviceVec<T>> {
+        a.eval_device().unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+impl<T: HostPrm> Matrix<Vec<T>> {
+    /// Starts a lazy expression. (See Expr)
+    pub fn lazy(&self) -> Expr<'_, T> {
+        Expr::Host(self)
+    }
+}
+
+impl<T: HostPrm> Matrix<DeviceVec<T>> {
+    /// Starts a lazy expression. (See Expr)
+    pub fn lazy(&self) -> Expr<'_, T> {
+        Expr::Device(self)
+    }
+}
+
+// Implementation of Expr = Expr @ Expr, Expr @ &Matrix, Expr @ T and &Matrix @ Expr
+macro_rules! expr_oper_impl {
+    ($op: ident, $fn: ident, $variant: ident) => {
+        impl<'a, T: HostPrm> ops::$op<Expr<'a, T>> for Expr<'a, T> {
+            type Output = Expr<'a, T>;
+
+            fn $fn(self, rhs: Expr<'a, T>) -> Self::Output {
+                Expr::binary(self, BinaryOp::$variant, rhs)
+            }
+        }
+
+        impl<'a, T: HostPrm> ops::$op<&'a Matrix<Vec<T>>> for Expr<'a, T> {
+            type Output = Expr<'a, T>;
+
+            fn $fn(self, rhs: &'a Matrix<Vec<T>>) -> Self::Output {
+                Expr::binary(self, BinaryOp::$variant, Expr::Host(rhs))
+            }
+        }
+
+        impl<'a, T: HostPrm> ops::$op<&'a Matrix<DeviceVec<T>>> for Expr<'a, T> {
+            type Output = Expr<'a, T>;
+
+            fn $fn(self, rhs: &'a Matrix<DeviceVec<T>>) -> Self::Output {
+                Expr::binary(self, BinaryOp::$variant, Expr::Device(rhs))
+            }
+        }
+
+        impl<'a, T: HostPrm> ops::$op<T> for Expr<'a, T> {
+            type Output = Expr<'a, T>;
+
+            fn $fn(self, rhs: T) -> Self::Output {
+                Expr::binary(self, BinaryOp::$variant, Expr::Scalar(rhs))
+            }
+        }
+
+        impl<'a, T: HostPrm> ops::$op<Expr<'a, T>> for &'a Matrix<Vec<T>> {
+            type Output = Expr<'a, T>;
+
+            fn $fn(self, rhs: Expr<'a, T>) -> Self::Output {
+                Expr::binary(Expr::Host(self), BinaryOp::$variant, rhs)
+            }
+        }
+
+        impl<'a, T: HostPrm> ops::$op<Expr<'a, T>> for &'a Matrix<DeviceVec<T>> {
+            type Output = Expr<'a, T>;
+
+            fn $fn(self, rhs: Expr<'a, T>) -> Self::Output {
+                Expr::binary(Expr::Device(self), BinaryOp::$variant, rhs)
+            }
+        }
+    };
+}
+
+expr_oper_impl!(Add, add, Add);
+expr_oper_impl!(Sub, sub, Sub);
+expr_oper_impl!(Mul, mul, Mul);
+expr_oper_impl!(Div, div, Div);
+
+// Implementation of Expr = T @ Expr
+// (See rscalar_oper_impl in vector.rs)
+macro_rules! expr_rscalar_oper_impl {
+    ($t: ty) => {
+        expr_rscalar_oper_impl!($t, Add, add, Add);
+        expr_rscalar_oper_impl!($t, Sub, sub, Sub);
+        expr_rscalar_oper_impl!($t, Mul, mul, Mul);
+        expr_rscalar_oper_impl!($t, Div, div, Div);
+    };
+    ($t: ty, $op: ident, $fn: ident, $variant: ident) => {
+        impl<'a> ops::$op<Expr<'a, $t>> for $t {
+            type Output = Expr<'a, $t>;
+
+            fn $fn(self, rhs: Expr<'a, $t>) -> Self::Output {
+                Expr::binary(Expr::Scalar(self), BinaryOp::$variant, rhs)
+            }
+        }
+    };
+}
+
+expr_rscalar_oper_impl!(half::f16);
+expr_rscalar_oper_impl!(f32);
+expr_rscalar_oper_impl!(f64);
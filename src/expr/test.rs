@@ -0,0 +1,102 @@
+#[cfg(test)]
+mod expr_tests {
+    use log::info;
+    use std::time::Instant;
+
+    use crate::expr::Expr;
+    use crate::vector::test::matrix_tests::{loader, setup, timer_end};
+    use crate::{Matrix, MatrixError};
+
+    fn expr_fused(host: bool) {
+        setup();
+        let start = Instant::now();
+
+        let loader = loader::<f32>(host);
+
+        let mut rng = oorandom::Rand32::new(3);
+        let mut random = |len: usize| Matrix {
+            loader: Some(loader.clone()),
+            A: (0..len)
+                .map(|_| (rng.rand_u32() % 10 + 1) as f32)
+                .collect::<Vec<f32>>(),
+        };
+
+        let (a, b, c) = (random(5000), random(5000), random(5000));
+        let dc = c.to_device().unwrap();
+
+        let expected = |f: &dyn Fn(f32, f32, f32) -> f32| {
+            (0..a.A.len())
+                .map(|i| f(a.A[i], b.A[i], c.A[i]))
+                .collect::<Vec<f32>>()
+        };
+
+        let result = (&a + &b * &c - 2.0).eval().unwrap();
+        assert_eq!(result.A, expected(&|a, b, c| a + b * c - 2.0));
+
+        // Host and device operands can be mixed.
+        let result = (&a + b.lazy() * &dc - 2.0).eval().unwrap();
+        assert_eq!(result.A, expected(&|a, b, c| a + b * c - 2.0));
+
+        // Same shape with different operands and scalars.
+        let result = (&b + &c * &a - 0.5).eval_device().unwrap();
+        assert_eq!(result.A.host(), &expected(&|a, b, c| b + c * a - 0.5)[..]);
+
+        let result: Matrix<Vec<f32>> = ((&a - &b) / (&c + 1.0)).into();
+        assert_eq!(result.A, expected(&|a, b, c| (a - b) / (c + 1.0)));
+
+        let result = (2.0 * (&a - &b) + 1.0).eval().unwrap();
+        assert_eq!(result.A, expected(&|a, b, _| 2.0 * (a - b) + 1.0));
+
+        let result = (2.0 / &c).eval().unwrap();
+        assert_eq!(result.A, expected(&|_, _, c| 2.0 / c));
+
+        let err = (&a + &random(3)).eval().unwrap_err();
+        info!("{}", err);
+        assert!(matches!(
+            err,
+            MatrixError::SizeMismatch { lhs: 5000, rhs: 3 }
+        ));
+
+        timer_end(start);
+    }
+
+    #[test]
+    fn expr_fused_f32() {
+        expr_fused(false);
+    }
+
+    #[test]
+    fn expr_fused_host_f32() {
+        expr_fused(true);
+    }
+
+    #[test]
+    fn expr_shape() {
+        let loader = loader::<f32>(true);
+        let new = |a: Vec<f32>| Matrix {
+            loader: Some(loader.clone()),
+            A: a,
+        };
+        let (a, b, c) = (new(vec![1.0]), new(vec![2.0]), new(vec![3.0]));
+
+        let shape = |expr: Expr<'_, f32>| {
+            let mut arguments = String::new();
+            let mut operands = Vec::new();
+            let expression = expr.shape(&mut arguments, &mut operands);
+
+            assert_eq!(operands.len(), 4);
+            (arguments, expression)
+        };
+
+        let (arguments, expression) = shape(&a + &b * &c - 2.0);
+        assert_eq!(expression, "((v0[i] + (v1[i] * v2[i])) - s3)");
+        assert_eq!(
+            arguments,
+            "__global const TYPE_T *v0, __global const TYPE_T *v1, \
+             __global const TYPE_T *v2, TYPE_T s3, "
+        );
+
+        // The values of the operands are not a part of the shape.
+        assert_eq!(shape(&c + &a * &b - 7.0), (arguments, expression));
+    }
+}
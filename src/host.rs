@@ -0,0 +1,685 @@
+use half::f16;
+use std::ops;
+
+/// All types which can be used by the host backend.
+///
+/// The host backend mirrors the OpenCL kernels in plain Rust, so it needs
+/// the arithmetic operators which the kernels get from OpenCL C for free.
+pub trait HostPrm:
+    ocl::OclPrm
+    + ops::Add<Output = Self>
+    + ops::Sub<Output = Self>
+    + ops::Mul<Output = Self>
+    + ops::Div<Output = Self>
+{
+    /// Math functions are computed in f64 and rounded back.
+    fn to_f64(self) -> f64;
+    fn from_f64(a: f64) -> Self;
+}
+
+impl HostPrm for f16 {
+    fn to_f64(self) -> f64 {
+        f16::to_f64(self)
+    }
+
+    fn from_f64(a: f64) -> Self {
+        f16::from_f64(a)
+    }
+}
+
+impl HostPrm for f32 {
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+
+    fn from_f64(a: f64) -> Self {
+        a as f32
+    }
+}
+
+impl HostPrm for f64 {
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    fn from_f64(a: f64) -> Self {
+        a
+    }
+}
+
+// Maps the name of an operator-generic kernel to its operator.
+fn operator<T: HostPrm>(kernel_name: &str) -> fn(T, T) -> T {
+    match kernel_name {
+        "add" => |lhs, rhs| lhs + rhs,
+        "sub" => |lhs, rhs| lhs - rhs,
+        "mul" => |lhs, rhs| lhs * rhs,
+        "div" => |lhs, rhs| lhs / rhs,
+        // vec_reduce.cl
+        "min" => |lhs, rhs| math2(lhs, rhs, f64::min),
+        "max" => |lhs, rhs| math2(lhs, rhs, f64::max),
+        // vec_norm.cl
+        "norm_l1" => |lhs, rhs| math2(lhs, rhs, |l, r| l + r.abs()),
+        "norm_inf" => |lhs, rhs| math2(lhs, rhs, |l, r| l.max(r.abs())),
+        // vec_binary.cl
+        "math_pow" => |lhs, rhs| math2(lhs, rhs, f64::powf),
+        "math_atan2" => |lhs, rhs| math2(lhs, rhs, f64::atan2),
+        "math_hypot" => |lhs, rhs| math2(lhs, rhs, f64::hypot),
+        "math_fmod" => |lhs, rhs| math2(lhs, rhs, |l, r| l % r),
+        "math_fmin" => |lhs, rhs| math2(lhs, rhs, f64::min),
+        "math_fmax" => |lhs, rhs| math2(lhs, rhs, f64::max),
+        "math_copysign" => |lhs, rhs| math2(lhs, rhs, f64::copysign),
+        _ => panic!(
+            "Missing host implementation of kernel {} (bug)",
+            kernel_name
+        ),
+    }
+}
+
+fn math2<T: HostPrm>(lhs: T, rhs: T, f: fn(f64, f64) -> f64) -> T {
+    T::from_f64(f(lhs.to_f64(), rhs.to_f64()))
+}
+
+// Maps the name of a kernel in vec_unary.cl to its function.
+fn function(kernel_name: &str) -> fn(f64) -> f64 {
+    match kernel_name {
+        "math_sqrt" => f64::sqrt,
+        "math_rsqrt" => |a| 1.0 / a.sqrt(),
+        "math_exp" => f64::exp,
+        "math_exp2" => f64::exp2,
+        "math_log" => f64::ln,
+        "math_log2" => f64::log2,
+        "math_sin" => f64::sin,
+        "math_cos" => f64::cos,
+        "math_tan" => f64::tan,
+        "math_tanh" => f64::tanh,
+        "math_abs" => f64::abs,
+        "math_floor" => f64::floor,
+        "math_ceil" => f64::ceil,
+        "math_round" => f64::round,
+        // Unlike signum, sign of OpenCL C returns 0 for zero and NaN.
+        "math_sign" => |a| {
+            if a > 0.0 {
+                1.0
+            } else if a < 0.0 {
+                -1.0
+            } else {
+                0.0
+            }
+        },
+        "math_recip" => f64::recip,
+        _ => panic!(
+            "Missing host implementation of kernel {} (bug)",
+            kernel_name
+        ),
+    }
+}
+
+/// Host version of `KERNEL_NAME` in vec_unary.cl.
+pub(crate) fn unary_op<T: HostPrm>(lhs: &[T], kernel_name: &str) -> Vec<T> {
+    let f = function(kernel_name);
+
+    lhs.iter().map(|l| T::from_f64(f(l.to_f64()))).collect()
+}
+
+/// Host version of `KERNEL_NAME` in vec_arithmetic.cl and vec_binary.cl.
+pub(crate) fn basic_op<T: HostPrm>(lhs: &[T], rhs: &[T], kernel_name: &str) -> Vec<T> {
+    let op = operator::<T>(kernel_name);
+
+    lhs.iter().zip(rhs).map(|(l, r)| op(*l, *r)).collect()
+}
+
+/// Host version of `CAT(KERNEL_NAME, _assign)` in vec_arithmetic.cl.
+pub(crate) fn assign_op<T: HostPrm>(lhs: &mut [T], rhs: &[T], kernel_name: &str) {
+    let op = operator::<T>(
+        kernel_name
+            .strip_suffix("_assign")
+            .expect("Not an _assign kernel (bug)"),
+    );
+
+    for (l, r) in lhs.iter_mut().zip(rhs) {
+        *l = op(*l, *r);
+    }
+}
+
+/// Host version of `CAT(KERNEL_NAME, _scalar)` and `CAT(KERNEL_NAME, _rscalar)` in
+/// vec_arithmetic.cl.
+pub(crate) fn scalar_op<T: HostPrm>(lhs: &[T], rhs: T, kernel_name: &str) -> Vec<T> {
+    if let Some(name) = kernel_name.strip_suffix("_rscalar") {
+        let op = operator::<T>(name);
+        return lhs.iter().map(|l| op(rhs, *l)).collect();
+    }
+
+    let op = operator::<T>(
+        kernel_name
+            .strip_suffix("_scalar")
+            .expect("Not a _scalar kernel (bug)"),
+    );
+
+    lhs.iter().map(|l| op(*l, rhs)).collect()
+}
+
+/// Host version of `CAT(KERNEL_NAME, _assign_scalar)` in vec_arithmetic.cl.
+pub(crate) fn assign_scalar_op<T: HostPrm>(lhs: &mut [T], rhs: T, kernel_name: &str) {
+    let op = operator::<T>(
+        kernel_name
+            .strip_suffix("_assign_scalar")
+            .expect("Not an _assign_scalar kernel (bug)"),
+    );
+
+    for l in lhs.iter_mut() {
+        *l = op(*l, rhs);
+    }
+}
+
+/// Host version of `CAT(KERNEL_NAME, _down)` in reduce.h.
+///
+/// Accumulates in f64, like the kernels accumulate in ACC_T, and returns the
+/// accumulator, which the caller narrows.
+pub(crate) fn down_op<T: HostPrm>(rhs: &[T], kernel_name: &str) -> f64 {
+    let name = kernel_name
+        .strip_suffix("_down")
+        .expect("Not a _down kernel (bug)");
+    let op = operator::<f64>(name);
+    let values = rhs.iter().map(|a| a.to_f64());
+
+    match name {
+        // These have to see every element as rhs, including the first one.
+        "norm_l1" | "norm_inf" => values.fold(0.0, op),
+        _ => values.reduce(op).unwrap_or_default(),
+    }
+}
+
+/// Host version of `CAT(KERNEL_NAME, _arg_down)` in vec_reduce.cl.
+///
+/// Returns None if there is no element to pick. (Only NaNs)
+pub(crate) fn arg_down_op<T: HostPrm>(rhs: &[T], kernel_name: &str) -> Option<usize> {
+    let op = operator::<T>(
+        kernel_name
+            .strip_suffix("_arg_down")
+            .expect("Not an _arg_down kernel (bug)"),
+    );
+
+    let mut picked: Option<(T, usize)> = None;
+
+    for (i, other) in rhs.iter().enumerate() {
+        match picked {
+            // Same comparison as ARG_PICK, but ties are already in order.
+            Some((value, _)) if op(value, *other) == value => {}
+            _ if other.to_f64().is_nan() => {}
+            _ => picked = Some((*other, i)),
+        }
+    }
+
+    picked.map(|(_, i)| i)
+}
+
+/// Host version of welford_down in vec_stats.cl, which returns the mean and the
+/// population variance, both still in f64.
+pub(crate) fn welford<T: HostPrm>(rhs: &[T]) -> (f64, f64) {
+    let mut mean = 0.0;
+    let mut m2 = 0.0;
+
+    for (n, value) in rhs.iter().enumerate() {
+        let value = value.to_f64();
+        let delta = value - mean;
+
+        mean += delta / (n + 1) as f64;
+        m2 += delta * (value - mean);
+    }
+
+    (mean, m2 / rhs.len().max(1) as f64)
+}
+
+/// Host version of dot_down in blas1.cl and add_down_acc.
+pub(crate) fn dot<T: HostPrm>(x: &[T], y: &[T]) -> f64 {
+    x.iter().zip(y).map(|(x, y)| x.to_f64() * y.to_f64()).sum()
+}
+
+/// Host version of nrm2_down in blas1.cl and add_down_acc.
+pub(crate) fn nrm2_scaled<T: HostPrm>(scale: T, x: &[T]) -> f64 {
+    x.iter()
+        .map(|x| (x.to_f64() / scale.to_f64()).powi(2))
+        .sum()
+}
+
+/// Host version of iamax_down in blas1.cl and max_arg_down.
+pub(crate) fn iamax<T: HostPrm>(x: &[T]) -> Option<usize> {
+    let mut picked: Option<(f64, usize)> = None;
+
+    for (i, value) in x.iter().enumerate() {
+        let value = value.to_f64().abs();
+
+        match picked {
+            Some((max, _)) if value <= max => {}
+            _ if value.is_nan() => {}
+            _ => picked = Some((value, i)),
+        }
+    }
+
+    picked.map(|(_, i)| i)
+}
+
+/// Host version of axpy in blas1.cl.
+pub(crate) fn axpy<T: HostPrm>(a: T, x: &[T], y: &mut [T]) {
+    for (x, y) in x.iter().zip(y.iter_mut()) {
+        *y = a * *x + *y;
+    }
+}
+
+/// Host version of rot in blas1.cl.
+pub(crate) fn rot<T: HostPrm>(c: T, s: T, x: &mut [T], y: &mut [T]) {
+    for (x, y) in x.iter_mut().zip(y.iter_mut()) {
+        let (xi, yi) = (*x, *y);
+
+        *x = c * xi + s * yi;
+        *y = c * yi - s * xi;
+    }
+}
+
+/// Host version of gemm in blas3.cl, where op(a) is m x k and op(b) is k x n.
+pub(crate) fn gemm<T: HostPrm>(
+    (m, n, k): (usize, usize, usize),
+    alpha: T,
+    (a, trans_a): (&[T], bool),
+    (b, trans_b): (&[T], bool),
+    beta: T,
+    c: &mut [T],
+) {
+    for row in 0..m {
+        for col in 0..n {
+            let mut acc = 0.0;
+
+            for p in 0..k {
+                let value_a = if trans_a {
+                    a[p * m + row]
+                } else {
+                    a[row * k + p]
+                };
+                let value_b = if trans_b {
+                    b[col * k + p]
+                } else {
+                    b[p * n + col]
+                };
+
+                acc += value_a.to_f64() * value_b.to_f64();
+            }
+
+            let mut result = alpha.to_f64() * acc;
+
+            // Same as the kernel, c is never read with beta = 0.
+            if beta.to_f64() != 0.0 {
+                result += beta.to_f64() * c[row * n + col].to_f64();
+            }
+
+            c[row * n + col] = T::from_f64(result);
+        }
+    }
+}
+
+/// Host version of gemv and gemv_t in blas2.cl, where a is m x n.
+pub(crate) fn gemv<T: HostPrm>(
+    (m, n): (usize, usize),
+    alpha: T,
+    (a, trans): (&[T], bool),
+    x: &[T],
+    beta: T,
+    y: &mut [T],
+) {
+    let (rows, cols) = if trans { (n, m) } else { (m, n) };
+
+    for (row, out) in y.iter_mut().enumerate().take(rows) {
+        let mut acc = 0.0;
+
+        for (col, x) in x.iter().enumerate().take(cols) {
+            let value = if trans {
+                a[col * n + row]
+            } else {
+                a[row * n + col]
+            };
+            acc += value.to_f64() * x.to_f64();
+        }
+
+        let mut result = alpha.to_f64() * acc;
+
+        // Same as the kernels, y is never read with beta = 0.
+        if beta.to_f64() != 0.0 {
+            result += beta.to_f64() * out.to_f64();
+        }
+
+        *out = T::from_f64(result);
+    }
+}
+
+/// Host version of ger in blas2.cl.
+pub(crate) fn ger<T: HostPrm>(alpha: T, x: &[T], y: &[T], a: &mut [T]) {
+    for (row, x) in a.chunks_mut(y.len().max(1)).zip(x) {
+        for (a, y) in row.iter_mut().zip(y) {
+            *a = *a + alpha * *x * *y;
+        }
+    }
+}
+
+/// Row-major strides of a tensor of `shape`.
+pub(crate) fn strides(shape: &[usize]) -> Vec<usize> {
+    let mut strides = vec![1; shape.len()];
+
+    for i in (0..shape.len().saturating_sub(1)).rev() {
+        strides[i] = strides[i + 1] * shape[i + 1];
+    }
+    strides
+}
+
+/// Host version of permute in device.rs.
+pub(crate) fn permute<T: HostPrm>(input: &[T], shape: &[usize], axes: &[usize]) -> Vec<T> {
+    let in_strides = strides(shape);
+    let out_shape = axes.iter().map(|a| shape[*a]).collect::<Vec<usize>>();
+
+    // Index of the current output element, counted up like an odometer.
+    let mut index = vec![0; shape.len()];
+    let mut output = Vec::with_capacity(input.len());
+
+    for _ in 0..input.len() {
+        let offset: usize = index
+            .iter()
+            .zip(axes)
+            .map(|(i, axis)| i * in_strides[*axis])
+            .sum();
+        output.push(input[offset]);
+
+        for (i, len) in index.iter_mut().zip(&out_shape).rev() {
+            *i += 1;
+
+            if *i < *len {
+                break;
+            }
+            *i = 0;
+        }
+    }
+
+    output
+}
+
+/// Host version of trsv in blas2.cl.
+pub(crate) fn trsv<T: HostPrm>(
+    a: &[T],
+    (upper, trans, unit_diag): (bool, bool, bool),
+    x: &mut [T],
+) {
+    let n = x.len();
+    let forward = upper == trans;
+
+    for s in 0..n {
+        let j = if forward { s } else { n - 1 - s };
+
+        if !unit_diag {
+            x[j] = x[j] / a[j * n + j];
+        }
+
+        for r in s + 1..n {
+            let i = if forward { r } else { n - 1 - r };
+            let aij = if trans { a[j * n + i] } else { a[i * n + j] };
+
+            x[i] = x[i] - aij * x[j];
+        }
+    }
+}
+
+/// Host version of the steps in lu.cl. Returns the pivot row of every column.
+pub(crate) fn lu<T: HostPrm>(a: &mut [T], n: usize) -> Vec<usize> {
+    let mut piv = Vec::with_capacity(n);
+
+    for k in 0..n {
+        // lu_pivot
+        let mut p = k;
+        let mut best = -1.0;
+        for i in k..n {
+            let value = a[i * n + k].to_f64().abs();
+
+            if value > best {
+                best = value;
+                p = i;
+            }
+        }
+        piv.push(p);
+
+        // lu_swap
+        if p != k {
+            for j in 0..n {
+                a.swap(k * n + j, p * n + j);
+            }
+        }
+
+        // lu_scale
+        let pivot = a[k * n + k];
+        if pivot.to_f64() == 0.0 {
+            continue;
+        }
+
+        for i in k + 1..n {
+            a[i * n + k] = a[i * n + k] / pivot;
+        }
+
+        // lu_update
+        for i in k + 1..n {
+            for j in k + 1..n {
+                a[i * n + j] = a[i * n + j] - a[i * n + k] * a[k * n + j];
+            }
+        }
+    }
+
+    piv
+}
+
+/// Host version of the steps in gauss_jordan.cl, where a has n rows of width w.
+/// Returns the first column whose pivot isn't larger than threshold.
+pub(crate) fn gauss_jordan<T: HostPrm>(
+    a: &mut [T],
+    (n, w): (usize, usize),
+    threshold: f64,
+) -> Option<usize> {
+    for k in 0..n {
+        // lu_pivot
+        let mut p = k;
+        let mut best = -1.0;
+        for i in k..n {
+            let value = a[i * w + k].to_f64().abs();
+
+            if value > best {
+                best = value;
+                p = i;
+            }
+        }
+
+        // lu_swap
+        if p != k {
+            for j in 0..w {
+                a.swap(k * w + j, p * w + j);
+            }
+        }
+
+        // gj_scale, where NaN counts as singular
+        let pivot = a[k * w + k].to_f64();
+        if pivot.abs().partial_cmp(&threshold) != Some(std::cmp::Ordering::Greater) {
+            return Some(k);
+        }
+
+        for j in k + 1..w {
+            a[k * w + j] = T::from_f64(a[k * w + j].to_f64() / pivot);
+        }
+
+        // gj_eliminate
+        for i in (0..n).filter(|i| *i != k) {
+            for j in k + 1..w {
+                a[i * w + j] = a[i * w + j] - a[i * w + k] * a[k * w + j];
+            }
+        }
+    }
+
+    None
+}
+
+/// Host version of the steps in cholesky.cl. Returns the first column whose
+/// diagonal element isn't positive.
+pub(crate) fn cholesky<T: HostPrm>(a: &mut [T], n: usize) -> Option<usize> {
+    let mut status = None;
+
+    for k in 0..n {
+        // chol_diag, where NaN counts as not positive
+        let d = a[k * n + k].to_f64();
+        if d.partial_cmp(&0.0) != Some(std::cmp::Ordering::Greater) && status.is_none() {
+            status = Some(k);
+        }
+        a[k * n + k] = T::from_f64(d.sqrt());
+
+        // lu_scale
+        let pivot = a[k * n + k];
+        if pivot.to_f64() != 0.0 {
+            for i in k + 1..n {
+                a[i * n + k] = a[i * n + k] / pivot;
+            }
+        }
+
+        // chol_update
+        for i in k + 1..n {
+            for j in k + 1..=i {
+                a[i * n + j] = a[i * n + j] - a[i * n + k] * a[j * n + k];
+            }
+        }
+    }
+
+    status
+}
+
+/// Host version of qr_apply in qr.cl.
+pub(crate) fn qr_apply<T: HostPrm>(
+    a: &[T],
+    n: usize,
+    (k, tau): (usize, T),
+    c: &mut [T],
+    w: usize,
+    start: usize,
+) {
+    let t = tau.to_f64();
+
+    if t == 0.0 {
+        return;
+    }
+
+    let m = c.len() / w;
+    let v = |i: usize| if i == k { 1.0 } else { a[i * n + k].to_f64() };
+
+    for col in start..w {
+        let factor = t * (k..m).map(|i| v(i) * c[i * w + col].to_f64()).sum::<f64>();
+
+        for i in k..m {
+            c[i * w + col] = T::from_f64(c[i * w + col].to_f64() - v(i) * factor);
+        }
+    }
+}
+
+/// Host version of the steps in qr.cl. Returns tau of every reflector.
+pub(crate) fn qr<T: HostPrm>(a: &mut [T], m: usize, n: usize) -> Vec<T> {
+    let mut tau = Vec::with_capacity(m.min(n));
+
+    for k in 0..m.min(n) {
+        // qr_house
+        let alpha = a[k * n + k].to_f64();
+        let sigma: f64 = (k + 1..m).map(|i| a[i * n + k].to_f64().powi(2)).sum();
+
+        if sigma == 0.0 {
+            tau.push(T::from_f64(0.0));
+            continue;
+        }
+
+        let norm = (alpha * alpha + sigma).sqrt();
+        let beta = if alpha >= 0.0 { -norm } else { norm };
+        let scale = 1.0 / (alpha - beta);
+
+        for i in k + 1..m {
+            a[i * n + k] = T::from_f64(a[i * n + k].to_f64() * scale);
+        }
+
+        a[k * n + k] = T::from_f64(beta);
+        tau.push(T::from_f64((beta - alpha) / beta));
+
+        // qr_apply on the remaining columns of a itself
+        let t = tau[k].to_f64();
+        let v = (k..m)
+            .map(|i| if i == k { 1.0 } else { a[i * n + k].to_f64() })
+            .collect::<Vec<f64>>();
+
+        for col in k + 1..n {
+            let factor = t * v
+                .iter()
+                .enumerate()
+                .map(|(p, v)| v * a[(k + p) * n + col].to_f64())
+                .sum::<f64>();
+
+            for (p, v) in v.iter().enumerate() {
+                let i = (k + p) * n + col;
+                a[i] = T::from_f64(a[i].to_f64() - v * factor);
+            }
+        }
+    }
+
+    tau
+}
+
+/// Host version of jacobi_rotate in eigen.cl.
+pub(crate) fn jacobi_rotate<T: HostPrm>(
+    a: &mut [T],
+    v: &mut [T],
+    n: usize,
+    (p, q): (usize, usize),
+) {
+    let apq = a[p * n + q].to_f64();
+
+    if apq == 0.0 {
+        return;
+    }
+
+    let theta = (a[q * n + q].to_f64() - a[p * n + p].to_f64()) / (2.0 * apq);
+    let t = if theta >= 0.0 { 1.0 } else { -1.0 } / (theta.abs() + (theta * theta + 1.0).sqrt());
+    let c = 1.0 / (t * t + 1.0).sqrt();
+    let s = t * c;
+
+    let rotate = |x: &mut [T], i: usize, j: usize| {
+        let (xi, xj) = (x[i].to_f64(), x[j].to_f64());
+        x[i] = T::from_f64(c * xi - s * xj);
+        x[j] = T::from_f64(s * xi + c * xj);
+    };
+
+    for k in 0..n {
+        rotate(a, k * n + p, k * n + q);
+        rotate(v, k * n + p, k * n + q);
+    }
+
+    for k in 0..n {
+        rotate(a, p * n + k, q * n + k);
+    }
+
+    a[p * n + q] = T::from_f64(0.0);
+    a[q * n + p] = T::from_f64(0.0);
+}
+
+/// Host version of power_normalize in eigen.cl. Returns the Rayleigh quotient
+/// and the distance between the old and the new x.
+pub(crate) fn power_normalize<T: HostPrm>(x: &mut [T], y: &[T]) -> (f64, f64) {
+    let dot: f64 = x.iter().zip(y).map(|(x, y)| x.to_f64() * y.to_f64()).sum();
+    let norm: f64 = y.iter().map(|y| y.to_f64().powi(2)).sum();
+
+    if norm == 0.0 {
+        return (0.0, 0.0);
+    }
+
+    let scale = if dot >= 0.0 { 1.0 } else { -1.0 } / norm.sqrt();
+
+    let mut diff = 0.0;
+    for (x, y) in x.iter_mut().zip(y) {
+        let value = y.to_f64() * scale;
+        diff += (value - x.to_f64()).powi(2);
+        *x = T::from_f64(value);
+    }
+
+    (dot, diff.sqrt())
+}
@@ -1,10 +1,18 @@
 #![feature(let_chains)]
 //#![feature(f16)]
 
+pub mod blas;
+pub mod device;
+pub mod expr;
+pub mod host;
+pub mod linalg;
 pub mod loader;
+pub mod vec2d;
 pub mod vector;
 pub use matrix_macro::matrix_new;
 
+use std::error::Error;
+use std::fmt;
 use std::sync::Arc;
 
 #[allow(non_snake_case)]
@@ -13,3 +21,133 @@ pub struct Matrix<T> {
     pub loader: Option<Arc<crate::loader::KernelLoader>>,
     pub A: T,
 }
+
+/// Errors of all fallible (try_*) matrix operations.
+#[derive(Debug)]
+pub enum MatrixError {
+    /// The matrix was created without a KernelLoader.
+    NoLoader,
+    /// Both operands have to be the same size.
+    SizeMismatch {
+        lhs: usize,
+        rhs: usize,
+    },
+    /// Both matrices have to have the same shape. (rows, cols)
+    ShapeMismatch {
+        lhs: (usize, usize),
+        rhs: (usize, usize),
+    },
+    /// The number of elements of a rows x cols matrix doesn't fit into usize.
+    TooLarge {
+        rows: usize,
+        cols: usize,
+    },
+    /// All rows of a nested vector have to be the same length.
+    RaggedRows {
+        row: usize,
+        len: usize,
+        cols: usize,
+    },
+    /// The axes have to be a permutation of all dims axes.
+    InvalidAxes {
+        axes: Vec<usize>,
+        dims: usize,
+    },
+    /// The operation needs a square matrix.
+    NotSquare {
+        rows: usize,
+        cols: usize,
+    },
+    /// A least squares solution needs at least as many rows as columns.
+    Underdetermined {
+        rows: usize,
+        cols: usize,
+    },
+    /// The matrix is singular, because the pivot of this row or column is zero.
+    Singular {
+        pivot: usize,
+    },
+    /// The matrix isn't positive definite, because the pivot of this column isn't
+    /// positive.
+    NotPositiveDefinite {
+        pivot: usize,
+    },
+    /// An iterative method didn't converge within this many iterations.
+    NotConverged {
+        iterations: usize,
+    },
+    /// The operation needs at least one element.
+    Empty,
+    BufferError(ocl::error::Error),
+    TransferError(ocl::error::Error),
+    KernelError(ocl::error::Error),
+    /// A generated kernel failed to compile.
+    CompileError(crate::loader::KernelLoaderEr),
+}
+
+impl fmt::Display for MatrixError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MatrixError::NoLoader => write!(f, "Matrix loader not initialized"),
+            MatrixError::SizeMismatch { lhs, rhs } => write!(
+                f,
+                "Both operators have to have the same size! lhs:{} != rhs:{}",
+                lhs, rhs
+            ),
+            MatrixError::ShapeMismatch { lhs, rhs } => write!(
+                f,
+                "Both operators have to have the same shape! lhs:{}x{} != rhs:{}x{}",
+                lhs.0, lhs.1, rhs.0, rhs.1
+            ),
+            MatrixError::TooLarge { rows, cols } => {
+                write!(f, "The matrix is too large! {}x{} elements", rows, cols)
+            }
+            MatrixError::RaggedRows { row, len, cols } => write!(
+                f,
+                "All rows have to be the same length! row {} has {} != {} columns",
+                row, len, cols
+            ),
+            MatrixError::InvalidAxes { axes, dims } => write!(
+                f,
+                "The axes {:?} are not a permutation of 0..{}!",
+                axes, dims
+            ),
+            MatrixError::NotSquare { rows, cols } => {
+                write!(f, "The matrix has to be square! {}x{}", rows, cols)
+            }
+            MatrixError::Underdetermined { rows, cols } => write!(
+                f,
+                "The system is underdetermined! {}x{} has more columns than rows",
+                rows, cols
+            ),
+            MatrixError::Singular { pivot } => {
+                write!(f, "The matrix is singular! (Pivot {} is zero)", pivot)
+            }
+            MatrixError::NotPositiveDefinite { pivot } => write!(
+                f,
+                "The matrix is not positive definite! (Pivot {} is not positive)",
+                pivot
+            ),
+            MatrixError::NotConverged { iterations } => {
+                write!(f, "Did not converge after {} iterations!", iterations)
+            }
+            MatrixError::Empty => write!(f, "Matrix is empty"),
+            MatrixError::BufferError(e) => write!(f, "Failed to create buffer: {}", e),
+            MatrixError::TransferError(e) => write!(f, "Failed to transfer buffer: {}", e),
+            MatrixError::KernelError(e) => write!(f, "Failed to run kernel: {}", e),
+            MatrixError::CompileError(e) => write!(f, "Failed to compile kernel: {}", e),
+        }
+    }
+}
+
+impl Error for MatrixError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MatrixError::BufferError(e)
+            | MatrixError::TransferError(e)
+            | MatrixError::KernelError(e) => Some(e),
+            MatrixError::CompileError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,1107 @@
+use std::sync::Arc;
+
+use crate::device::{self, KernelArg};
+use crate::host::{self, HostPrm};
+use crate::loader::{Backend, KernelLoader};
+use crate::vec2d::Vec2D;
+use crate::{Matrix, MatrixError};
+
+pub mod test;
+
+// Limits of the iterative eigenvalue solvers.
+const MAX_SWEEPS: usize = 100;
+const MAX_ITERATIONS: usize = 10_000;
+
+// The power iteration only downloads its result to check the convergence after
+// this many iterations.
+const CHECK_INTERVAL: usize = 16;
+
+// Solves the triangular systems of `a` one after another in place of x, where
+// every system is given by the flags (upper, trans, unit_diag) of trsv.
+pub(crate) fn triangular_solve<T: HostPrm>(
+    a: &Matrix<Vec2D<T>>,
+    systems: &[(bool, bool, bool)],
+    mut x: Vec<T>,
+) -> Result<Vec<T>, MatrixError> {
+    let loader = a.loader.clone().ok_or(MatrixError::NoLoader)?;
+
+    match &loader.backend {
+        Backend::OpenCl { queue, program, .. } => {
+            let buffer_a = device::upload(queue, a.A.as_slice())?;
+            let buffer_x = device::upload(queue, &x)?;
+
+            for flags in systems {
+                device::trsv(&loader, queue, program, &buffer_a, *flags, &buffer_x)?;
+            }
+
+            device::download_into(&buffer_x, &mut x)?;
+        }
+        Backend::Host => {
+            for flags in systems {
+                host::trsv(a.A.as_slice(), *flags, &mut x);
+            }
+        }
+    }
+
+    Ok(x)
+}
+
+/// LU decomposition with partial pivoting. (P * A = L * U, see `Matrix::lu`)
+pub struct Lu<T: HostPrm> {
+    // L below the diagonal (without its unit diagonal) and U on and above it.
+    lu: Matrix<Vec2D<T>>,
+    /// Row i of P * A is row perm[i] of A.
+    pub perm: Vec<usize>,
+    // Number of row swaps, which is the sign of the determinant.
+    swaps: usize,
+    // Pivots up to this magnitude count as zero.
+    threshold: f64,
+}
+
+impl<T> Lu<T>
+where
+    T: HostPrm,
+{
+    /// Lower triangular factor with a unit diagonal.
+    pub fn l(&self) -> Matrix<Vec2D<T>> {
+        let n = self.lu.A.rows();
+        let mut l = Vec2D::identity(n);
+
+        for row in 0..n {
+            l.row_mut(row)[..row].copy_from_slice(&self.lu.A.row(row)[..row]);
+        }
+
+        Matrix {
+            loader: self.lu.loader.clone(),
+            A: l,
+        }
+    }
+
+    /// Upper triangular factor.
+    pub fn u(&self) -> Matrix<Vec2D<T>> {
+        let n = self.lu.A.rows();
+        let mut u = Vec2D::zeros(n, n);
+
+        for row in 0..n {
+            u.row_mut(row)[row..].copy_from_slice(&self.lu.A.row(row)[row..]);
+        }
+
+        Matrix {
+            loader: self.lu.loader.clone(),
+            A: u,
+        }
+    }
+
+    /// Fallible version of `solve`.
+    pub fn try_solve(&self, b: &Matrix<Vec<T>>) -> Result<Matrix<Vec<T>>, MatrixError> {
+        let n = self.lu.A.rows();
+
+        if b.A.len() != n {
+            return Err(MatrixError::SizeMismatch {
+                lhs: n,
+                rhs: b.A.len(),
+            });
+        }
+
+        if let Some(pivot) = (0..n).find(|i| self.lu.A[(*i, *i)].to_f64().abs() <= self.threshold) {
+            return Err(MatrixError::Singular { pivot });
+        }
+
+        // P * A * x = P * b, so L * y = P * b and then U * x = y
+        let x = self.perm.iter().map(|i| b.A[*i]).collect::<Vec<T>>();
+
+        Ok(Matrix {
+            loader: b.loader.clone(),
+            A: triangular_solve(&self.lu, &[(false, false, true), (true, false, false)], x)?,
+        })
+    }
+
+    /// Solves A * x = b.
+    pub fn solve(&self, b: &Matrix<Vec<T>>) -> Matrix<Vec<T>> {
+        self.try_solve(b).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Determinant of A. (0 if A is singular, with the same pivot threshold as
+    /// `solve`)
+    pub fn det(&self) -> T {
+        let n = self.lu.A.rows();
+
+        if (0..n).any(|i| self.lu.A[(i, i)].to_f64().abs() <= self.threshold) {
+            return T::from_f64(0.0);
+        }
+
+        let sign = (-1.0f64).powi(self.swaps as i32);
+
+        T::from_f64(
+            (0..n)
+                .map(|i| self.lu.A[(i, i)].to_f64())
+                .fold(sign, |a, b| a * b),
+        )
+    }
+}
+
+/// Cholesky decomposition of a symmetric positive definite matrix. (A = L * L^T,
+/// see `Matrix::cholesky`)
+pub struct Cholesky<T: HostPrm> {
+    l: Matrix<Vec2D<T>>,
+}
+
+impl<T> Cholesky<T>
+where
+    T: HostPrm,
+{
+    /// Lower triangular factor.
+    pub fn l(&self) -> &Matrix<Vec2D<T>> {
+        &self.l
+    }
+
+    /// Fallible version of `solve`.
+    pub fn try_solve(&self, b: &Matrix<Vec<T>>) -> Result<Matrix<Vec<T>>, MatrixError> {
+        if b.A.len() != self.l.A.rows() {
+            return Err(MatrixError::SizeMismatch {
+                lhs: self.l.A.rows(),
+                rhs: b.A.len(),
+            });
+        }
+
+        // Forward substitution L * y = b, then back substitution L^T * x = y
+        Ok(Matrix {
+            loader: b.loader.clone(),
+            A: triangular_solve(
+                &self.l,
+                &[(false, false, false), (false, true, false)],
+                b.A.clone(),
+            )?,
+        })
+    }
+
+    /// Solves A * x = b.
+    pub fn solve(&self, b: &Matrix<Vec<T>>) -> Matrix<Vec<T>> {
+        self.try_solve(b).unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+/// Householder QR decomposition. (A = Q * R, see `Matrix::qr`)
+pub struct Qr<T: HostPrm> {
+    // R on and above the diagonal and the reflectors below it.
+    qr: Matrix<Vec2D<T>>,
+    tau: Vec<T>,
+    // Diagonal elements of R up to this magnitude count as zero.
+    threshold: f64,
+}
+
+impl<T> Qr<T>
+where
+    T: HostPrm,
+{
+    // Applies the reflectors to the columns of c, which gives Q^T * c, or Q * c in
+    // reverse order.
+    fn apply(&self, mut c: Vec2D<T>, reverse: bool) -> Result<Vec2D<T>, MatrixError> {
+        let loader = self.qr.loader.clone().ok_or(MatrixError::NoLoader)?;
+        let (m, n) = self.qr.shape();
+
+        let mut order = (0..self.tau.len()).collect::<Vec<usize>>();
+        if reverse {
+            order.reverse();
+        }
+
+        match &loader.backend {
+            Backend::OpenCl { queue, program, .. } => {
+                let buffer_a = device::upload(queue, self.qr.A.as_slice())?;
+                let buffer_tau = device::upload(queue, &self.tau)?;
+                let buffer_c = device::upload(queue, c.as_slice())?;
+                let acc_size = loader.kernel_type.get_type().acc_size();
+
+                for k in order {
+                    device::group_op(
+                        &loader,
+                        queue,
+                        program,
+                        "qr_apply",
+                        &[
+                            KernelArg::Size(m),
+                            KernelArg::Size(n),
+                            KernelArg::Size(k),
+                            KernelArg::Buffer(&buffer_a),
+                            KernelArg::Buffer(&buffer_tau),
+                            KernelArg::Buffer(&buffer_c),
+                            KernelArg::Size(c.cols()),
+                            KernelArg::Size(0),
+                        ],
+                        c.cols(),
+                        &[acc_size],
+                    )?;
+                }
+
+                device::download_into(&buffer_c, c.as_mut_slice())?;
+            }
+            Backend::Host => {
+                let w = c.cols();
+
+                for k in order {
+                    host::qr_apply(
+                        self.qr.A.as_slice(),
+                        n,
+                        (k, self.tau[k]),
+                        c.as_mut_slice(),
+                        w,
+                        0,
+                    );
+                }
+            }
+        }
+
+        Ok(c)
+    }
+
+    /// Fallible version of `q`.
+    pub fn try_q(&self) -> Result<Matrix<Vec2D<T>>, MatrixError> {
+        let (m, n) = self.qr.shape();
+        let k = m.min(n);
+
+        let mut q = Vec2D::zeros(m, k);
+        for i in 0..k {
+            q[(i, i)] = T::from_f64(1.0);
+        }
+
+        Ok(Matrix {
+            loader: self.qr.loader.clone(),
+            A: self.apply(q, true)?,
+        })
+    }
+
+    /// Orthonormal factor with min(m, n) columns. The reflectors are applied on
+    /// the device.
+    pub fn q(&self) -> Matrix<Vec2D<T>> {
+        self.try_q().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Upper triangular factor with min(m, n) rows.
+    pub fn r(&self) -> Matrix<Vec2D<T>> {
+        let (m, n) = self.qr.shape();
+        let mut r = Vec2D::zeros(m.min(n), n);
+
+        for row in 0..m.min(n) {
+            r.row_mut(row)[row..].copy_from_slice(&self.qr.A.row(row)[row..]);
+        }
+
+        Matrix {
+            loader: self.qr.loader.clone(),
+            A: r,
+        }
+    }
+
+    /// Fallible version of `solve`.
+    pub fn try_solve(&self, b: &Matrix<Vec<T>>) -> Result<Matrix<Vec<T>>, MatrixError> {
+        let (m, n) = self.qr.shape();
+
+        if m < n {
+            return Err(MatrixError::Underdetermined { rows: m, cols: n });
+        }
+
+        if b.A.len() != m {
+            return Err(MatrixError::SizeMismatch {
+                lhs: m,
+                rhs: b.A.len(),
+            });
+        }
+
+        if let Some(pivot) = (0..n).find(|i| self.qr.A[(*i, *i)].to_f64().abs() <= self.threshold) {
+            return Err(MatrixError::Singular { pivot });
+        }
+
+        // R * x = Q^T * b, where only the first n rows of R aren't zero.
+        let mut y = self
+            .apply(Vec2D::new(m, 1, b.A.clone())?, false)?
+            .into_vec();
+        y.truncate(n);
+
+        let r = Matrix {
+            loader: self.qr.loader.clone(),
+            A: Vec2D::new(n, n, self.qr.A.as_slice()[..n * n].to_vec())?,
+        };
+
+        Ok(Matrix {
+            loader: b.loader.clone(),
+            A: triangular_solve(&r, &[(true, false, false)], y)?,
+        })
+    }
+
+    /// Least squares solution of A * x = b, which minimizes |A * x - b|.
+    pub fn solve(&self, b: &Matrix<Vec<T>>) -> Matrix<Vec<T>> {
+        self.try_solve(b).unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+/// Eigenvalues and unit eigenvectors. (See `Matrix::eigh` and
+/// `Matrix::power_iteration`)
+pub struct Eigen<T: HostPrm> {
+    pub values: Vec<T>,
+    /// Column i is the eigenvector of values[i].
+    pub vectors: Matrix<Vec2D<T>>,
+}
+
+impl<T> Matrix<Vec2D<T>>
+where
+    T: HostPrm,
+{
+    // Checks that the matrix is square and not empty and returns the loader.
+    fn square_check(&self) -> Result<Arc<KernelLoader>, MatrixError> {
+        let (rows, cols) = self.A.shape();
+
+        if rows != cols {
+            return Err(MatrixError::NotSquare { rows, cols });
+        }
+
+        if self.A.is_empty() {
+            return Err(MatrixError::Empty);
+        }
+
+        self.loader.clone().ok_or(MatrixError::NoLoader)
+    }
+
+    /// Fallible version of `lu`.
+    pub fn try_lu(&self) -> Result<Lu<T>, MatrixError> {
+        let loader = self.square_check()?;
+        let n = self.A.rows();
+
+        // Rounding leaves pivots of singular matrices behind, which are tiny compared
+        // to the largest element, but not exactly zero.
+        let largest = self
+            .A
+            .as_slice()
+            .iter()
+            .fold(0.0, |a: f64, b| a.max(b.to_f64().abs()));
+        let threshold = n as f64 * loader.kernel_type.get_type().epsilon() * largest;
+
+        let mut lu = self.clone();
+
+        let piv = match &loader.backend {
+            Backend::OpenCl { queue, program, .. } => {
+                let buffer_a = device::upload(queue, self.A.as_slice())?;
+                let buffer_piv = device::new_buffer::<u64>(queue, n)?;
+                let acc_size = loader.kernel_type.get_type().acc_size();
+
+                // The pivots never leave the device until the end.
+                for k in 0..n {
+                    device::group_op(
+                        &loader,
+                        queue,
+                        program,
+                        "lu_pivot",
+                        &[
+                            KernelArg::Size(n),
+                            KernelArg::Size(n),
+                            KernelArg::Size(k),
+                            KernelArg::Buffer(&buffer_a),
+                            KernelArg::Sizes(&buffer_piv),
+                        ],
+                        1,
+                        &[acc_size, size_of::<u64>()],
+                    )?;
+
+                    device::fused_op(
+                        &loader,
+                        queue,
+                        program,
+                        "lu_swap",
+                        &[
+                            KernelArg::Size(k),
+                            KernelArg::Sizes(&buffer_piv),
+                            KernelArg::Buffer(&buffer_a),
+                        ],
+                        n,
+                    )?;
+
+                    device::fused_op(
+                        &loader,
+                        queue,
+                        program,
+                        "lu_scale",
+                        &[KernelArg::Size(k), KernelArg::Buffer(&buffer_a)],
+                        n,
+                    )?;
+
+                    if k + 1 < n {
+                        device::tiled_op(
+                            &loader,
+                            queue,
+                            program,
+                            "lu_update",
+                            &[
+                                KernelArg::Size(n),
+                                KernelArg::Size(k),
+                                KernelArg::Buffer(&buffer_a),
+                            ],
+                            (1, n - k - 1, n - k - 1),
+                            0,
+                        )?;
+                    }
+                }
+
+                device::download_into(&buffer_a, lu.A.as_mut_slice())?;
+                device::download(&buffer_piv)?
+                    .into_iter()
+                    .map(|p| p as usize)
+                    .collect()
+            }
+            Backend::Host => host::lu(lu.A.as_mut_slice(), n),
+        };
+
+        // Apply the swaps in the same order as the kernels.
+        let mut perm = (0..n).collect::<Vec<usize>>();
+        for (k, p) in piv.iter().enumerate() {
+            perm.swap(k, *p);
+        }
+
+        Ok(Lu {
+            lu,
+            perm,
+            swaps: piv.iter().enumerate().filter(|(k, p)| k != *p).count(),
+            threshold,
+        })
+    }
+
+    /// LU decomposition with partial pivoting, P * A = L * U.
+    ///
+    /// The row elimination runs on the device. A singular matrix still has a
+    /// decomposition, but it can't be solved. A pivot counts as zero if it isn't
+    /// larger than n * epsilon times the largest element of A.
+    pub fn lu(&self) -> Lu<T> {
+        self.try_lu().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `cholesky`.
+    pub fn try_cholesky(&self) -> Result<Cholesky<T>, MatrixError> {
+        let loader = self.square_check()?;
+        let n = self.A.rows();
+
+        let mut l = self.clone();
+
+        let status = match &loader.backend {
+            Backend::OpenCl { queue, program, .. } => {
+                let buffer_a = device::upload(queue, self.A.as_slice())?;
+                let buffer_status = device::upload(queue, &[0u64])?;
+
+                for k in 0..n {
+                    device::fused_op(
+                        &loader,
+                        queue,
+                        program,
+                        "chol_diag",
+                        &[
+                            KernelArg::Size(k),
+                            KernelArg::Buffer(&buffer_a),
+                            KernelArg::Sizes(&buffer_status),
+                        ],
+                        n,
+                    )?;
+
+                    device::fused_op(
+                        &loader,
+                        queue,
+                        program,
+                        "lu_scale",
+                        &[KernelArg::Size(k), KernelArg::Buffer(&buffer_a)],
+                        n,
+                    )?;
+
+                    if k + 1 < n {
+                        device::tiled_op(
+                            &loader,
+                            queue,
+                            program,
+                            "chol_update",
+                            &[
+                                KernelArg::Size(n),
+                                KernelArg::Size(k),
+                                KernelArg::Buffer(&buffer_a),
+                            ],
+                            (1, n - k - 1, n - k - 1),
+                            0,
+                        )?;
+                    }
+                }
+
+                device::download_into(&buffer_a, l.A.as_mut_slice())?;
+                device::download(&buffer_status)?[0]
+                    .checked_sub(1)
+                    .map(|k| k as usize)
+            }
+            Backend::Host => host::cholesky(l.A.as_mut_slice(), n),
+        };
+
+        if let Some(pivot) = status {
+            return Err(MatrixError::NotPositiveDefinite { pivot });
+        }
+
+        // The upper triangle still holds A.
+        for row in 0..n {
+            for a in &mut l.A.row_mut(row)[row + 1..] {
+                *a = T::from_f64(0.0);
+            }
+        }
+
+        Ok(Cholesky { l })
+    }
+
+    /// Cholesky decomposition, A = L * L^T.
+    ///
+    /// Only the lower triangle of the matrix is read, so it has to be symmetric.
+    /// Returns an error instead if it isn't positive definite.
+    pub fn cholesky(&self) -> Cholesky<T> {
+        self.try_cholesky().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `cholesky_solve`.
+    pub fn try_cholesky_solve(&self, b: &Matrix<Vec<T>>) -> Result<Matrix<Vec<T>>, MatrixError> {
+        self.try_cholesky()?.try_solve(b)
+    }
+
+    /// Solves self * x = b with a Cholesky decomposition, which needs about half the
+    /// work of `solve`, but only works for symmetric positive definite matrices.
+    pub fn cholesky_solve(&self, b: &Matrix<Vec<T>>) -> Matrix<Vec<T>> {
+        self.try_cholesky_solve(b)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `qr`.
+    pub fn try_qr(&self) -> Result<Qr<T>, MatrixError> {
+        if self.A.is_empty() {
+            return Err(MatrixError::Empty);
+        }
+
+        let loader = self.loader.clone().ok_or(MatrixError::NoLoader)?;
+        let (m, n) = self.shape();
+
+        let mut qr = self.clone();
+
+        let tau = match &loader.backend {
+            Backend::OpenCl { queue, program, .. } => {
+                let buffer_a = device::upload(queue, self.A.as_slice())?;
+                let buffer_tau = device::new_buffer::<T>(queue, m.min(n))?;
+                let acc_size = loader.kernel_type.get_type().acc_size();
+
+                for k in 0..m.min(n) {
+                    device::group_op(
+                        &loader,
+                        queue,
+                        program,
+                        "qr_house",
+                        &[
+                            KernelArg::Size(m),
+                            KernelArg::Size(n),
+                            KernelArg::Size(k),
+                            KernelArg::Buffer(&buffer_a),
+                            KernelArg::Buffer(&buffer_tau),
+                        ],
+                        1,
+                        &[acc_size],
+                    )?;
+
+                    if k + 1 < n {
+                        device::group_op(
+                            &loader,
+                            queue,
+                            program,
+                            "qr_apply",
+                            &[
+                                KernelArg::Size(m),
+                                KernelArg::Size(n),
+                                KernelArg::Size(k),
+                                KernelArg::Buffer(&buffer_a),
+                                KernelArg::Buffer(&buffer_tau),
+                                KernelArg::Buffer(&buffer_a),
+                                KernelArg::Size(n),
+                                KernelArg::Size(k + 1),
+                            ],
+                            n - k - 1,
+                            &[acc_size],
+                        )?;
+                    }
+                }
+
+                device::download_into(&buffer_a, qr.A.as_mut_slice())?;
+                device::download(&buffer_tau)?
+            }
+            Backend::Host => host::qr(qr.A.as_mut_slice(), m, n),
+        };
+
+        // Rounding leaves tiny diagonal elements behind for dependent columns,
+        // which are hardly ever exactly zero.
+        let largest = (0..m.min(n)).fold(0.0, |a: f64, i| a.max(qr.A[(i, i)].to_f64().abs()));
+        let threshold = m.max(n) as f64 * loader.kernel_type.get_type().epsilon() * largest;
+
+        Ok(Qr { qr, tau, threshold })
+    }
+
+    /// Householder QR decomposition, A = Q * R, of an m x n matrix.
+    ///
+    /// Every reflector is computed and applied on the device.
+    pub fn qr(&self) -> Qr<T> {
+        self.try_qr().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `lstsq`.
+    pub fn try_lstsq(&self, b: &Matrix<Vec<T>>) -> Result<Matrix<Vec<T>>, MatrixError> {
+        self.try_qr()?.try_solve(b)
+    }
+
+    /// Least squares solution of self * x = b with a QR decomposition, for systems
+    /// with at least as many rows as columns. A diagonal element of R counts as
+    /// zero if it isn't larger than max(m, n) * epsilon times the largest one.
+    pub fn lstsq(&self, b: &Matrix<Vec<T>>) -> Matrix<Vec<T>> {
+        self.try_lstsq(b).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `inverse`.
+    pub fn try_inverse(&self, tolerance: f64) -> Result<Matrix<Vec2D<T>>, MatrixError> {
+        let loader = self.square_check()?;
+        let (n, w) = (self.A.rows(), 2 * self.A.rows());
+
+        // Pivots are compared with the largest element, so that the tolerance
+        // doesn't depend on the scale of the matrix. The comparison is done in
+        // ACC_T, because useful thresholds can round to zero in f16.
+        let largest = self
+            .A
+            .as_slice()
+            .iter()
+            .map(|a| a.to_f64().abs())
+            .fold(0.0, f64::max);
+        let threshold = tolerance * largest;
+
+        // [A | I]
+        let mut augmented = Vec2D::zeros(n, w);
+        for row in 0..n {
+            augmented.row_mut(row)[..n].copy_from_slice(self.A.row(row));
+            augmented[(row, n + row)] = T::from_f64(1.0);
+        }
+
+        let status = match &loader.backend {
+            Backend::OpenCl { queue, program, .. } => {
+                let buffer_a = device::upload(queue, augmented.as_slice())?;
+                let buffer_piv = device::new_buffer::<u64>(queue, n)?;
+                let buffer_status = device::upload(queue, &[0u64])?;
+                let acc_size = loader.kernel_type.get_type().acc_size();
+
+                for k in 0..n {
+                    device::group_op(
+                        &loader,
+                        queue,
+                        program,
+                        "lu_pivot",
+                        &[
+                            KernelArg::Size(n),
+                            KernelArg::Size(w),
+                            KernelArg::Size(k),
+                            KernelArg::Buffer(&buffer_a),
+                            KernelArg::Sizes(&buffer_piv),
+                        ],
+                        1,
+                        &[acc_size, size_of::<u64>()],
+                    )?;
+
+                    device::fused_op(
+                        &loader,
+                        queue,
+                        program,
+                        "lu_swap",
+                        &[
+                            KernelArg::Size(k),
+                            KernelArg::Sizes(&buffer_piv),
+                            KernelArg::Buffer(&buffer_a),
+                        ],
+                        w,
+                    )?;
+
+                    device::fused_op(
+                        &loader,
+                        queue,
+                        program,
+                        "gj_scale",
+                        &[
+                            KernelArg::Size(k),
+                            KernelArg::AccScalar(threshold),
+                            KernelArg::Buffer(&buffer_a),
+                            KernelArg::Sizes(&buffer_status),
+                        ],
+                        w,
+                    )?;
+
+                    device::tiled_op(
+                        &loader,
+                        queue,
+                        program,
+                        "gj_eliminate",
+                        &[
+                            KernelArg::Size(n),
+                            KernelArg::Size(w),
+                            KernelArg::Size(k),
+                            KernelArg::Buffer(&buffer_a),
+                        ],
+                        (1, n, w - k - 1),
+                        0,
+                    )?;
+                }
+
+                device::download_into(&buffer_a, augmented.as_mut_slice())?;
+                device::download(&buffer_status)?[0]
+                    .checked_sub(1)
+                    .map(|k| k as usize)
+            }
+            Backend::Host => host::gauss_jordan(augmented.as_mut_slice(), (n, w), threshold),
+        };
+
+        if let Some(pivot) = status {
+            return Err(MatrixError::Singular { pivot });
+        }
+
+        let mut inverse = Vec2D::zeros(n, n);
+        for row in 0..n {
+            inverse
+                .row_mut(row)
+                .copy_from_slice(&augmented.row(row)[n..]);
+        }
+
+        Ok(Matrix {
+            loader: self.loader.clone(),
+            A: inverse,
+        })
+    }
+
+    /// Inverse with Gauss-Jordan elimination on the device.
+    ///
+    /// The matrix counts as singular if a pivot isn't larger than tolerance times
+    /// the largest absolute element, so 0 only rejects exactly singular matrices.
+    pub fn inverse(&self, tolerance: f64) -> Matrix<Vec2D<T>> {
+        self.try_inverse(tolerance)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `pinv`.
+    pub fn try_pinv(&self, tolerance: f64) -> Result<Matrix<Vec2D<T>>, MatrixError> {
+        let (m, n) = self.shape();
+
+        // pinv(A) = pinv(A^T)^T
+        if m < n {
+            return self.try_transpose()?.try_pinv(tolerance)?.try_transpose();
+        }
+
+        // pinv(A) = R^-1 * Q^T with the reduced QR decomposition.
+        let qr = self.try_qr()?;
+        let r_inv = qr.r().try_inverse(tolerance)?;
+
+        let mut pinv = Matrix {
+            loader: self.loader.clone(),
+            A: Vec2D::zeros(n, m),
+        };
+        pinv.try_gemm(
+            T::from_f64(1.0),
+            &r_inv,
+            false,
+            &qr.try_q()?,
+            true,
+            T::from_f64(0.0),
+        )?;
+
+        Ok(pinv)
+    }
+
+    /// Moore-Penrose pseudo-inverse with a QR decomposition, for matrices with
+    /// full rank. A rank deficient matrix is singular with the same tolerance as
+    /// `inverse`, relative to its R factor.
+    pub fn pinv(&self, tolerance: f64) -> Matrix<Vec2D<T>> {
+        self.try_pinv(tolerance).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `eigh`.
+    pub fn try_eigh(&self) -> Result<Eigen<T>, MatrixError> {
+        let loader = self.square_check()?;
+        let n = self.A.rows();
+        let epsilon = loader.kernel_type.get_type().epsilon();
+
+        let mut a = self.A.clone();
+        let mut v = Vec2D::identity(n);
+
+        // Done once the off-diagonal part is negligible compared to the whole
+        // matrix. Returns an error if the last sweep didn't get there.
+        let converged = |a: &Vec2D<T>, sweeps: usize| {
+            let (mut off, mut total) = (0.0, 0.0);
+            for row in 0..n {
+                for (col, a) in a.row(row).iter().enumerate() {
+                    let square = a.to_f64().powi(2);
+                    total += square;
+                    if col != row {
+                        off += square;
+                    }
+                }
+            }
+
+            match off.sqrt() <= epsilon * total.sqrt() {
+                true => Ok(true),
+                false if sweeps == MAX_SWEEPS => Err(MatrixError::NotConverged {
+                    iterations: MAX_SWEEPS,
+                }),
+                false => Ok(false),
+            }
+        };
+
+        // Cyclic Jacobi sweeps, which rotate away every off-diagonal pair.
+        let pairs = (0..n).flat_map(|p| (p + 1..n).map(move |q| (p, q)));
+
+        match &loader.backend {
+            Backend::OpenCl { queue, program, .. } => {
+                let buffer_a = device::upload(queue, a.as_slice())?;
+                let buffer_v = device::upload(queue, v.as_slice())?;
+
+                let mut sweeps = 0;
+                while !converged(&a, sweeps)? {
+                    for (p, q) in pairs.clone() {
+                        device::group_op(
+                            &loader,
+                            queue,
+                            program,
+                            "jacobi_rotate",
+                            &[
+                                KernelArg::Size(n),
+                                KernelArg::Size(p),
+                                KernelArg::Size(q),
+                                KernelArg::Buffer(&buffer_a),
+                                KernelArg::Buffer(&buffer_v),
+                            ],
+                            1,
+                            &[],
+                        )?;
+                    }
+
+                    device::download_into(&buffer_a, a.as_mut_slice())?;
+                    sweeps += 1;
+                }
+
+                device::download_into(&buffer_v, v.as_mut_slice())?;
+            }
+            Backend::Host => {
+                let mut sweeps = 0;
+                while !converged(&a, sweeps)? {
+                    for pair in pairs.clone() {
+                        host::jacobi_rotate(a.as_mut_slice(), v.as_mut_slice(), n, pair);
+                    }
+
+                    sweeps += 1;
+                }
+            }
+        }
+
+        // Ascending order, like the columns of the eigenvectors.
+        let mut order = (0..n).collect::<Vec<usize>>();
+        order.sort_by(|i, j| a[(*i, *i)].to_f64().total_cmp(&a[(*j, *j)].to_f64()));
+
+        let mut vectors = Vec2D::zeros(n, n);
+        for row in 0..n {
+            for (col, i) in order.iter().enumerate() {
+                vectors[(row, col)] = v[(row, *i)];
+            }
+        }
+
+        Ok(Eigen {
+            values: order.iter().map(|i| a[(*i, *i)]).collect(),
+            vectors: Matrix {
+                loader: self.loader.clone(),
+                A: vectors,
+            },
+        })
+    }
+
+    /// All eigenvalues in ascending order and their eigenvectors, with the Jacobi
+    /// method. Every rotation runs on the device.
+    ///
+    /// Only works for symmetric matrices, which isn't checked.
+    pub fn eigh(&self) -> Eigen<T> {
+        self.try_eigh().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `power_iteration`.
+    pub fn try_power_iteration(&self, k: usize, tolerance: T) -> Result<Eigen<T>, MatrixError> {
+        let loader = self.square_check()?;
+        let n = self.A.rows();
+
+        if k > n {
+            return Err(MatrixError::SizeMismatch { lhs: n, rhs: k });
+        }
+
+        // Unlikely to be orthogonal to any eigenvector.
+        let norm = (0..n)
+            .map(|i| (1.0 + i as f64 / n as f64).powi(2))
+            .sum::<f64>()
+            .sqrt();
+        let start = (0..n)
+            .map(|i| T::from_f64((1.0 + i as f64 / n as f64) / norm))
+            .collect::<Vec<T>>();
+
+        let mut values = Vec::with_capacity(k);
+        let mut vectors = Vec2D::zeros(n, k);
+
+        match &loader.backend {
+            Backend::OpenCl { queue, program, .. } => {
+                let buffer_a = device::upload(queue, self.A.as_slice())?;
+                let buffer_y = device::new_buffer::<T>(queue, n)?;
+                let buffer_result = device::new_buffer::<T>(queue, 2)?;
+                let acc_size = loader.kernel_type.get_type().acc_size();
+
+                for j in 0..k {
+                    let buffer_x = device::upload(queue, &start)?;
+
+                    let mut value = None;
+                    for i in 1..=MAX_ITERATIONS {
+                        device::group_op(
+                            &loader,
+                            queue,
+                            program,
+                            "gemv",
+                            &[
+                                KernelArg::Size(n),
+                                KernelArg::Scalar(T::from_f64(1.0)),
+                                KernelArg::Buffer(&buffer_a),
+                                KernelArg::Buffer(&buffer_x),
+                                KernelArg::Scalar(T::from_f64(0.0)),
+                                KernelArg::Buffer(&buffer_y),
+                            ],
+                            n,
+                            &[acc_size],
+                        )?;
+
+                        device::group_op(
+                            &loader,
+                            queue,
+                            program,
+                            "power_normalize",
+                            &[
+                                KernelArg::Size(n),
+                                KernelArg::Buffer(&buffer_x),
+                                KernelArg::Buffer(&buffer_y),
+                                KernelArg::Buffer(&buffer_result),
+                            ],
+                            1,
+                            &[acc_size, acc_size],
+                        )?;
+
+                        if i % CHECK_INTERVAL != 0 {
+                            continue;
+                        }
+
+                        let result = device::download(&buffer_result)?;
+                        if result[1].to_f64() <= tolerance.to_f64() {
+                            value = Some(result[0]);
+                            break;
+                        }
+                    }
+                    let value = value.ok_or(MatrixError::NotConverged {
+                        iterations: MAX_ITERATIONS,
+                    })?;
+
+                    // Deflate, so that the next eigenpair is the dominant one.
+                    if j + 1 < k {
+                        device::tiled_op(
+                            &loader,
+                            queue,
+                            program,
+                            "ger",
+                            &[
+                                KernelArg::Size(n),
+                                KernelArg::Size(n),
+                                KernelArg::Scalar(T::from_f64(-value.to_f64())),
+                                KernelArg::Buffer(&buffer_x),
+                                KernelArg::Buffer(&buffer_x),
+                                KernelArg::Buffer(&buffer_a),
+                            ],
+                            (1, n, n),
+                            0,
+                        )?;
+                    }
+
+                    for (row, x) in device::download(&buffer_x)?.into_iter().enumerate() {
+                        vectors[(row, j)] = x;
+                    }
+                    values.push(value);
+                }
+            }
+            Backend::Host => {
+                let mut a = self.A.clone();
+                let mut y = vec![T::from_f64(0.0); n];
+
+                for j in 0..k {
+                    let mut x = start.clone();
+
+                    let mut value = None;
+                    for _ in 0..MAX_ITERATIONS {
+                        host::gemv(
+                            (n, n),
+                            T::from_f64(1.0),
+                            (a.as_slice(), false),
+                            &x,
+                            T::from_f64(0.0),
+                            &mut y,
+                        );
+
+                        let (dot, diff) = host::power_normalize(&mut x, &y);
+                        if diff <= tolerance.to_f64() {
+                            value = Some(T::from_f64(dot));
+                            break;
+                        }
+                    }
+                    let value = value.ok_or(MatrixError::NotConverged {
+                        iterations: MAX_ITERATIONS,
+                    })?;
+
+                    if j + 1 < k {
+                        host::ger(T::from_f64(-value.to_f64()), &x, &x, a.as_mut_slice());
+                    }
+
+                    for (row, x) in x.into_iter().enumerate() {
+                        vectors[(row, j)] = x;
+                    }
+                    values.push(value);
+                }
+            }
+        }
+
+        Ok(Eigen {
+            values,
+            vectors: Matrix {
+                loader: self.loader.clone(),
+                A: vectors,
+            },
+        })
+    }
+
+    /// The k eigenvalues with the largest absolute values and their eigenvectors,
+    /// with the power iteration and deflation of a symmetric matrix. Cheaper than
+    /// `eigh` for a few eigenpairs.
+    ///
+    /// An eigenvector has converged once it moves less than tolerance in an
+    /// iteration. The matrix-vector products and normalizations run on the device.
+    pub fn power_iteration(&self, k: usize, tolerance: T) -> Eigen<T> {
+        self.try_power_iteration(k, tolerance)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `solve`.
+    pub fn try_solve(&self, b: &Matrix<Vec<T>>) -> Result<Matrix<Vec<T>>, MatrixError> {
+        self.try_lu()?.try_solve(b)
+    }
+
+    /// Solves self * x = b with an LU decomposition.
+    pub fn solve(&self, b: &Matrix<Vec<T>>) -> Matrix<Vec<T>> {
+        self.try_solve(b).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `det`.
+    pub fn try_det(&self) -> Result<T, MatrixError> {
+        Ok(self.try_lu()?.det())
+    }
+
+    /// Determinant with an LU decomposition.
+    pub fn det(&self) -> T {
+        self.try_det().unwrap_or_else(|e| panic!("{}", e))
+    }
+}
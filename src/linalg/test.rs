@@ -0,0 +1,704 @@
+#[cfg(test)]
+mod linalg_tests {
+    use log::info;
+    use std::time::Instant;
+
+    use crate::host::HostPrm;
+    use crate::linalg::Eigen;
+    use crate::vec2d::Vec2D;
+    use crate::vector::test::matrix_tests::{check, loader, setup, timer_end, values};
+    use crate::{Matrix, MatrixError};
+
+    // Gaussian elimination with partial pivoting in f64. Returns x and det(a).
+    fn naive_solve(mut a: Vec<f64>, mut b: Vec<f64>) -> (Vec<f64>, f64) {
+        let n = b.len();
+        let mut det = 1.0;
+
+        for k in 0..n {
+            let p = (k..n)
+                .max_by(|i, j| a[i * n + k].abs().total_cmp(&a[j * n + k].abs()))
+                .unwrap();
+
+            if p != k {
+                for j in 0..n {
+                    a.swap(k * n + j, p * n + j);
+                }
+                b.swap(k, p);
+                det = -det;
+            }
+            det *= a[k * n + k];
+
+            for i in k + 1..n {
+                let factor = a[i * n + k] / a[k * n + k];
+                for j in k..n {
+                    a[i * n + j] -= factor * a[k * n + j];
+                }
+                b[i] -= factor * b[k];
+            }
+        }
+
+        for k in (0..n).rev() {
+            let sum: f64 = (k + 1..n).map(|j| a[k * n + j] * b[j]).sum();
+            b[k] = (b[k] - sum) / a[k * n + k];
+        }
+
+        (b, det)
+    }
+
+    fn lu<T: HostPrm>(host: bool, tolerance: f64) {
+        setup();
+        let start = Instant::now();
+
+        let loader = loader::<T>(host);
+
+        let mut rng = oorandom::Rand32::new(21);
+        let mut random = |len: usize| {
+            (0..len)
+                .map(|_| T::from_f64(rng.rand_float() as f64 * 2.0 - 1.0))
+                .collect::<Vec<T>>()
+        };
+
+        for n in [1, 2, 5, 33, 100] {
+            let a = Matrix {
+                loader: Some(loader.clone()),
+                A: Vec2D::new(n, n, random(n * n)).unwrap(),
+            };
+            let b = Matrix {
+                loader: Some(loader.clone()),
+                A: random(n),
+            };
+
+            // P * A = L * U
+            let lu = a.lu();
+            let (l, u) = (values(lu.l().A.as_slice()), values(lu.u().A.as_slice()));
+            let av = values(a.A.as_slice());
+            for row in 0..n {
+                assert_eq!(l[row * n + row], 1.0);
+
+                for col in 0..n {
+                    let product: f64 = (0..n).map(|p| l[row * n + p] * u[p * n + col]).sum();
+                    check("lu", &[product], &[av[lu.perm[row] * n + col]], tolerance);
+                }
+            }
+
+            let (x, det) = naive_solve(av, values(&b.A));
+            info!("n: {}, det: {}", n, det);
+
+            check("solve", &values(&lu.solve(&b).A), &x, tolerance);
+            check("solve", &values(&a.solve(&b).A), &x, tolerance);
+
+            // The determinant can be tiny or huge, so it is compared relatively.
+            // (It overflows f32 for the largest matrix)
+            let out = a.det().to_f64();
+            assert!(
+                !T::from_f64(det).to_f64().is_finite()
+                    || (out - det).abs() <= tolerance * det.abs(),
+                "det: {} != {}",
+                out,
+                det
+            );
+        }
+
+        timer_end(start);
+    }
+
+    #[test]
+    fn lu_f32() {
+        lu::<f32>(false, 1e-3);
+    }
+
+    #[test]
+    fn lu_f64() {
+        lu::<f64>(false, 1e-9);
+    }
+
+    #[test]
+    fn lu_host_f32() {
+        lu::<f32>(true, 1e-3);
+    }
+
+    fn cholesky<T: HostPrm>(host: bool, tolerance: f64) {
+        setup();
+        let start = Instant::now();
+
+        let loader = loader::<T>(host);
+
+        let mut rng = oorandom::Rand32::new(22);
+
+        for n in [1, 2, 5, 33, 100] {
+            // M * M^T + n * I is symmetric positive definite.
+            let m = (0..n * n)
+                .map(|_| rng.rand_float() as f64 * 2.0 - 1.0)
+                .collect::<Vec<f64>>();
+            let mut av = vec![0.0; n * n];
+            for row in 0..n {
+                for col in 0..n {
+                    av[row * n + col] = (0..n).map(|p| m[row * n + p] * m[col * n + p]).sum();
+                }
+                av[row * n + row] += n as f64;
+            }
+
+            let a = Matrix {
+                loader: Some(loader.clone()),
+                A: Vec2D::new(n, n, av.iter().map(|a| T::from_f64(*a)).collect()).unwrap(),
+            };
+            let av = values(a.A.as_slice());
+            let b = Matrix {
+                loader: Some(loader.clone()),
+                A: (0..n)
+                    .map(|_| T::from_f64(rng.rand_float() as f64 * 2.0 - 1.0))
+                    .collect::<Vec<T>>(),
+            };
+
+            // A = L * L^T
+            let chol = a.cholesky();
+            let l = values(chol.l().A.as_slice());
+            for row in 0..n {
+                for col in 0..n {
+                    if col > row {
+                        assert_eq!(l[row * n + col], 0.0);
+                    }
+
+                    let product: f64 = (0..n).map(|p| l[row * n + p] * l[col * n + p]).sum();
+                    check("cholesky", &[product], &[av[row * n + col]], tolerance);
+                }
+            }
+
+            let (x, _) = naive_solve(av, values(&b.A));
+            check("cholesky_solve", &values(&chol.solve(&b).A), &x, tolerance);
+            check(
+                "cholesky_solve",
+                &values(&a.cholesky_solve(&b).A),
+                &x,
+                tolerance,
+            );
+        }
+
+        timer_end(start);
+    }
+
+    #[test]
+    fn cholesky_f32() {
+        cholesky::<f32>(false, 1e-4);
+    }
+
+    #[test]
+    fn cholesky_f64() {
+        cholesky::<f64>(false, 1e-10);
+    }
+
+    #[test]
+    fn cholesky_host_f32() {
+        cholesky::<f32>(true, 1e-4);
+    }
+
+    fn cholesky_errors(host: bool) {
+        let loader = loader::<f64>(host);
+        let new = |n: usize, a: Vec<f64>| Matrix {
+            loader: Some(loader.clone()),
+            A: Vec2D::new(n, n, a).unwrap(),
+        };
+
+        let err = new(2, vec![1.0, 2.0, 2.0, 1.0])
+            .try_cholesky()
+            .err()
+            .unwrap();
+        info!("{}", err);
+        assert!(matches!(err, MatrixError::NotPositiveDefinite { pivot: 1 }));
+
+        assert!(matches!(
+            new(2, vec![-1.0, 0.0, 0.0, 1.0]).try_cholesky_solve(&Matrix {
+                loader: Some(loader.clone()),
+                A: vec![1.0, 1.0],
+            }),
+            Err(MatrixError::NotPositiveDefinite { pivot: 0 })
+        ));
+        assert!(matches!(
+            new(2, vec![f64::NAN, 0.0, 0.0, 1.0]).try_cholesky(),
+            Err(MatrixError::NotPositiveDefinite { pivot: 0 })
+        ));
+
+        // Positive semi-definite isn't enough.
+        assert!(matches!(
+            new(2, vec![1.0, 1.0, 1.0, 1.0]).try_cholesky(),
+            Err(MatrixError::NotPositiveDefinite { pivot: 1 })
+        ));
+    }
+
+    #[test]
+    fn cholesky_errors_f64() {
+        cholesky_errors(false);
+    }
+
+    #[test]
+    fn cholesky_errors_host_f64() {
+        cholesky_errors(true);
+    }
+
+    fn qr<T: HostPrm>(host: bool, tolerance: f64) {
+        setup();
+        let start = Instant::now();
+
+        let loader = loader::<T>(host);
+
+        let mut rng = oorandom::Rand32::new(23);
+        let mut random = |len: usize| {
+            (0..len)
+                .map(|_| T::from_f64(rng.rand_float() as f64 * 2.0 - 1.0))
+                .collect::<Vec<T>>()
+        };
+
+        // Square, tall and wide matrices.
+        for (m, n) in [(1, 1), (2, 2), (5, 3), (33, 33), (100, 20), (3, 5)] {
+            let a = Matrix {
+                loader: Some(loader.clone()),
+                A: Vec2D::new(m, n, random(m * n)).unwrap(),
+            };
+            let av = values(a.A.as_slice());
+            let k = m.min(n);
+
+            // A = Q * R, where Q^T * Q = I
+            let qr = a.qr();
+            let (q, r) = (values(qr.q().A.as_slice()), values(qr.r().A.as_slice()));
+            for row in 0..m {
+                for col in 0..n {
+                    let product: f64 = (0..k).map(|p| q[row * k + p] * r[p * n + col]).sum();
+                    check("qr", &[product], &[av[row * n + col]], tolerance);
+                }
+            }
+            for i in 0..k {
+                for j in 0..k {
+                    let product: f64 = (0..m).map(|p| q[p * k + i] * q[p * k + j]).sum();
+                    check("q", &[product], &[(i == j) as usize as f64], tolerance);
+                }
+            }
+
+            if m < n {
+                continue;
+            }
+
+            // The normal equations A^T * A * x = A^T * b have the same solution.
+            let b = Matrix {
+                loader: Some(loader.clone()),
+                A: random(m),
+            };
+            let bv = values(&b.A);
+            let mut ata = vec![0.0; n * n];
+            let mut atb = vec![0.0; n];
+            for i in 0..n {
+                for j in 0..n {
+                    ata[i * n + j] = (0..m).map(|p| av[p * n + i] * av[p * n + j]).sum();
+                }
+                atb[i] = (0..m).map(|p| av[p * n + i] * bv[p]).sum();
+            }
+
+            let (x, _) = naive_solve(ata, atb);
+            check("lstsq", &values(&qr.solve(&b).A), &x, tolerance);
+            check("lstsq", &values(&a.lstsq(&b).A), &x, tolerance);
+        }
+
+        timer_end(start);
+    }
+
+    #[test]
+    fn qr_f32() {
+        qr::<f32>(false, 1e-3);
+    }
+
+    #[test]
+    fn qr_f64() {
+        qr::<f64>(false, 1e-9);
+    }
+
+    #[test]
+    fn qr_host_f32() {
+        qr::<f32>(true, 1e-3);
+    }
+
+    #[test]
+    fn qr_errors() {
+        let loader = loader::<f64>(true);
+        let new = |rows: usize, cols: usize, a: Vec<f64>| Matrix {
+            loader: Some(loader.clone()),
+            A: Vec2D::new(rows, cols, a).unwrap(),
+        };
+        let b = Matrix {
+            loader: Some(loader.clone()),
+            A: vec![1.0, 2.0, 3.0],
+        };
+
+        // The line through (0, 1), (1, 2) and (2, 3)
+        let x = new(3, 2, vec![1.0, 0.0, 1.0, 1.0, 1.0, 2.0]).lstsq(&b).A;
+        assert!((x[0] - 1.0).abs() < 1e-12 && (x[1] - 1.0).abs() < 1e-12);
+
+        let err = new(2, 3, vec![1.0; 6]).try_lstsq(&b).err().unwrap();
+        info!("{}", err);
+        assert!(matches!(
+            err,
+            MatrixError::Underdetermined { rows: 2, cols: 3 }
+        ));
+
+        // The second column is zero, so R is singular.
+        assert!(matches!(
+            new(3, 2, vec![1.0, 0.0, 2.0, 0.0, 3.0, 0.0]).try_lstsq(&b),
+            Err(MatrixError::Singular { pivot: 1 })
+        ));
+
+        // The second column only depends on the first one up to rounding, so R[1, 1]
+        // is tiny, but not zero.
+        let col0 = [0.1, 0.7, 1.3];
+        let col1 = col0.map(|a: f64| 3.0 * a / 7.0 * 7.0 / 3.0);
+        assert_ne!(col0, col1);
+        assert!(matches!(
+            new(
+                3,
+                2,
+                vec![col0[0], col1[0], col0[1], col1[1], col0[2], col1[2]]
+            )
+            .try_lstsq(&b),
+            Err(MatrixError::Singular { pivot: 1 })
+        ));
+        assert!(matches!(
+            new(2, 2, vec![1.0; 4]).try_lstsq(&b),
+            Err(MatrixError::SizeMismatch { lhs: 2, rhs: 3 })
+        ));
+    }
+
+    // a * b of row-major a (m x k) and b (k x n) in f64.
+    fn naive_matmul(a: &[f64], b: &[f64], (m, k, n): (usize, usize, usize)) -> Vec<f64> {
+        (0..m * n)
+            .map(|i| (0..k).map(|p| a[i / n * k + p] * b[p * n + i % n]).sum())
+            .collect()
+    }
+
+    fn identity(n: usize) -> Vec<f64> {
+        (0..n * n)
+            .map(|i| (i / n == i % n) as usize as f64)
+            .collect()
+    }
+
+    fn inverse<T: HostPrm>(host: bool, tolerance: f64) {
+        setup();
+        let start = Instant::now();
+
+        let loader = loader::<T>(host);
+
+        let mut rng = oorandom::Rand32::new(24);
+        let mut random = |len: usize| {
+            (0..len)
+                .map(|_| T::from_f64(rng.rand_float() as f64 * 2.0 - 1.0))
+                .collect::<Vec<T>>()
+        };
+
+        for n in [1, 2, 5, 33, 100] {
+            let a = Matrix {
+                loader: Some(loader.clone()),
+                A: Vec2D::new(n, n, random(n * n)).unwrap(),
+            };
+            let av = values(a.A.as_slice());
+
+            let inv = values(a.inverse(0.0).A.as_slice());
+            check(
+                "inverse",
+                &naive_matmul(&av, &inv, (n, n, n)),
+                &identity(n),
+                tolerance,
+            );
+
+            // Same as the inverse for a square matrix.
+            let pinv = values(a.pinv(0.0).A.as_slice());
+            check("pinv", &pinv, &inv, tolerance * n as f64);
+        }
+
+        // Tall and wide matrices, with A * pinv(A) * A = A
+        for (m, n) in [(5, 3), (100, 20), (3, 5)] {
+            let a = Matrix {
+                loader: Some(loader.clone()),
+                A: Vec2D::new(m, n, random(m * n)).unwrap(),
+            };
+            let av = values(a.A.as_slice());
+
+            let pinv = a.pinv(0.0);
+            assert_eq!(pinv.shape(), (n, m));
+            let pv = values(pinv.A.as_slice());
+
+            let apa = naive_matmul(&naive_matmul(&av, &pv, (m, n, m)), &av, (m, m, n));
+            check("pinv", &apa, &av, tolerance);
+
+            // pinv(A) is a left inverse of a tall and a right inverse of a wide A.
+            if m > n {
+                check(
+                    "pinv",
+                    &naive_matmul(&pv, &av, (n, m, n)),
+                    &identity(n),
+                    tolerance,
+                );
+            } else {
+                check(
+                    "pinv",
+                    &naive_matmul(&av, &pv, (m, n, m)),
+                    &identity(m),
+                    tolerance,
+                );
+            }
+        }
+
+        timer_end(start);
+    }
+
+    #[test]
+    fn inverse_f32() {
+        inverse::<f32>(false, 1e-3);
+    }
+
+    #[test]
+    fn inverse_f64() {
+        inverse::<f64>(false, 1e-9);
+    }
+
+    #[test]
+    fn inverse_host_f32() {
+        inverse::<f32>(true, 1e-3);
+    }
+
+    fn inverse_errors(host: bool) {
+        let loader = loader::<f64>(host);
+        let new = |rows: usize, cols: usize, a: Vec<f64>| Matrix {
+            loader: Some(loader.clone()),
+            A: Vec2D::new(rows, cols, a).unwrap(),
+        };
+
+        let err = new(2, 2, vec![1.0, 2.0, 2.0, 4.0])
+            .try_inverse(0.0)
+            .err()
+            .unwrap();
+        info!("{}", err);
+        assert!(matches!(err, MatrixError::Singular { pivot: 1 }));
+
+        // Only singular with a tolerance.
+        let nearly = new(2, 2, vec![1.0, 1.0, 1.0, 1.0 + 1e-10]);
+        assert!(nearly.try_inverse(0.0).is_ok());
+        assert!(matches!(
+            nearly.try_inverse(1e-8),
+            Err(MatrixError::Singular { pivot: 1 })
+        ));
+        assert!(matches!(
+            nearly.try_pinv(1e-8),
+            Err(MatrixError::Singular { pivot: 1 })
+        ));
+
+        // The second column is zero, so A has no full rank.
+        assert!(matches!(
+            new(3, 2, vec![1.0, 0.0, 2.0, 0.0, 3.0, 0.0]).try_pinv(0.0),
+            Err(MatrixError::Singular { pivot: 1 })
+        ));
+        assert!(matches!(
+            new(2, 3, vec![1.0; 6]).try_inverse(0.0),
+            Err(MatrixError::NotSquare { rows: 2, cols: 3 })
+        ));
+    }
+
+    #[test]
+    fn inverse_errors_f64() {
+        inverse_errors(false);
+    }
+
+    #[test]
+    fn inverse_errors_host_f64() {
+        inverse_errors(true);
+    }
+
+    // Symmetric matrix with the given eigenvalues and random eigenvectors.
+    fn symmetric(values: &[f64], seed: u64) -> Vec<f64> {
+        let n = values.len();
+        let loader = loader::<f64>(true);
+
+        let mut rng = oorandom::Rand32::new(seed);
+        let q = Matrix {
+            loader: Some(loader),
+            A: Vec2D::new(
+                n,
+                n,
+                (0..n * n)
+                    .map(|_| rng.rand_float() as f64 * 2.0 - 1.0)
+                    .collect(),
+            )
+            .unwrap(),
+        }
+        .qr()
+        .q();
+
+        (0..n * n)
+            .map(|i| {
+                (0..n)
+                    .map(|p| q.A[(i / n, p)] * values[p] * q.A[(i % n, p)])
+                    .sum()
+            })
+            .collect()
+    }
+
+    fn eigen<T: HostPrm>(host: bool, tolerance: f64) {
+        setup();
+        let start = Instant::now();
+
+        let loader = loader::<T>(host);
+
+        for n in [1, 2, 5, 33, 100] {
+            // Eigenvalues with both signs, which shrink by half, so the power
+            // iteration converges quickly.
+            let expected = (0..n)
+                .map(|i| 10.0 * (-0.5f64).powi(i as i32))
+                .collect::<Vec<f64>>();
+            let a = Matrix {
+                loader: Some(loader.clone()),
+                A: Vec2D::new(
+                    n,
+                    n,
+                    symmetric(&expected, n as u64)
+                        .into_iter()
+                        .map(T::from_f64)
+                        .collect(),
+                )
+                .unwrap(),
+            };
+            let av = values(a.A.as_slice());
+
+            // A * v = lambda * v for unit vectors v
+            let check_pairs = |name: &str, eigen: &Eigen<T>| {
+                let k = eigen.values.len();
+                let vectors = values(eigen.vectors.A.as_slice());
+
+                for (j, value) in values(&eigen.values).into_iter().enumerate() {
+                    let v = (0..n).map(|i| vectors[i * k + j]).collect::<Vec<f64>>();
+                    let av = naive_matmul(&av, &v, (n, n, 1));
+
+                    let lambda_v = v.iter().map(|v| value * v).collect::<Vec<f64>>();
+                    check(name, &av, &lambda_v, tolerance * 10.0);
+                    check(name, &[v.iter().map(|v| v * v).sum()], &[1.0], tolerance);
+                }
+            };
+
+            let eigh = a.eigh();
+            let mut sorted = expected.clone();
+            sorted.sort_by(f64::total_cmp);
+            check("eigh", &values(&eigh.values), &sorted, tolerance * 10.0);
+            check_pairs("eigh", &eigh);
+
+            let k = n.min(3);
+            let power = a.power_iteration(k, T::from_f64(tolerance));
+            check(
+                "power_iteration",
+                &values(&power.values),
+                &expected[..k],
+                tolerance * 10.0,
+            );
+            check_pairs("power_iteration", &power);
+        }
+
+        timer_end(start);
+    }
+
+    #[test]
+    fn eigen_f32() {
+        eigen::<f32>(false, 1e-4);
+    }
+
+    #[test]
+    fn eigen_f64() {
+        eigen::<f64>(false, 1e-9);
+    }
+
+    #[test]
+    fn eigen_host_f32() {
+        eigen::<f32>(true, 1e-4);
+    }
+
+    fn eigen_errors(host: bool) {
+        let loader = loader::<f64>(host);
+        let new = |rows: usize, cols: usize, a: Vec<f64>| Matrix {
+            loader: Some(loader.clone()),
+            A: Vec2D::new(rows, cols, a).unwrap(),
+        };
+
+        // The eigenvalues 1 and -1 are equally dominant.
+        let err = new(2, 2, vec![0.0, 1.0, 1.0, 0.0])
+            .try_power_iteration(1, 1e-9)
+            .err()
+            .unwrap();
+        info!("{}", err);
+        assert!(matches!(err, MatrixError::NotConverged { .. }));
+
+        // The matrix is zero after the deflation, so a * x = 0.
+        let power = new(2, 2, vec![2.0, 0.0, 0.0, 0.0]).power_iteration(2, 1e-9);
+        assert_eq!(power.values, vec![2.0, 0.0]);
+        assert_eq!(power.vectors.A.row(0)[0], 1.0);
+
+        // Already diagonal.
+        let eigh = new(2, 2, vec![2.0, 0.0, 0.0, 1.0]).eigh();
+        assert_eq!(eigh.values, vec![1.0, 2.0]);
+        assert_eq!(eigh.vectors.A.as_slice(), &[0.0, 1.0, 1.0, 0.0]);
+
+        assert!(matches!(
+            new(2, 2, vec![1.0; 4]).try_power_iteration(3, 1e-9),
+            Err(MatrixError::SizeMismatch { lhs: 2, rhs: 3 })
+        ));
+        assert!(matches!(
+            new(2, 3, vec![1.0; 6]).try_eigh(),
+            Err(MatrixError::NotSquare { rows: 2, cols: 3 })
+        ));
+    }
+
+    #[test]
+    fn eigen_errors_f64() {
+        eigen_errors(false);
+    }
+
+    #[test]
+    fn eigen_errors_host_f64() {
+        eigen_errors(true);
+    }
+
+    #[test]
+    fn lu_errors() {
+        let loader = loader::<f64>(true);
+        let new = |rows: usize, cols: usize, a: Vec<f64>| Matrix {
+            loader: Some(loader.clone()),
+            A: Vec2D::new(rows, cols, a).unwrap(),
+        };
+        let b = Matrix {
+            loader: Some(loader.clone()),
+            A: vec![1.0, 2.0],
+        };
+
+        let singular = new(2, 2, vec![1.0, 2.0, 2.0, 4.0]);
+        assert_eq!(singular.det(), 0.0);
+        assert!(matches!(
+            singular.try_solve(&b),
+            Err(MatrixError::Singular { pivot: 1 })
+        ));
+
+        // The last pivot is only rounded to zero, which det has to agree with.
+        let rounded = new(2, 2, vec![0.1, 0.3, 0.3, 0.9]);
+        assert_ne!(rounded.lu().u().A[(1, 1)], 0.0);
+        assert_eq!(rounded.det(), 0.0);
+        assert!(matches!(
+            rounded.try_solve(&b),
+            Err(MatrixError::Singular { pivot: 1 })
+        ));
+
+        // Needs a row swap, so the determinant changes its sign.
+        let swapped = new(2, 2, vec![0.0, 1.0, 1.0, 0.0]);
+        assert_eq!(swapped.det(), -1.0);
+        assert_eq!(swapped.lu().perm, vec![1, 0]);
+        assert_eq!(swapped.solve(&b).A, vec![2.0, 1.0]);
+
+        assert!(matches!(
+            new(2, 3, vec![1.0; 6]).try_lu(),
+            Err(MatrixError::NotSquare { rows: 2, cols: 3 })
+        ));
+        assert!(matches!(
+            new(3, 3, vec![1.0; 9]).try_solve(&b),
+            Err(MatrixError::SizeMismatch { lhs: 3, rhs: 2 })
+        ));
+    }
+}
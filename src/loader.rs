@@ -1,5 +1,5 @@
 use half::f16;
-use log::debug;
+use log::{debug, warn};
 use ocl::{
     builders::{BuildOpt, ProgramBuilder},
     enums::{DeviceInfo, DeviceInfoResult},
@@ -7,12 +7,19 @@ use ocl::{
     Context, Device, Platform, Program, Queue, SpatialDims,
 };
 use std::any::TypeId;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
 use std::ffi::OsStr;
+use std::fmt;
 use std::fs;
 use std::io;
 use std::path::Path;
-use std::sync::OnceLock;
+use std::sync::{Mutex, OnceLock};
+
+mod cache;
+pub mod test;
+
+use cache::ProgramCache;
 
 /// TypeMap is an internal type map which represents all possible types
 /// useable by the compute shaders.
@@ -32,7 +39,33 @@ impl TypeMap {
         }
     }
 
-    fn from_typeid(input: &TypeId) -> TypeMap {
+    // Type of accumulators in kernels. (half overflows too easily)
+    fn acc_c_str(&self) -> &str {
+        match self {
+            TypeMap::F16 => "float",
+            TypeMap::F32 => "float",
+            TypeMap::F64 => "double",
+        }
+    }
+
+    /// Size of an accumulator in kernels.
+    pub(crate) fn acc_size(&self) -> usize {
+        match self {
+            TypeMap::F16 | TypeMap::F32 => 4,
+            TypeMap::F64 => 8,
+        }
+    }
+
+    /// Machine epsilon of the type.
+    pub(crate) fn epsilon(&self) -> f64 {
+        match self {
+            TypeMap::F16 => 9.765625e-4,
+            TypeMap::F32 => f32::EPSILON as f64,
+            TypeMap::F64 => f64::EPSILON,
+        }
+    }
+
+    fn from_typeid(input: &TypeId) -> Option<TypeMap> {
         let map: HashMap<TypeId, TypeMap> = [
             (TypeId::of::<f16>(), TypeMap::F16),
             (TypeId::of::<f32>(), TypeMap::F32),
@@ -40,9 +73,205 @@ impl TypeMap {
         ]
         .into();
 
-        map.get(input)
-            .copied()
-            .expect("Missing from_typeid implementation (bug)")
+        map.get(input).copied()
+    }
+}
+
+/// Kernel sources which are compiled into the library.
+const BUILTIN_SOURCES: &[(&str, &str)] = &[
+    ("blas1.cl", include_str!("../kernels/blas1.cl")),
+    ("blas2.cl", include_str!("../kernels/blas2.cl")),
+    ("blas3.cl", include_str!("../kernels/blas3.cl")),
+    ("cholesky.cl", include_str!("../kernels/cholesky.cl")),
+    ("eigen.cl", include_str!("../kernels/eigen.cl")),
+    (
+        "gauss_jordan.cl",
+        include_str!("../kernels/gauss_jordan.cl"),
+    ),
+    ("lu.cl", include_str!("../kernels/lu.cl")),
+    ("permute.cl", include_str!("../kernels/permute.cl")),
+    ("qr.cl", include_str!("../kernels/qr.cl")),
+    (
+        "vec_arithmetic.cl",
+        include_str!("../kernels/vec_arithmetic.cl"),
+    ),
+    ("vec_unary.cl", include_str!("../kernels/vec_unary.cl")),
+    ("vec_binary.cl", include_str!("../kernels/vec_binary.cl")),
+    ("vec_norm.cl", include_str!("../kernels/vec_norm.cl")),
+    ("vec_reduce.cl", include_str!("../kernels/vec_reduce.cl")),
+    ("vec_stats.cl", include_str!("../kernels/vec_stats.cl")),
+];
+
+/// Headers which can be included by all kernel sources.
+const BUILTIN_HEADERS: &[(&str, &str)] = &[
+    ("helpers.h", include_str!("../kernels/helpers.h")),
+    ("reduce.h", include_str!("../kernels/reduce.h")),
+];
+
+// Replaces every `#include "<name>"` of a known header with its contents.
+//
+// Includes of unknown headers are left for the compiler. Recursive includes of a
+// header that is already being resolved are dropped.
+fn resolve_includes(
+    src: &str,
+    headers: &BTreeMap<String, String>,
+    stack: &mut Vec<String>,
+) -> String {
+    let mut resolved = String::with_capacity(src.len());
+
+    for line in src.lines() {
+        let include = line
+            .trim_start()
+            .strip_prefix("#include")
+            .map(str::trim)
+            .and_then(|a| a.strip_prefix('"'))
+            .and_then(|a| a.strip_suffix('"'));
+
+        match include.and_then(|a| headers.get_key_value(a)) {
+            Some((name, _)) if stack.contains(name) => {}
+            Some((name, header)) => {
+                stack.push(name.clone());
+                resolved.push_str(&resolve_includes(header, headers, stack));
+                stack.pop();
+            }
+            None => {
+                resolved.push_str(line);
+                resolved.push('\n');
+            }
+        }
+    }
+
+    resolved
+}
+
+// Queries the floating point configuration of a device for one type.
+//
+// Returns None if the device does not support the type at all.
+fn fp_config(dev: &Device, ty: TypeMap) -> Option<DeviceFpConfig> {
+    let config = match ty {
+        TypeMap::F16 => match dev.info(DeviceInfo::HalfFpConfig) {
+            Ok(DeviceInfoResult::HalfFpConfig(a)) => a,
+            _ => return None,
+        },
+        TypeMap::F32 => match dev.info(DeviceInfo::SingleFpConfig) {
+            Ok(DeviceInfoResult::SingleFpConfig(a)) => a,
+            _ => return None,
+        },
+        TypeMap::F64 => match dev.info(DeviceInfo::DoubleFpConfig) {
+            Ok(DeviceInfoResult::DoubleFpConfig(a)) => a,
+            _ => return None,
+        },
+    };
+
+    // Unsupported types report an empty configuration.
+    if config.is_empty() {
+        None
+    } else {
+        Some(config)
+    }
+}
+
+/// Capabilities of a single OpenCL device. (See list_devices)
+#[derive(Clone, Debug)]
+pub struct DeviceReport {
+    /// The index used by DeviceSelector::Index.
+    pub index: usize,
+    pub platform: Platform,
+    pub device: Device,
+
+    pub platform_name: String,
+    pub name: String,
+    pub vendor: String,
+    pub device_type: Option<DeviceType>,
+    pub version: String,
+
+    pub max_wg_size: usize,
+    pub global_mem_size: u64,
+    pub local_mem_size: u64,
+
+    /// None if the type is not supported by the device.
+    pub half_fp_config: Option<DeviceFpConfig>,
+    pub single_fp_config: Option<DeviceFpConfig>,
+    pub double_fp_config: Option<DeviceFpConfig>,
+
+    pub extensions: Vec<String>,
+}
+
+impl DeviceReport {
+    fn new(index: usize, platform: Platform, device: Device) -> DeviceReport {
+        let global_mem_size = match device.info(DeviceInfo::GlobalMemSize) {
+            Ok(DeviceInfoResult::GlobalMemSize(a)) => a,
+            _ => 0,
+        };
+
+        let local_mem_size = match device.info(DeviceInfo::LocalMemSize) {
+            Ok(DeviceInfoResult::LocalMemSize(a)) => a,
+            _ => 0,
+        };
+
+        let extensions = match device.info(DeviceInfo::Extensions) {
+            Ok(DeviceInfoResult::Extensions(a)) => {
+                a.split_whitespace().map(str::to_owned).collect()
+            }
+            _ => Vec::new(),
+        };
+
+        DeviceReport {
+            index,
+            platform,
+            device,
+
+            platform_name: platform.name().unwrap_or_default(),
+            name: device.name().unwrap_or_default(),
+            vendor: device.vendor().unwrap_or_default(),
+            device_type: KernelLoader::device_type(&device),
+            version: device.version().map(|a| a.to_string()).unwrap_or_default(),
+
+            max_wg_size: device.max_wg_size().unwrap_or(0),
+            global_mem_size,
+            local_mem_size,
+
+            half_fp_config: fp_config(&device, TypeMap::F16),
+            single_fp_config: fp_config(&device, TypeMap::F32),
+            double_fp_config: fp_config(&device, TypeMap::F64),
+
+            extensions,
+        }
+    }
+
+    pub fn has_extension(&self, name: &str) -> bool {
+        self.extensions.iter().any(|a| a == name)
+    }
+
+    /// Checks if kernels with the given type can be compiled for this device.
+    pub fn supports(&self, ty: TypeMap) -> bool {
+        match ty {
+            TypeMap::F16 => self.half_fp_config.is_some() && self.has_extension("cl_khr_fp16"),
+            TypeMap::F32 => self.single_fp_config.is_some(),
+            TypeMap::F64 => self.double_fp_config.is_some(),
+        }
+    }
+
+    /// All TypeMap variants which can be used with this device.
+    pub fn supported_types(&self) -> Vec<TypeMap> {
+        [TypeMap::F16, TypeMap::F32, TypeMap::F64]
+            .into_iter()
+            .filter(|a| self.supports(*a))
+            .collect()
+    }
+}
+
+/// Lists every device of every platform, in the order used by DeviceSelector::Index.
+///
+/// Returns an empty list if there is no OpenCL driver.
+pub fn list_devices() -> Vec<DeviceReport> {
+    match KernelLoader::list_platform_devices() {
+        Ok(a) => a
+            .into_iter()
+            .enumerate()
+            .map(|(idx, (pl, dev))| DeviceReport::new(idx, pl, dev))
+            .collect(),
+        Err(_) => Vec::new(),
     }
 }
 
@@ -55,32 +284,21 @@ impl KernelType {
     /// Checks the devices for their floating point configuration.
     /// (Rounding mode, and so on...)
     fn new(type_id: &TypeId, dev: &Device) -> Option<KernelType> {
-        let static_repr = TypeMap::from_typeid(type_id);
-
-        let fp_config = match static_repr {
-            TypeMap::F16 => match dev.info(DeviceInfo::HalfFpConfig).expect("no HalfFpConfig") {
-                DeviceInfoResult::HalfFpConfig(a) => a,
-                _ => return None,
-            },
-            TypeMap::F32 => match dev
-                .info(DeviceInfo::SingleFpConfig)
-                .expect("no SingleFpConfig")
-            {
-                DeviceInfoResult::SingleFpConfig(a) => a,
-                _ => return None,
-            },
-            TypeMap::F64 => match dev
-                .info(DeviceInfo::DoubleFpConfig)
-                .expect("no DoubleFpConfig")
-            {
-                DeviceInfoResult::DoubleFpConfig(a) => a,
-                _ => return None,
-            },
-        };
+        let static_repr = TypeMap::from_typeid(type_id)?;
 
         Some(KernelType {
             static_repr,
-            fp_config,
+            fp_config: fp_config(dev, static_repr)?,
+        })
+    }
+
+    /// The host backend always computes with IEEE 754 round to nearest.
+    fn host(type_id: &TypeId) -> Option<KernelType> {
+        Some(KernelType {
+            static_repr: TypeMap::from_typeid(type_id)?,
+            fp_config: DeviceFpConfig::DENORM
+                | DeviceFpConfig::INF_NAN
+                | DeviceFpConfig::ROUND_TO_NEAREST,
         })
     }
 
@@ -98,6 +316,12 @@ impl KernelType {
 struct KernelVariant<'a> {
     pub name: &'a [&'a str],
     pub operator: &'a [&'a str],
+    /// The identity element of the operator (if it has one), which is needed for
+    /// reductions. Exposed to the kernel as IDENTITY.
+    pub identity: &'a [Option<&'a str>],
+    /// Replaces the operator with its native_* builtin if unsafe_fast_math is
+    /// enabled. (Only float has native builtins)
+    pub native: &'a [bool],
     pub length: usize,
 }
 
@@ -108,20 +332,196 @@ pub enum KernelLoaderEr {
     SrcDirError,
     SrcReadError(io::Error),
     SrcDirEmpty,
+    NoDevice,
     PlatformError(ocl::error::Error),
+    DeviceQueryError(ocl::error::Error),
     QueueError(ocl::error::Error),
     ContextError(ocl::error::Error),
+    /// Contains the build log of the failed compilation.
+    CompileError(String),
+}
+
+impl fmt::Display for KernelLoaderEr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KernelLoaderEr::UnsupportedType => {
+                write!(f, "The type is not supported by the device or library")
+            }
+            KernelLoaderEr::SrcDirReadError(e) => write!(f, "Failed to read kernel dir: {}", e),
+            KernelLoaderEr::SrcDirError => write!(f, "Kernel dir path is not valid unicode"),
+            KernelLoaderEr::SrcReadError(e) => write!(f, "Failed to read kernel source: {}", e),
+            KernelLoaderEr::SrcDirEmpty => write!(f, "No kernel sources (.cl) found"),
+            KernelLoaderEr::NoDevice => write!(f, "No matching OpenCL device found"),
+            KernelLoaderEr::PlatformError(e) => write!(f, "OpenCL platform error: {}", e),
+            KernelLoaderEr::DeviceQueryError(e) => write!(f, "Failed to query device: {}", e),
+            KernelLoaderEr::QueueError(e) => write!(f, "Failed to create queue: {}", e),
+            KernelLoaderEr::ContextError(e) => write!(f, "Failed to create context: {}", e),
+            KernelLoaderEr::CompileError(log) => {
+                write!(f, "Source file failed to compile!:\n{}", log)
+            }
+        }
+    }
+}
+
+impl Error for KernelLoaderEr {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            KernelLoaderEr::SrcDirReadError(e) | KernelLoaderEr::SrcReadError(e) => Some(e),
+            KernelLoaderEr::PlatformError(e)
+            | KernelLoaderEr::DeviceQueryError(e)
+            | KernelLoaderEr::QueueError(e)
+            | KernelLoaderEr::ContextError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// The environment variable which overrides DeviceSelector::Auto in KernelLoader::new.
+pub const DEVICE_ENV: &str = "MATRIX_DEVICE";
+
+/// A user supplied filter for DeviceSelector::Custom.
+pub type DeviceFilter = Box<dyn Fn(&Platform, &Device) -> bool>;
+
+/// Decides which device a KernelLoader uses.
+///
+/// If more than one device matches, the one with the highest "performance" is picked.
+#[derive(Default)]
+pub enum DeviceSelector {
+    /// The fastest GPU, or the host backend if there is none. Can be overridden by
+    /// the MATRIX_DEVICE environment variable.
+    #[default]
+    Auto,
+    /// The fastest device of the given type(s). (CPU, GPU, ACCELERATOR, ALL, ...)
+    Type(DeviceType),
+    /// The fastest device whose platform or device name contains the string. (Ignores case)
+    Name(String),
+    /// The n-th device, counting through all devices of all platforms.
+    Index(usize),
+    /// The fastest device for which the closure returns true.
+    Custom(DeviceFilter),
+    /// Runs all kernels on the host. (See KernelLoader::new_host)
+    Host,
+}
+
+impl DeviceSelector {
+    /// Reads a selector from the MATRIX_DEVICE environment variable.
+    ///
+    /// Accepts "auto", "host", "cpu", "gpu", "accelerator", "all", a device index,
+    /// or "name:<substring>". Anything else is used as a name substring as well.
+    pub fn from_env() -> Option<DeviceSelector> {
+        let value = std::env::var(DEVICE_ENV).ok()?;
+
+        Some(Self::parse(&value))
+    }
+
+    // Only Auto is replaced by the environment, so that explicit selectors (e.g. of
+    // tests which iterate over all devices) always pick the same device.
+    fn or_env(self, env: Option<DeviceSelector>) -> DeviceSelector {
+        match (self, env) {
+            (DeviceSelector::Auto, Some(a)) => {
+                debug!("Device selector overridden by {}", DEVICE_ENV);
+                a
+            }
+            (selector, _) => selector,
+        }
+    }
+
+    fn parse(value: &str) -> DeviceSelector {
+        let value = value.trim();
+
+        if let Some(name) = value.strip_prefix("name:") {
+            return DeviceSelector::Name(name.to_owned());
+        }
+
+        if let Ok(idx) = value.parse::<usize>() {
+            return DeviceSelector::Index(idx);
+        }
+
+        match value.to_lowercase().as_str() {
+            "auto" => DeviceSelector::Auto,
+            "host" => DeviceSelector::Host,
+            "cpu" => DeviceSelector::Type(DeviceType::CPU),
+            "gpu" => DeviceSelector::Type(DeviceType::GPU),
+            "accelerator" => DeviceSelector::Type(DeviceType::ACCELERATOR),
+            "all" => DeviceSelector::Type(DeviceType::ALL),
+            _ => DeviceSelector::Name(value.to_owned()),
+        }
+    }
+}
+
+/// The place where all kernels of a KernelLoader are executed.
+pub enum Backend {
+    /// Compiled kernels on an OpenCL device.
+    OpenCl {
+        context: Context,
+        queue: Queue,
+        program: Program,
+        compiler: KernelCompiler,
+    },
+    /// Plain Rust implementations of all kernels. (See host.rs)
+    Host,
+}
+
+/// Compiles programs after the KernelLoader was created, with the same type, debug
+/// and compiler options as the built-in kernels. (Used for generated kernels)
+pub struct KernelCompiler {
+    device: Device,
+    src_global_prefix: String,
+    include_dirs: Vec<String>,
+    build_options: String,
+    headers: BTreeMap<String, String>,
+    programs: Mutex<HashMap<String, Program>>,
+}
+
+impl KernelCompiler {
+    /// Returns the program which was compiled for `key` or compiles `source` first.
+    ///
+    /// `source` may include every header which is known to the KernelLoader.
+    pub fn get_or_compile(
+        &self,
+        context: &Context,
+        key: &str,
+        source: impl FnOnce() -> String,
+    ) -> Result<Program, KernelLoaderEr> {
+        let mut programs = self.programs.lock().unwrap_or_else(|e| e.into_inner());
+
+        if let Some(program) = programs.get(key) {
+            return Ok(program.clone());
+        }
+
+        debug!("Compiling generated program {}", key);
+
+        let mut cs = resolve_includes(&source(), &self.headers, &mut Vec::new());
+        cs.insert_str(0, &self.src_global_prefix);
+
+        let mut prog_build = ProgramBuilder::new();
+
+        // Passed as separate options, because the paths can contain spaces.
+        for path in &self.include_dirs {
+            prog_build.bo(BuildOpt::CmplrInclDir { path: path.clone() });
+        }
+
+        let program = match prog_build
+            .source(cs)
+            .cmplr_opt(self.build_options.as_str())
+            .devices(self.device)
+            .build(context)
+        {
+            Ok(a) => a,
+            Err(e) => return Err(KernelLoaderEr::CompileError(e.to_string())),
+        };
+
+        programs.insert(key.to_string(), program.clone());
+        Ok(program)
+    }
 }
 
 /// A struct which acts as a context for the library.
 #[allow(dead_code)]
 pub struct KernelLoader {
-    pub global_work_size: SpatialDims,
     pub local_work_size: SpatialDims,
 
-    pub context: Context,
-    pub queue: Queue,
-    pub program: Program,
+    pub backend: Backend,
 
     pub kernel_type: KernelType,
 }
@@ -140,114 +540,258 @@ impl KernelLoader {
                 KernelVariant {
                     operator: &["+", "-", "*", "/"],
                     name: &["add", "sub", "mul", "div"],
+                    identity: &[Some("0"), None, Some("1"), None],
+                    native: &[false; 4],
                     length: 4,
                 },
             );
 
+            m.insert(
+                "vec_unary.cl",
+                KernelVariant {
+                    operator: &[
+                        "sqrt", "rsqrt", "exp", "exp2", "log", "log2", "sin", "cos", "tan", "tanh",
+                        "fabs", "floor", "ceil", "round", "sign", "recip",
+                    ],
+                    name: &[
+                        "math_sqrt",
+                        "math_rsqrt",
+                        "math_exp",
+                        "math_exp2",
+                        "math_log",
+                        "math_log2",
+                        "math_sin",
+                        "math_cos",
+                        "math_tan",
+                        "math_tanh",
+                        "math_abs",
+                        "math_floor",
+                        "math_ceil",
+                        "math_round",
+                        "math_sign",
+                        "math_recip",
+                    ],
+                    identity: &[None; 16],
+                    native: &[
+                        true, true, true, true, true, true, true, true, true, false, false, false,
+                        false, false, false, true,
+                    ],
+                    length: 16,
+                },
+            );
+
+            m.insert(
+                "vec_binary.cl",
+                KernelVariant {
+                    operator: &["pow", "atan2", "hypot", "fmod", "fmin", "fmax", "copysign"],
+                    name: &[
+                        "math_pow",
+                        "math_atan2",
+                        "math_hypot",
+                        "math_fmod",
+                        "math_fmin",
+                        "math_fmax",
+                        "math_copysign",
+                    ],
+                    identity: &[None; 7],
+                    native: &[false; 7],
+                    length: 7,
+                },
+            );
+
+            m.insert(
+                "vec_reduce.cl",
+                KernelVariant {
+                    operator: &["fmin", "fmax"],
+                    name: &["min", "max"],
+                    identity: &[Some("INFINITY"), Some("-INFINITY")],
+                    native: &[false; 2],
+                    length: 2,
+                },
+            );
+
+            m.insert(
+                "vec_norm.cl",
+                KernelVariant {
+                    operator: &["add_abs", "max_abs"],
+                    name: &["norm_l1", "norm_inf"],
+                    identity: &[Some("0"), Some("0")],
+                    native: &[false; 2],
+                    length: 2,
+                },
+            );
+
             m
         })
     }
 
-    // Checks for available devices
+    // Lists all devices of all platforms in a stable order.
     //
-    // Currently, goes through all devices and searches the one with
-    // the highest "performance" metrics.
-    //
-    // "performance" = MaxComputeUnits * MaxClockFreq * MaxWorkGroupSize
-    fn get_device() -> Result<(Platform, Device), KernelLoaderEr> {
-        let mut device_list: HashMap<u64, (Platform, Device)> = HashMap::new();
+    // Platforms which fail to list their devices are skipped.
+    fn list_platform_devices() -> Result<Vec<(Platform, Device)>, KernelLoaderEr> {
+        let mut device_list = Vec::new();
 
-        for pl in Platform::list() {
-            match Device::list(pl, Some(DeviceType::GPU)) {
+        // Platform::list panics if there is no OpenCL driver at all.
+        let platforms = match ocl::core::get_platform_ids() {
+            Ok(a) => a,
+            Err(e) => {
+                debug!("No OpenCL platform found: {}", e);
+                return Err(KernelLoaderEr::NoDevice);
+            }
+        };
+
+        for pl in platforms.into_iter().map(Platform::new) {
+            match Device::list_all(pl) {
                 Ok(a) => {
                     for dev in a {
-                        let mut pref: u64 = 1;
+                        device_list.push((pl, dev));
+                    }
+                }
+                Err(e) => debug!("Skipping platform: {}", e),
+            };
+        }
 
-                        if let DeviceInfoResult::MaxComputeUnits(temp) = dev
-                            .info(DeviceInfo::MaxComputeUnits)
-                            .expect("no MaxComputeUnits")
-                        {
-                            pref *= temp as u64;
-                        }
+        Ok(device_list)
+    }
 
-                        if let DeviceInfoResult::MaxClockFrequency(temp) = dev
-                            .info(DeviceInfo::MaxClockFrequency)
-                            .expect("no MaxClockFrequency")
-                        {
-                            pref *= temp as u64;
-                        }
+    // "performance" = MaxComputeUnits * MaxClockFreq * MaxWorkGroupSize
+    //
+    // Unavailable devices always have a performance of 0.
+    fn device_performance(dev: &Device) -> u64 {
+        let mut pref: u64 = 1;
 
-                        pref *= dev.max_wg_size().expect("no MaxWorkGroupSize") as u64;
-                        pref *= if dev.is_available().expect("no IsAvailable") {
-                            1
-                        } else {
-                            0
-                        };
+        if let Ok(DeviceInfoResult::MaxComputeUnits(temp)) = dev.info(DeviceInfo::MaxComputeUnits) {
+            pref = pref.saturating_mul(temp as u64);
+        }
 
-                        device_list.insert(pref, (pl, dev));
-                    }
-                }
-                Err(e) => return Err(KernelLoaderEr::PlatformError(e)),
+        if let Ok(DeviceInfoResult::MaxClockFrequency(temp)) =
+            dev.info(DeviceInfo::MaxClockFrequency)
+        {
+            pref = pref.saturating_mul(temp as u64);
+        }
+
+        if let Ok(temp) = dev.max_wg_size() {
+            pref = pref.saturating_mul(temp as u64);
+        }
+
+        if !dev.is_available().unwrap_or(false) {
+            pref = 0;
+        }
+
+        pref
+    }
+
+    fn device_type(dev: &Device) -> Option<DeviceType> {
+        match dev.info(DeviceInfo::Type) {
+            Ok(DeviceInfoResult::Type(a)) => Some(a),
+            _ => None,
+        }
+    }
+
+    // Checks for available devices
+    //
+    // Goes through all devices accepted by the selector and searches the one with
+    // the highest "performance" metrics. (See device_performance)
+    // Devices with equal performance are picked in the order they were listed.
+    fn get_device(selector: &DeviceSelector) -> Result<(Platform, Device), KernelLoaderEr> {
+        let device_list = Self::list_platform_devices()?;
+
+        if let DeviceSelector::Index(idx) = selector {
+            return match device_list.get(*idx) {
+                Some(a) => Ok(a.to_owned()),
+                None => Err(KernelLoaderEr::NoDevice),
             };
         }
 
-        let mut last_pref = 1;
-        for (pref, _) in &device_list {
-            if *pref > last_pref {
-                last_pref = *pref;
+        let mut best: Option<(u64, (Platform, Device))> = None;
+
+        for (pl, dev) in device_list {
+            let accepted = match selector {
+                DeviceSelector::Auto => {
+                    Self::device_type(&dev).is_some_and(|a| a.intersects(DeviceType::GPU))
+                }
+                DeviceSelector::Type(device_type) => {
+                    Self::device_type(&dev).is_some_and(|a| a.intersects(*device_type))
+                }
+                DeviceSelector::Name(name) => {
+                    let name = name.to_lowercase();
+
+                    [pl.name(), dev.name()]
+                        .into_iter()
+                        .any(|a| a.is_ok_and(|a| a.to_lowercase().contains(&name)))
+                }
+                DeviceSelector::Custom(filter) => filter(&pl, &dev),
+                DeviceSelector::Index(_) | DeviceSelector::Host => false,
+            };
+
+            if !accepted {
+                continue;
+            }
+
+            let pref = Self::device_performance(&dev);
+
+            if best.as_ref().is_none_or(|(last_pref, _)| pref > *last_pref) {
+                best = Some((pref, (pl, dev)));
             }
         }
 
-        Ok(device_list.get(&last_pref).unwrap().to_owned())
+        match best {
+            Some((_, a)) => Ok(a),
+            None => Err(KernelLoaderEr::NoDevice),
+        }
     }
 
-    /// Loads and compiles all kernels.
-    /// On success, returns a new KernelLoader. The object can then be used to create
-    /// matrices using the matrix_new macro.
+    /// The global work size needed to run one work-item per element.
     ///
-    /// Although currently matrix_new is a bit limited. It may be better to do it manually,
-    /// sometimes.
-    ///
-    /// * `kernel_dir` - The directory of all OpenCL C files (.cl).
-    /// * `unsafe_fast_math` - Enables -cl-finite-math-only, -cl-unsafe-math-optimizations and
-    /// -cl-mad-enable which is a bit faster but generally rounded and no bounds checks.
-    /// * `kernel_debug` - Enables all debug statements in all kernels.
-    /// * `threads` - The amount of threads that will use this context. (Indirectly scales work
-    /// size)
-    pub fn new<T: 'static>(
-        kernel_dir: &Path,
-        unsafe_fast_math: bool,
-        kernel_debug: bool,
-        threads: usize,
-    ) -> Result<Self, KernelLoaderEr> {
-        let mut src: HashMap<String, String> = HashMap::new();
+    /// Rounded up to a multiple of the local work size, so kernels have to check
+    /// their global id against the length.
+    pub fn global_work_size(&self, len: usize) -> SpatialDims {
+        let local = self.local_work_size.to_len().max(1);
 
-        let kernel_dir_str = match kernel_dir.to_str() {
-            Some(a) => a,
-            None => return Err(KernelLoaderEr::SrcDirError),
-        };
+        SpatialDims::from(len.max(1).div_ceil(local) * local)
+    }
 
-        // Get the fastest device that is available.
-        let (platfrom, device) = KernelLoader::get_device()?;
-        debug!("Picked OpenCL device: {}", device.name().unwrap());
+    /// Edge length of the square tiles of two-dimensional kernels.
+    ///
+    /// The largest power of two whose square still fits into one work-group.
+    pub fn tile_size(&self) -> usize {
+        let local = self.local_work_size.to_len().max(1);
+        let mut tile = 1;
 
-        let global_work_size =
-            SpatialDims::from(device.max_wg_size().expect("no MaxWorkGroupSize") / threads);
+        while (tile * 2) * (tile * 2) <= local {
+            tile *= 2;
+        }
+        tile
+    }
 
-        let local_work_size =
-            SpatialDims::from(device.max_wg_size().expect("no MaxWorkGroupSize") / threads);
+    /// Creates a KernelLoader which runs all kernels on the host.
+    ///
+    /// Nothing has to be compiled, so this always works as long as `T` is one of the
+    /// types in TypeMap.
+    pub fn new_host<T: 'static>() -> Result<Self, KernelLoaderEr> {
+        debug!("Using the host backend");
 
-        // Construct a dynamic representation of the primary type used in all generic kernels.
-        let kernel_type = match KernelType::new(&TypeId::of::<T>(), &device) {
+        let kernel_type = match KernelType::host(&TypeId::of::<T>()) {
             Some(a) => a,
             None => return Err(KernelLoaderEr::UnsupportedType),
         };
 
-        debug!(
-            "Device rounding mode with {:?}: {:?}",
-            kernel_type.get_type(),
-            kernel_type.get_fp_config()
-        );
+        Ok(KernelLoader {
+            local_work_size: SpatialDims::from(1),
+
+            backend: Backend::Host,
+
+            kernel_type,
+        })
+    }
+
+    // Reads all kernel sources (.cl) and headers (.h) of a directory.
+    fn read_kernel_dir(
+        kernel_dir: &Path,
+        src: &mut BTreeMap<String, String>,
+        headers: &mut BTreeMap<String, String>,
+    ) -> Result<(), KernelLoaderEr> {
+        let mut found = 0;
 
         // Read all file contents into a vec.
         let directory_entries = match fs::read_dir(kernel_dir) {
@@ -260,19 +804,24 @@ impl KernelLoader {
         for entry in directory_entries {
             if let Ok(entry) = entry {
                 let path = entry.path();
-                let e_type = entry.file_type().expect("get file type");
-                let e_extension = path
-                    .extension()
-                    .and_then(OsStr::to_str)
-                    .expect("get file extension");
-
-                let e_name = path
-                    .file_name()
-                    .expect("file name")
-                    .to_str()
-                    .expect("file name encoding");
-
-                if e_type.is_file() && e_extension == "cl" {
+                let e_type = match entry.file_type() {
+                    Ok(a) => a,
+                    Err(e) => return Err(KernelLoaderEr::SrcDirReadError(e)),
+                };
+
+                // Skips everything without a (unicode) name or extension.
+                let e_extension = path.extension().and_then(OsStr::to_str);
+                let Some(e_name) = path.file_name().and_then(OsStr::to_str) else {
+                    continue;
+                };
+
+                let target = match e_extension {
+                    Some("cl") => &mut *src,
+                    Some("h") => &mut *headers,
+                    _ => continue,
+                };
+
+                if e_type.is_file() {
                     let file = match fs::read_to_string(&path) {
                         Ok(a) => a,
                         Err(e) => {
@@ -280,45 +829,168 @@ impl KernelLoader {
                         }
                     };
 
-                    src.insert(e_name.to_owned(), file);
+                    if target.insert(e_name.to_owned(), file).is_some() {
+                        debug!("{} replaces a built-in file", e_name);
+                    }
+                    found += 1;
                 }
             }
         }
 
-        if src.is_empty() {
+        if found == 0 {
             return Err(KernelLoaderEr::SrcDirEmpty);
         }
 
+        Ok(())
+    }
+
+    /// Loads and compiles all built-in kernels.
+    /// On success, returns a new KernelLoader. The object can then be used to create
+    /// matrices using the matrix_new macro.
+    ///
+    /// Although currently matrix_new is a bit limited. It may be better to do it manually,
+    /// sometimes.
+    ///
+    /// The environment variable MATRIX_DEVICE overrides `selector`, if it is
+    /// DeviceSelector::Auto.
+    ///
+    /// With `unsafe_fast_math`, math functions use their native_* builtins for float.
+    ///
+    /// * `selector` - Decides on which device the kernels are run.
+    /// * `unsafe_fast_math` - Enables -cl-finite-math-only, -cl-unsafe-math-optimizations and
+    /// -cl-mad-enable which is a bit faster but generally rounded and no bounds checks.
+    /// * `kernel_debug` - Enables all debug statements in all kernels.
+    /// * `threads` - The amount of threads that will use this context. (Indirectly scales work
+    /// size)
+    pub fn new<T: 'static>(
+        selector: DeviceSelector,
+        unsafe_fast_math: bool,
+        kernel_debug: bool,
+        threads: usize,
+    ) -> Result<Self, KernelLoaderEr> {
+        Self::with_kernel_dirs::<T>(&[], None, selector, unsafe_fast_math, kernel_debug, threads)
+    }
+
+    /// Same as `new`, but additionally loads all OpenCL C files (.cl) and headers (.h)
+    /// of the given directories.
+    ///
+    /// Files with the same name as a built-in file replace it. Later directories
+    /// replace files of earlier ones.
+    ///
+    /// * `kernel_dirs` - The directories of all additional kernels.
+    /// * `cache_dir` - If set, compiled programs are cached in this directory and reused
+    /// as long as nothing that influences the compilation changed.
+    pub fn with_kernel_dirs<T: 'static>(
+        kernel_dirs: &[&Path],
+        cache_dir: Option<&Path>,
+        selector: DeviceSelector,
+        unsafe_fast_math: bool,
+        kernel_debug: bool,
+        threads: usize,
+    ) -> Result<Self, KernelLoaderEr> {
+        // Sorted, so that the sources always end up in the same order. (See ProgramCache)
+        let mut src: BTreeMap<String, String> = BUILTIN_SOURCES
+            .iter()
+            .map(|(name, file)| (name.to_string(), file.to_string()))
+            .collect();
+
+        let mut headers: BTreeMap<String, String> = BUILTIN_HEADERS
+            .iter()
+            .map(|(name, file)| (name.to_string(), file.to_string()))
+            .collect();
+
+        let mut include_dirs = Vec::new();
+
+        for kernel_dir in kernel_dirs {
+            Self::read_kernel_dir(kernel_dir, &mut src, &mut headers)?;
+
+            match kernel_dir.to_str() {
+                Some(a) => include_dirs.push(a),
+                None => return Err(KernelLoaderEr::SrcDirError),
+            };
+        }
+
         debug!("Found {} source files", src.len());
 
+        let selector = selector.or_env(DeviceSelector::from_env());
+
+        // Get the fastest device that is available.
+        let (platfrom, device) = match selector {
+            DeviceSelector::Host => return Self::new_host::<T>(),
+            _ => match KernelLoader::get_device(&selector) {
+                Ok(a) => a,
+                Err(KernelLoaderEr::NoDevice) if matches!(selector, DeviceSelector::Auto) => {
+                    warn!("No OpenCL device found, falling back to the host backend");
+                    return Self::new_host::<T>();
+                }
+                Err(e) => return Err(e),
+            },
+        };
+        debug!(
+            "Picked OpenCL device: {}",
+            device.name().unwrap_or_default()
+        );
+
+        let max_wg_size = match device.max_wg_size() {
+            Ok(a) => a,
+            Err(e) => return Err(KernelLoaderEr::DeviceQueryError(e)),
+        };
+
+        let local_work_size = SpatialDims::from((max_wg_size / threads).max(1));
+
+        // Construct a dynamic representation of the primary type used in all generic kernels.
+        let kernel_type = match KernelType::new(&TypeId::of::<T>(), &device) {
+            Some(a) => a,
+            None => return Err(KernelLoaderEr::UnsupportedType),
+        };
+
+        debug!(
+            "Device rounding mode with {:?}: {:?}",
+            kernel_type.get_type(),
+            kernel_type.get_fp_config()
+        );
+
         let mut prog_build = ProgramBuilder::new();
+        let mut build_options = String::new();
+        let mut sources = Vec::new();
 
-        // Should add a include directory. (FIX)
-        prog_build.bo(BuildOpt::CmplrInclDir {
-            path: kernel_dir_str.to_string(),
-        });
+        // Includes of unknown headers are still resolved by the compiler.
+        let include_dirs: Vec<String> = include_dirs.iter().map(|a| a.to_string()).collect();
+
+        for kernel_dir in &include_dirs {
+            prog_build.bo(BuildOpt::CmplrInclDir {
+                path: kernel_dir.clone(),
+            });
+        }
 
         // Dynamically adjust types of kernels.
         let mut src_global_prefix = String::new();
         src_global_prefix.push_str(
             format!(
-                "#undef TYPE_T\n#define TYPE_T {}\n",
-                kernel_type.get_type().c_str()
+                "#undef TYPE_T\n#define TYPE_T {}\n#undef ACC_T\n#define ACC_T {}\n",
+                kernel_type.get_type().c_str(),
+                kernel_type.get_type().acc_c_str()
             )
             .as_str(),
         );
 
         if unsafe_fast_math {
-            prog_build
-                .cmplr_opt("-cl-finite-math-only -cl-unsafe-math-optimizations -cl-mad-enable");
+            let opt = "-cl-finite-math-only -cl-unsafe-math-optimizations -cl-mad-enable";
+
+            prog_build.cmplr_opt(opt);
+            build_options.push_str(opt);
         }
 
+        // The native_* builtins are only defined for float.
+        let native = unsafe_fast_math && kernel_type.get_type() == TypeMap::F32;
+
         if kernel_debug {
             src_global_prefix.push_str(format!("#define DEBUG\n").as_str());
         }
 
         // Dynamically adjust the operator used in the kernel.
         for (idx, cs) in &mut src {
+            *cs = resolve_includes(cs, &headers, &mut Vec::new());
             cs.insert_str(0, &src_global_prefix);
 
             let current_variant = Self::get_variants().get(idx.as_str());
@@ -333,25 +1005,40 @@ impl KernelLoader {
                 for k in 0..var.length {
                     let mut cs_local = cs.clone();
 
+                    let operator = if var.native[k] && native {
+                        format!("native_{}", var.operator[k])
+                    } else {
+                        var.operator[k].to_string()
+                    };
+
                     cs_local
                         .insert_str(0, format!("#define KERNEL_NAME {}\n", var.name[k]).as_str());
-                    cs_local.insert_str(
-                        0,
-                        format!("#define OPERATOR {}\n", var.operator[k]).as_str(),
-                    );
+                    cs_local.insert_str(0, format!("#define OPERATOR {}\n", operator).as_str());
+
+                    if let Some(identity) = var.identity[k] {
+                        cs_local.insert_str(
+                            0,
+                            format!("#define IDENTITY ((TYPE_T){})\n", identity).as_str(),
+                        );
+                    }
 
                     // Is backwards because we insert at the top of the source file.
-                    cs_local.insert_str(0, "#undef KERNEL_NAME\n#undef OPERATOR\n");
+                    cs_local
+                        .insert_str(0, "#undef KERNEL_NAME\n#undef OPERATOR\n#undef IDENTITY\n");
 
-                    prog_build.source(cs_local.clone());
+                    sources.push(cs_local);
                 }
             } else {
                 debug!("Found generic kernel in {}", idx);
 
-                prog_build.source(cs.clone());
+                sources.push(cs.clone());
             }
         }
 
+        for cs in &sources {
+            prog_build.source(cs.clone());
+        }
+
         // Initialize the context and queue.
         let context = match Context::builder().platform(platfrom).build() {
             Ok(a) => a,
@@ -363,19 +1050,49 @@ impl KernelLoader {
             Err(e) => return Err(KernelLoaderEr::QueueError(e)),
         };
 
-        // Compile the kernel.
-        let program = match prog_build.devices(device).build(&context) {
-            Ok(a) => a,
-            Err(e) => panic!("\nSource file failed to compile!:{}", e),
+        let cache = cache_dir.map(|dir| {
+            ProgramCache::new(
+                dir,
+                &device,
+                kernel_type.get_type(),
+                &format!("{:?} {}", include_dirs, build_options),
+                &sources,
+            )
+        });
+
+        // Compile the kernel, if it isn't cached yet.
+        let program = match cache.as_ref().and_then(|a| a.load(&context, device)) {
+            Some(a) => a,
+            None => {
+                let program = match prog_build.devices(device).build(&context) {
+                    Ok(a) => a,
+                    Err(e) => return Err(KernelLoaderEr::CompileError(e.to_string())),
+                };
+
+                if let Some(cache) = &cache {
+                    cache.store(&program);
+                }
+
+                program
+            }
         };
 
         let loader = KernelLoader {
-            global_work_size,
             local_work_size,
 
-            context,
-            queue,
-            program,
+            backend: Backend::OpenCl {
+                context,
+                queue,
+                program,
+                compiler: KernelCompiler {
+                    device,
+                    src_global_prefix,
+                    include_dirs,
+                    build_options,
+                    headers,
+                    programs: Mutex::new(HashMap::new()),
+                },
+            },
 
             kernel_type,
         };
@@ -0,0 +1,145 @@
+use log::{debug, warn};
+use ocl::{
+    builders::ProgramBuilder,
+    enums::{DeviceInfo, DeviceInfoResult, ProgramInfo, ProgramInfoResult},
+    Context, Device, Program,
+};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::TypeMap;
+
+/// An on-disk cache for the binary of a compiled program.
+///
+/// Every entry is keyed by everything that influences the compilation. (Device, driver
+/// version, type, build options and all sources) If anything changes, the key
+/// changes and the program is compiled from source again.
+pub(crate) struct ProgramCache {
+    pub(crate) path: PathBuf,
+    pub(crate) key: String,
+}
+
+// FNV-1a, because the std hashers are not stable between Rust versions.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+
+    for b in bytes {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    hash
+}
+
+impl ProgramCache {
+    pub(crate) fn new(
+        dir: &Path,
+        device: &Device,
+        ty: TypeMap,
+        build_options: &str,
+        sources: &[String],
+    ) -> ProgramCache {
+        let driver_version = match device.info(DeviceInfo::DriverVersion) {
+            Ok(DeviceInfoResult::DriverVersion(a)) => a,
+            _ => String::new(),
+        };
+
+        let mut source_hash = 0;
+        for src in sources {
+            source_hash ^= fnv1a(src.as_bytes());
+            source_hash = source_hash.rotate_left(5);
+        }
+
+        let key = format!(
+            "{}\n{}\n{}\n{:?}\n{}\n{:016x}",
+            device.name().unwrap_or_default(),
+            device.vendor().unwrap_or_default(),
+            driver_version,
+            ty,
+            build_options,
+            source_hash
+        );
+
+        ProgramCache::with_key(dir, key)
+    }
+
+    pub(crate) fn with_key(dir: &Path, key: String) -> ProgramCache {
+        ProgramCache {
+            path: dir.join(format!("{:016x}.bin", fnv1a(key.as_bytes()))),
+            key,
+        }
+    }
+
+    /// Reads the cached binary, if there is one with a matching key.
+    pub(crate) fn read(&self) -> Option<Vec<u8>> {
+        let file = fs::read(&self.path).ok()?;
+
+        // Layout: <key> \0 <binary>
+        let split = file.iter().position(|a| *a == 0)?;
+
+        if &file[..split] != self.key.as_bytes() {
+            debug!("Cache key mismatch in {}", self.path.display());
+            return None;
+        }
+
+        Some(file[split + 1..].to_vec())
+    }
+
+    pub(crate) fn write(&self, binary: &[u8]) {
+        let mut file = Vec::with_capacity(self.key.len() + 1 + binary.len());
+        file.extend_from_slice(self.key.as_bytes());
+        file.push(0);
+        file.extend_from_slice(binary);
+
+        // Write to a temporary file first, so that no other process reads half an entry.
+        let tmp = self
+            .path
+            .with_extension(format!("tmp{}", std::process::id()));
+
+        let result = self
+            .path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&tmp, &file))
+            .and_then(|_| fs::rename(&tmp, &self.path));
+
+        match result {
+            Ok(_) => debug!("Cached program binary in {}", self.path.display()),
+            Err(e) => warn!("Failed to cache program binary: {}", e),
+        }
+    }
+
+    /// Builds the program from the cached binary.
+    ///
+    /// Returns None if there is no usable binary, which means that the program has
+    /// to be compiled from source.
+    pub(crate) fn load(&self, context: &Context, device: Device) -> Option<Program> {
+        let binary = self.read()?;
+        let binaries = [binary.as_slice()];
+
+        match ProgramBuilder::new()
+            .binaries(&binaries)
+            .devices(device)
+            .build(context)
+        {
+            Ok(a) => {
+                debug!("Loaded program binary from {}", self.path.display());
+                Some(a)
+            }
+            Err(e) => {
+                debug!("Cached program binary is unusable: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Stores the binary of a program that was compiled for a single device.
+    pub(crate) fn store(&self, program: &Program) {
+        match program.info(ProgramInfo::Binaries) {
+            Ok(ProgramInfoResult::Binaries(a)) if !a.is_empty() && !a[0].is_empty() => {
+                self.write(&a[0])
+            }
+            _ => warn!("Failed to read the program binary, nothing is cached"),
+        }
+    }
+}
@@ -0,0 +1,161 @@
+#[cfg(test)]
+mod loader_tests {
+    use log::info;
+    use ocl::flags::DeviceType;
+
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::path::Path;
+
+    use crate::loader::cache::{fnv1a, ProgramCache};
+    use crate::loader::{
+        list_devices, resolve_includes, DeviceSelector, KernelLoader, KernelLoaderEr, TypeMap,
+        BUILTIN_HEADERS, BUILTIN_SOURCES,
+    };
+    use crate::vector::test::matrix_tests::setup;
+
+    #[test]
+    fn device_report() {
+        setup();
+
+        for (idx, report) in list_devices().iter().enumerate() {
+            info!("{:#?}", report);
+            info!("Supported types: {:?}", report.supported_types());
+
+            assert_eq!(report.index, idx);
+
+            // Every supported type has to actually compile.
+            if report.supports(TypeMap::F32) {
+                let loader =
+                    KernelLoader::new::<f32>(DeviceSelector::Index(idx), false, false, 1).unwrap();
+                assert_eq!(loader.kernel_type.get_type(), TypeMap::F32);
+            }
+        }
+    }
+
+    #[test]
+    fn include_resolution() {
+        let headers: BTreeMap<String, String> = [
+            ("a.h".to_owned(), "int a;\n#include \"b.h\"\n".to_owned()),
+            ("b.h".to_owned(), "int b;\n#include \"a.h\"\n".to_owned()),
+        ]
+        .into();
+
+        let src = "#include \"a.h\"\n  #include \"unknown.h\"\nint c;";
+        let resolved = resolve_includes(src, &headers, &mut Vec::new());
+
+        assert_eq!(
+            resolved,
+            "int a;\nint b;\n  #include \"unknown.h\"\nint c;\n"
+        );
+
+        // All built-in sources have to be self-contained.
+        for (name, file) in BUILTIN_SOURCES {
+            let headers = BUILTIN_HEADERS
+                .iter()
+                .map(|(name, file)| (name.to_string(), file.to_string()))
+                .collect();
+
+            let resolved = resolve_includes(file, &headers, &mut Vec::new());
+            assert!(!resolved.contains("#include"), "{} includes a file", name);
+        }
+    }
+
+    #[test]
+    fn missing_kernel_dir() {
+        assert!(matches!(
+            KernelLoader::with_kernel_dirs::<f32>(
+                &[Path::new("./does_not_exist")],
+                None,
+                DeviceSelector::Host,
+                false,
+                false,
+                1,
+            ),
+            Err(KernelLoaderEr::SrcDirReadError(_))
+        ));
+    }
+
+    #[test]
+    fn program_cache() {
+        let dir = std::env::temp_dir().join(format!("matrix_cache_{}", std::process::id()));
+
+        let cache = ProgramCache::with_key(&dir, "device\nkey".to_owned());
+        assert_eq!(cache.read(), None);
+
+        cache.write(&[1, 0, 2, 3]);
+        assert_eq!(cache.read(), Some(vec![1, 0, 2, 3]));
+
+        // Same file name, but a different key is never loaded.
+        let other = ProgramCache {
+            path: cache.path.clone(),
+            key: "device\nother".to_owned(),
+        };
+        assert_eq!(other.read(), None);
+
+        assert_ne!(fnv1a(b"a"), fnv1a(b"b"));
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unsupported_type() {
+        assert!(matches!(
+            KernelLoader::new_host::<u32>(),
+            Err(KernelLoaderEr::UnsupportedType)
+        ));
+    }
+
+    #[test]
+    fn device_selector_parse() {
+        assert!(matches!(
+            DeviceSelector::parse("auto"),
+            DeviceSelector::Auto
+        ));
+        assert!(matches!(
+            DeviceSelector::parse("HOST"),
+            DeviceSelector::Host
+        ));
+        assert!(matches!(
+            DeviceSelector::parse(" 2 "),
+            DeviceSelector::Index(2)
+        ));
+        assert!(matches!(
+            DeviceSelector::parse("cpu"),
+            DeviceSelector::Type(DeviceType::CPU)
+        ));
+        assert!(matches!(
+            DeviceSelector::parse("accelerator"),
+            DeviceSelector::Type(DeviceType::ACCELERATOR)
+        ));
+        assert!(matches!(DeviceSelector::parse("name:gpu"), DeviceSelector::Name(a) if a == "gpu"));
+        assert!(
+            matches!(DeviceSelector::parse("Radeon"), DeviceSelector::Name(a) if a == "Radeon")
+        );
+    }
+
+    #[test]
+    fn device_selector_env() {
+        let env = || Some(DeviceSelector::Type(DeviceType::CPU));
+
+        assert!(matches!(
+            DeviceSelector::Auto.or_env(env()),
+            DeviceSelector::Type(DeviceType::CPU)
+        ));
+        assert!(matches!(
+            DeviceSelector::Auto.or_env(None),
+            DeviceSelector::Auto
+        ));
+
+        // Explicit selectors are never replaced.
+        assert!(matches!(
+            DeviceSelector::Index(1).or_env(env()),
+            DeviceSelector::Index(1)
+        ));
+        assert!(matches!(
+            DeviceSelector::Host.or_env(env()),
+            DeviceSelector::Host
+        ));
+    }
+}
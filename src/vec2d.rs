@@ -0,0 +1,406 @@
+use std::fmt::Debug;
+use std::ops;
+
+use crate::device;
+use crate::host::{self, HostPrm};
+use crate::loader::Backend;
+use crate::{Matrix, MatrixError};
+
+pub mod test;
+
+/// A two-dimensional matrix with contiguous row-major storage.
+///
+/// Element (row, col) is stored at `row * cols + col`, so the whole matrix can be
+/// uploaded as one buffer and used by the same kernels as `Vec<T>`.
+#[derive(Clone, PartialEq)]
+pub struct Vec2D<T> {
+    rows: usize,
+    cols: usize,
+    data: Vec<T>,
+}
+
+impl<T> Vec2D<T> {
+    /// Creates a rows x cols matrix from row-major data.
+    pub fn new(rows: usize, cols: usize, data: Vec<T>) -> Result<Vec2D<T>, MatrixError> {
+        let len = rows
+            .checked_mul(cols)
+            .ok_or(MatrixError::TooLarge { rows, cols })?;
+
+        if len != data.len() {
+            return Err(MatrixError::SizeMismatch {
+                lhs: len,
+                rhs: data.len(),
+            });
+        }
+
+        Ok(Vec2D { rows, cols, data })
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// (rows, cols)
+    pub fn shape(&self) -> (usize, usize) {
+        (self.rows, self.cols)
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// All elements in row-major order.
+    pub fn as_slice(&self) -> &[T] {
+        &self.data
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        &mut self.data
+    }
+
+    pub fn into_vec(self) -> Vec<T> {
+        self.data
+    }
+
+    pub fn row(&self, row: usize) -> &[T] {
+        &self.data[row * self.cols..(row + 1) * self.cols]
+    }
+
+    pub fn row_mut(&mut self, row: usize) -> &mut [T] {
+        &mut self.data[row * self.cols..(row + 1) * self.cols]
+    }
+}
+
+impl<T: Clone> Vec2D<T> {
+    /// Copies the matrix into nested vectors, one per row.
+    pub fn to_nested(&self) -> Vec<Vec<T>> {
+        self.data
+            .chunks(self.cols.max(1))
+            .map(|a| a.to_vec())
+            .collect()
+    }
+}
+
+impl<T: HostPrm> Vec2D<T> {
+    pub fn zeros(rows: usize, cols: usize) -> Vec2D<T> {
+        Vec2D {
+            rows,
+            cols,
+            data: vec![T::from_f64(0.0); rows * cols],
+        }
+    }
+
+    pub fn identity(size: usize) -> Vec2D<T> {
+        let mut out = Vec2D::zeros(size, size);
+
+        for i in 0..size {
+            out[(i, i)] = T::from_f64(1.0);
+        }
+        out
+    }
+}
+
+impl<T> TryFrom<Vec<Vec<T>>> for Vec2D<T> {
+    type Error = MatrixError;
+
+    /// Every inner vector is one row. All rows have to be the same length.
+    fn try_from(nested: Vec<Vec<T>>) -> Result<Vec2D<T>, MatrixError> {
+        let rows = nested.len();
+        let cols = nested.first().map_or(0, |a| a.len());
+        let mut data = Vec::with_capacity(rows * cols);
+
+        for (i, row) in nested.into_iter().enumerate() {
+            if row.len() != cols {
+                return Err(MatrixError::RaggedRows {
+                    row: i,
+                    len: row.len(),
+                    cols,
+                });
+            }
+            data.extend(row);
+        }
+
+        Ok(Vec2D { rows, cols, data })
+    }
+}
+
+impl<T> ops::Index<(usize, usize)> for Vec2D<T> {
+    type Output = T;
+
+    fn index(&self, (row, col): (usize, usize)) -> &T {
+        assert!(col < self.cols, "Column {} out of {}", col, self.cols);
+        &self.data[row * self.cols + col]
+    }
+}
+
+impl<T> ops::IndexMut<(usize, usize)> for Vec2D<T> {
+    fn index_mut(&mut self, (row, col): (usize, usize)) -> &mut T {
+        assert!(col < self.cols, "Column {} out of {}", col, self.cols);
+        &mut self.data[row * self.cols + col]
+    }
+}
+
+impl<T> Debug for Vec2D<T>
+where
+    T: std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.data.chunks(self.cols.max(1)))
+            .finish()
+    }
+}
+
+impl<T> Debug for Matrix<Vec2D<T>>
+where
+    T: std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.A)
+    }
+}
+
+impl<T> TryFrom<Matrix<Vec<Vec<T>>>> for Matrix<Vec2D<T>> {
+    type Error = MatrixError;
+
+    /// Converts the nested vectors of `matrix_new!(loader, T, 2)` into contiguous storage.
+    fn try_from(matrix: Matrix<Vec<Vec<T>>>) -> Result<Matrix<Vec2D<T>>, MatrixError> {
+        Ok(Matrix {
+            loader: matrix.loader,
+            A: Vec2D::try_from(matrix.A)?,
+        })
+    }
+}
+
+impl<T> Matrix<Vec2D<T>>
+where
+    T: HostPrm,
+{
+    pub fn rows(&self) -> usize {
+        self.A.rows()
+    }
+
+    pub fn cols(&self) -> usize {
+        self.A.cols()
+    }
+
+    /// (rows, cols)
+    pub fn shape(&self) -> (usize, usize) {
+        self.A.shape()
+    }
+
+    /// Copies the matrix into nested vectors, one per row.
+    pub fn to_nested(&self) -> Matrix<Vec<Vec<T>>> {
+        Matrix {
+            loader: self.loader.clone(),
+            A: self.A.to_nested(),
+        }
+    }
+
+    // Same shape, but different data.
+    fn with_data(&self, data: Vec<T>) -> Matrix<Vec2D<T>> {
+        Matrix {
+            loader: self.loader.clone(),
+            A: Vec2D {
+                rows: self.A.rows,
+                cols: self.A.cols,
+                data,
+            },
+        }
+    }
+
+    fn basic_op(&self, rhs: &Vec2D<T>, kernel_name: &str) -> Result<Matrix<Vec2D<T>>, MatrixError> {
+        // Check for common invocation errors.
+        if self.A.shape() != rhs.shape() {
+            return Err(MatrixError::ShapeMismatch {
+                lhs: self.A.shape(),
+                rhs: rhs.shape(),
+            });
+        }
+
+        if self.A.is_empty() {
+            return Err(MatrixError::Empty);
+        }
+
+        let loader = self.loader.clone().ok_or(MatrixError::NoLoader)?;
+
+        let data = match &loader.backend {
+            Backend::OpenCl { queue, program, .. } => {
+                let buffer_lhs = device::upload(queue, &self.A.data)?;
+                let buffer_rhs = device::upload(queue, &rhs.data)?;
+
+                device::download(&device::basic_op(
+                    &loader,
+                    queue,
+                    program,
+                    &buffer_lhs,
+                    &buffer_rhs,
+                    kernel_name,
+                )?)?
+            }
+            Backend::Host => host::basic_op(&self.A.data, &rhs.data, kernel_name),
+        };
+
+        Ok(self.with_data(data))
+    }
+
+    fn scalar_op(&self, rhs: T, kernel_name: &str) -> Result<Matrix<Vec2D<T>>, MatrixError> {
+        if self.A.is_empty() {
+            return Err(MatrixError::Empty);
+        }
+
+        let loader = self.loader.clone().ok_or(MatrixError::NoLoader)?;
+
+        let data = match &loader.backend {
+            Backend::OpenCl { queue, program, .. } => {
+                let buffer_lhs = device::upload(queue, &self.A.data)?;
+
+                device::download(&device::scalar_op(
+                    &loader,
+                    queue,
+                    program,
+                    &buffer_lhs,
+                    rhs,
+                    kernel_name,
+                )?)?
+            }
+            Backend::Host => host::scalar_op(&self.A.data, rhs, kernel_name),
+        };
+
+        Ok(self.with_data(data))
+    }
+
+    /// Fallible version of `transpose`.
+    pub fn try_transpose(&self) -> Result<Matrix<Vec2D<T>>, MatrixError> {
+        let (rows, cols) = self.A.shape();
+        device::permute_check(self.A.len(), &[rows, cols], &[1, 0])?;
+
+        let loader = self.loader.clone().ok_or(MatrixError::NoLoader)?;
+
+        let data = match &loader.backend {
+            Backend::OpenCl { queue, program, .. } => {
+                let buffer_lhs = device::upload(queue, &self.A.data)?;
+
+                device::download(&device::permute(
+                    &loader,
+                    queue,
+                    program,
+                    &buffer_lhs,
+                    &[rows, cols],
+                    &[1, 0],
+                )?)?
+            }
+            Backend::Host => host::permute(&self.A.data, &[rows, cols], &[1, 0]),
+        };
+
+        Ok(Matrix {
+            loader: self.loader.clone(),
+            A: Vec2D {
+                rows: cols,
+                cols: rows,
+                data,
+            },
+        })
+    }
+
+    /// Swaps rows and columns.
+    pub fn transpose(&self) -> Matrix<Vec2D<T>> {
+        self.try_transpose().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `&self + rhs`.
+    pub fn try_add(&self, rhs: &Matrix<Vec2D<T>>) -> Result<Matrix<Vec2D<T>>, MatrixError> {
+        self.basic_op(&rhs.A, "add")
+    }
+
+    /// Fallible version of `&self - rhs`.
+    pub fn try_sub(&self, rhs: &Matrix<Vec2D<T>>) -> Result<Matrix<Vec2D<T>>, MatrixError> {
+        self.basic_op(&rhs.A, "sub")
+    }
+
+    /// Fallible version of `&self * rhs`. (Elementwise, see matmul for the matrix product)
+    pub fn try_mul(&self, rhs: &Matrix<Vec2D<T>>) -> Result<Matrix<Vec2D<T>>, MatrixError> {
+        self.basic_op(&rhs.A, "mul")
+    }
+
+    /// Fallible version of `&self / rhs`.
+    pub fn try_div(&self, rhs: &Matrix<Vec2D<T>>) -> Result<Matrix<Vec2D<T>>, MatrixError> {
+        self.basic_op(&rhs.A, "div")
+    }
+}
+
+// Implementation of Matrix<Vec2D<T>> = Matrix<Vec2D<T>> @ Matrix<Vec2D<T>>
+macro_rules! vec2d_oper_impl {
+    ($op: ident, $kernel: ident) => {
+        impl<T> ops::$op<&Matrix<Vec2D<T>>> for &Matrix<Vec2D<T>>
+        where
+            T: HostPrm,
+        {
+            type Output = Matrix<Vec2D<T>>;
+
+            fn $kernel(self, rhs: &Matrix<Vec2D<T>>) -> Self::Output {
+                self.basic_op(&rhs.A, std::stringify!($kernel))
+                    .unwrap_or_else(|e| panic!("{}", e))
+            }
+        }
+    };
+}
+
+vec2d_oper_impl!(Add, add);
+vec2d_oper_impl!(Sub, sub);
+vec2d_oper_impl!(Mul, mul);
+vec2d_oper_impl!(Div, div);
+
+// Implementation of Matrix<Vec2D<T>> = Matrix<Vec2D<T>> @ T
+macro_rules! vec2d_scalar_oper_impl {
+    ($op: ident, $fn: ident, $kernel: ident) => {
+        impl<T> ops::$op<T> for &Matrix<Vec2D<T>>
+        where
+            T: HostPrm,
+        {
+            type Output = Matrix<Vec2D<T>>;
+
+            fn $fn(self, rhs: T) -> Self::Output {
+                self.scalar_op(rhs, std::stringify!($kernel))
+                    .unwrap_or_else(|e| panic!("{}", e))
+            }
+        }
+    };
+}
+
+vec2d_scalar_oper_impl!(Add, add, add_scalar);
+vec2d_scalar_oper_impl!(Sub, sub, sub_scalar);
+vec2d_scalar_oper_impl!(Mul, mul, mul_scalar);
+vec2d_scalar_oper_impl!(Div, div, div_scalar);
+
+// Implementation of Matrix<Vec2D<T>> = T @ Matrix<Vec2D<T>>
+macro_rules! vec2d_rscalar_oper_impl {
+    ($t: ty) => {
+        vec2d_rscalar_oper_impl!($t, Add, add, add_rscalar);
+        vec2d_rscalar_oper_impl!($t, Sub, sub, sub_rscalar);
+        vec2d_rscalar_oper_impl!($t, Mul, mul, mul_rscalar);
+        vec2d_rscalar_oper_impl!($t, Div, div, div_rscalar);
+    };
+    ($t: ty, $op: ident, $fn: ident, $kernel: ident) => {
+        impl ops::$op<&Matrix<Vec2D<$t>>> for $t {
+            type Output = Matrix<Vec2D<$t>>;
+
+            fn $fn(self, rhs: &Matrix<Vec2D<$t>>) -> Self::Output {
+                rhs.scalar_op(self, std::stringify!($kernel))
+                    .unwrap_or_else(|e| panic!("{}", e))
+            }
+        }
+    };
+}
+
+vec2d_rscalar_oper_impl!(half::f16);
+vec2d_rscalar_oper_impl!(f32);
+vec2d_rscalar_oper_impl!(f64);
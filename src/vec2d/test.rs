@@ -0,0 +1,163 @@
+#[cfg(test)]
+mod vec2d_tests {
+    use log::info;
+    use std::time::Instant;
+
+    use crate::host::HostPrm;
+    use crate::vec2d::Vec2D;
+    use crate::vector::test::matrix_tests::{loader, setup, timer_end};
+    use crate::{Matrix, MatrixError};
+    use matrix_macro::matrix_new;
+
+    fn vec2d_ops<T: HostPrm>(host: bool) {
+        setup();
+        let start = Instant::now();
+
+        let loader = loader::<T>(host);
+
+        let mut rng = oorandom::Rand32::new(7);
+        let mut random = |rows: usize, cols: usize| {
+            let mut nested = matrix_new!(loader.clone(), T, 2, rows);
+            for _ in 0..rows {
+                nested.A.push(
+                    (0..cols)
+                        .map(|_| T::from_f64((rng.rand_u32() % 10 + 1) as f64))
+                        .collect(),
+                );
+            }
+            Matrix::<Vec2D<T>>::try_from(nested).unwrap()
+        };
+
+        let (a, b) = (random(37, 53), random(37, 53));
+        assert_eq!((a.rows(), a.cols()), (37, 53));
+
+        let values = |a: &Matrix<Vec2D<T>>| {
+            a.A.as_slice()
+                .iter()
+                .map(|a| a.to_f64())
+                .collect::<Vec<f64>>()
+        };
+        let (av, bv) = (values(&a), values(&b));
+
+        let check = |out: &Matrix<Vec2D<T>>, f: fn(f64, f64) -> f64| {
+            assert_eq!(out.shape(), (37, 53));
+            for (i, out) in values(out).iter().enumerate() {
+                assert_eq!(*out, T::from_f64(f(av[i], bv[i])).to_f64());
+            }
+        };
+
+        check(&(&a + &b), |a, b| a + b);
+        check(&(&a - &b), |a, b| a - b);
+        check(&(&a * &b), |a, b| a * b);
+        check(&(&a / &b), |a, b| a / b);
+        check(&(&a * T::from_f64(2.0)), |a, _| a * 2.0);
+
+        // Row-major order.
+        assert_eq!(a.A[(1, 2)], a.A.as_slice()[53 + 2]);
+        assert_eq!(a.A.row(1)[2], a.A[(1, 2)]);
+        assert_eq!(a.to_nested().A[1][2], a.A[(1, 2)]);
+
+        // Same length, but a different shape.
+        let err = a.try_add(&random(53, 37)).unwrap_err();
+        info!("{}", err);
+        assert!(matches!(
+            err,
+            MatrixError::ShapeMismatch {
+                lhs: (37, 53),
+                rhs: (53, 37)
+            }
+        ));
+
+        timer_end(start);
+    }
+
+    fn vec2d_transpose<T: HostPrm>(host: bool) {
+        setup();
+        let start = Instant::now();
+
+        let loader = loader::<T>(host);
+
+        // Sizes which are multiples of the tile size and sizes which aren't.
+        for (rows, cols) in [(1, 1), (1, 100), (37, 53), (64, 64), (1000, 3)] {
+            let a = Matrix {
+                loader: Some(loader.clone()),
+                A: Vec2D::new(
+                    rows,
+                    cols,
+                    (0..rows * cols)
+                        .map(|i| T::from_f64((i % 2048) as f64))
+                        .collect(),
+                )
+                .unwrap(),
+            };
+
+            let t = a.transpose();
+            assert_eq!(t.shape(), (cols, rows));
+
+            for row in 0..rows {
+                for col in 0..cols {
+                    assert_eq!(t.A[(col, row)], a.A[(row, col)]);
+                }
+            }
+            assert_eq!(t.transpose().A, a.A);
+        }
+
+        timer_end(start);
+    }
+
+    #[test]
+    fn vec2d_transpose_f32() {
+        vec2d_transpose::<f32>(false);
+    }
+
+    #[test]
+    fn vec2d_transpose_host_f64() {
+        vec2d_transpose::<f64>(true);
+    }
+
+    #[test]
+    fn vec2d_ops_f32() {
+        vec2d_ops::<f32>(false);
+    }
+
+    #[test]
+    fn vec2d_ops_f64() {
+        vec2d_ops::<f64>(false);
+    }
+
+    #[test]
+    fn vec2d_ops_host_f32() {
+        vec2d_ops::<f32>(true);
+    }
+
+    #[test]
+    fn vec2d_from_nested() {
+        let a = Vec2D::try_from(vec![vec![1.0f32, 2.0, 3.0], vec![4.0, 5.0, 6.0]]).unwrap();
+
+        assert_eq!(a.shape(), (2, 3));
+        assert_eq!(a.as_slice(), &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        assert_eq!(a[(1, 0)], 4.0);
+        assert_eq!(Vec2D::new(2, 3, a.as_slice().to_vec()).unwrap(), a);
+        assert_eq!(Vec2D::<f32>::identity(2).as_slice(), &[1.0, 0.0, 0.0, 1.0]);
+
+        assert!(matches!(
+            Vec2D::try_from(vec![vec![1.0f32, 2.0], vec![3.0]]),
+            Err(MatrixError::RaggedRows {
+                row: 1,
+                len: 1,
+                cols: 2
+            })
+        ));
+        assert!(matches!(
+            Vec2D::new(2, 2, vec![1.0f32; 3]),
+            Err(MatrixError::SizeMismatch { lhs: 4, rhs: 3 })
+        ));
+        assert!(matches!(
+            Vec2D::new(usize::MAX, 2, vec![1.0f32; 3]),
+            Err(MatrixError::TooLarge {
+                rows: usize::MAX,
+                cols: 2
+            })
+        ));
+    }
+}
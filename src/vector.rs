@@ -1,10 +1,11 @@
-use std::convert::From;
 use std::fmt::Debug;
 use std::ops;
 
-use ocl::{Buffer, Kernel};
-
-use crate::Matrix;
+use crate::device::{self, KernelArg};
+use crate::expr::{BinaryOp, Expr};
+use crate::host::{self, HostPrm};
+use crate::loader::Backend;
+use crate::{Matrix, MatrixError};
 
 pub mod test;
 
@@ -23,199 +24,680 @@ where
 
 impl<T> Matrix<Vec<T>>
 where
-    T: ocl::OclPrm,
+    T: HostPrm,
 {
-    fn basic_op(&self, rhs: &Matrix<Vec<T>>, kernel_name: &str) -> Matrix<Vec<T>> {
+    fn basic_op(&self, rhs: &[T], kernel_name: &str) -> Result<Matrix<Vec<T>>, MatrixError> {
         // Check for common invocation errors.
-        debug_assert!(
-            self.A.len() == rhs.A.len(),
-            "Both operators have to have the same size! lhs:{} != rhs:{}",
-            self.A.len(),
-            rhs.A.len()
-        );
-        debug_assert!(self.A.len() != 0, "LHS is empty");
-        debug_assert!(rhs.A.len() != 0, "RHS is empty");
-
-        let buffer_size = self.A.len();
-        let loader = self.loader.clone().expect("Self loader not initalized!");
-
-        // Create all operator buffers.
-        let buffer_rhs = Buffer::<T>::builder()
-            .len(buffer_size)
-            .queue(loader.queue.clone())
-            .build()
-            .expect("buffer rhs");
-
-        let buffer_lhs = Buffer::<T>::builder()
-            .len(buffer_size)
-            .queue(loader.queue.clone())
-            .build()
-            .expect("buffer lhs");
-
-        let buffer_output = Buffer::<T>::builder()
-            .len(buffer_size)
-            .queue(loader.queue.clone())
-            .build()
-            .expect("buffer out");
-
-        // Write the Vec contents to their respective buffer.
-        buffer_rhs.write(&rhs.A).enq().expect("write to rhs");
-        buffer_lhs.write(&self.A).enq().expect("write to lhs");
-
-        // Run the kernel.
-        let kernel = match Kernel::builder()
-            .program(&loader.program)
-            .name(kernel_name)
-            .queue(loader.queue.clone())
-            .global_work_size(loader.global_work_size)
-            .local_work_size(loader.local_work_size)
-            .arg(&buffer_rhs)
-            .arg(buffer_rhs.len() as u64)
-            .arg(&buffer_lhs)
-            .arg(buffer_lhs.len() as u64)
-            .arg(&buffer_output)
-            .build()
-        {
-            Ok(a) => a,
-            Err(e) => {
-                panic!("{}", e);
+        if self.A.len() != rhs.len() {
+            return Err(MatrixError::SizeMismatch {
+                lhs: self.A.len(),
+                rhs: rhs.len(),
+            });
+        }
+
+        if self.A.is_empty() {
+            return Err(MatrixError::Empty);
+        }
+
+        let loader = self.loader.clone().ok_or(MatrixError::NoLoader)?;
+
+        let (queue, program) = match &loader.backend {
+            Backend::OpenCl { queue, program, .. } => (queue, program),
+            Backend::Host => {
+                return Ok(Matrix {
+                    loader: self.loader.clone(),
+                    A: host::basic_op(&self.A, rhs, kernel_name),
+                });
             }
         };
 
-        unsafe {
-            kernel.enq().expect("kernel enque");
+        let buffer_lhs = device::upload(queue, &self.A)?;
+        let buffer_rhs = device::upload(queue, rhs)?;
+
+        let buffer_output = device::basic_op(
+            &loader,
+            queue,
+            program,
+            &buffer_lhs,
+            &buffer_rhs,
+            kernel_name,
+        )?;
+
+        Ok(Matrix {
+            loader: self.loader.clone(),
+            A: device::download(&buffer_output)?,
+        })
+    }
+
+    fn unary_op(&self, kernel_name: &str) -> Result<Matrix<Vec<T>>, MatrixError> {
+        if self.A.is_empty() {
+            return Err(MatrixError::Empty);
         }
 
-        // Package the results.
-        let mut result = Matrix {
+        let loader = self.loader.clone().ok_or(MatrixError::NoLoader)?;
+
+        let (queue, program) = match &loader.backend {
+            Backend::OpenCl { queue, program, .. } => (queue, program),
+            Backend::Host => {
+                return Ok(Matrix {
+                    loader: self.loader.clone(),
+                    A: host::unary_op(&self.A, kernel_name),
+                });
+            }
+        };
+
+        let buffer_lhs = device::upload(queue, &self.A)?;
+
+        let buffer_output = device::unary_op(&loader, queue, program, &buffer_lhs, kernel_name)?;
+
+        Ok(Matrix {
             loader: self.loader.clone(),
-            A: vec![T::default(); buffer_size],
+            A: device::download(&buffer_output)?,
+        })
+    }
+
+    fn assign_op(&mut self, rhs: &[T], kernel_name: &str) -> Result<(), MatrixError> {
+        // Check for common invocation errors.
+        if self.A.len() != rhs.len() {
+            return Err(MatrixError::SizeMismatch {
+                lhs: self.A.len(),
+                rhs: rhs.len(),
+            });
+        }
+
+        if self.A.is_empty() {
+            return Err(MatrixError::Empty);
+        }
+
+        let loader = self.loader.clone().ok_or(MatrixError::NoLoader)?;
+
+        let (queue, program) = match &loader.backend {
+            Backend::OpenCl { queue, program, .. } => (queue, program),
+            Backend::Host => {
+                host::assign_op(&mut self.A, rhs, kernel_name);
+                return Ok(());
+            }
         };
 
-        buffer_output
-            .read(&mut result.A)
-            .len(buffer_size)
-            .enq()
-            .expect("read from out");
+        let buffer_lhs = device::upload(queue, &self.A)?;
+        let buffer_rhs = device::upload(queue, rhs)?;
 
-        result
+        // The result is written into buffer_lhs, so no output buffer is needed.
+        device::assign_op(
+            &loader,
+            queue,
+            program,
+            &buffer_lhs,
+            &buffer_rhs,
+            kernel_name,
+        )?;
+
+        device::download_into(&buffer_lhs, &mut self.A)
     }
 
-    fn down_op(&self, kernel_name: &str) -> Matrix<T> {
+    pub(crate) fn assign_scalar_op(
+        &mut self,
+        rhs: T,
+        kernel_name: &str,
+    ) -> Result<(), MatrixError> {
+        if self.A.is_empty() {
+            return Err(MatrixError::Empty);
+        }
+
+        let loader = self.loader.clone().ok_or(MatrixError::NoLoader)?;
+
+        let (queue, program) = match &loader.backend {
+            Backend::OpenCl { queue, program, .. } => (queue, program),
+            Backend::Host => {
+                host::assign_scalar_op(&mut self.A, rhs, kernel_name);
+                return Ok(());
+            }
+        };
+
+        let buffer_lhs = device::upload(queue, &self.A)?;
+
+        device::assign_scalar_op(&loader, queue, program, &buffer_lhs, rhs, kernel_name)?;
+
+        device::download_into(&buffer_lhs, &mut self.A)
+    }
+
+    fn down_op(&self, kernel_name: &str) -> Result<Matrix<T>, MatrixError> {
+        Ok(Matrix {
+            loader: self.loader.clone(),
+            A: T::from_f64(self.down_op_acc(kernel_name)?),
+        })
+    }
+
+    // Same as down_op, but returns the accumulator before it is narrowed to T.
+    fn down_op_acc(&self, kernel_name: &str) -> Result<f64, MatrixError> {
         // Check for common invocation errors.
-        debug_assert!(self.A.len() != 0, "RHS is empty");
-
-        let loader = self.loader.clone().expect("Self loader not initalized!");
-
-        // Create buffers and initialize them.
-        let buffer_rhs = Buffer::<T>::builder()
-            .len(self.A.len())
-            .queue(loader.queue.clone())
-            .build()
-            .expect("buffer rhs");
-
-        buffer_rhs.write(&self.A).enq().expect("write to rhs");
-
-        // Build and run the kernel.
-        let kernel = match Kernel::builder()
-            .program(&loader.program)
-            .name(kernel_name)
-            .queue(loader.queue.clone())
-            .global_work_size(loader.global_work_size)
-            .local_work_size(loader.local_work_size)
-            .arg(&buffer_rhs)
-            .arg(buffer_rhs.len() as u64)
-            .build()
-        {
-            Ok(a) => a,
-            Err(e) => {
-                panic!("{}", e);
+        if self.A.is_empty() {
+            return Err(MatrixError::Empty);
+        }
+
+        let loader = self.loader.clone().ok_or(MatrixError::NoLoader)?;
+
+        let (queue, program) = match &loader.backend {
+            Backend::OpenCl { queue, program, .. } => (queue, program),
+            Backend::Host => return Ok(host::down_op(&self.A, kernel_name)),
+        };
+
+        let buffer_rhs = device::upload(queue, &self.A)?;
+
+        device::reduce(&loader, queue, program, &buffer_rhs, kernel_name)
+    }
+
+    // Index picked by a `CAT(KERNEL_NAME, _arg_down)` kernel or by iamax_down of
+    // blas1.cl.
+    pub(crate) fn arg_down_op(&self, kernel_name: &str) -> Result<usize, MatrixError> {
+        if self.A.is_empty() {
+            return Err(MatrixError::Empty);
+        }
+
+        let loader = self.loader.clone().ok_or(MatrixError::NoLoader)?;
+
+        let index = match (&loader.backend, kernel_name) {
+            (Backend::OpenCl { queue, program, .. }, "iamax_down") => {
+                let buffer_rhs = device::upload(queue, &self.A)?;
+                device::iamax(&loader, queue, program, &buffer_rhs)?
+            }
+            (Backend::OpenCl { queue, program, .. }, _) => {
+                let buffer_rhs = device::upload(queue, &self.A)?;
+                device::reduce_arg(&loader, queue, program, &buffer_rhs, kernel_name)?
             }
+            (Backend::Host, "iamax_down") => host::iamax(&self.A),
+            (Backend::Host, _) => host::arg_down_op(&self.A, kernel_name),
         };
 
-        unsafe {
-            kernel.enq().expect("kernel enque");
+        // Only NaNs, so every element is as good as any other.
+        Ok(index.unwrap_or(0))
+    }
+
+    // Mean and population variance.
+    fn welford(&self) -> Result<(f64, f64), MatrixError> {
+        if self.A.is_empty() {
+            return Err(MatrixError::Empty);
         }
 
-        // Read the output from device memory.
-        let mut result: Vec<T> = vec![T::default(); 1];
+        let loader = self.loader.clone().ok_or(MatrixError::NoLoader)?;
+
+        match &loader.backend {
+            Backend::OpenCl { queue, program, .. } => {
+                let buffer_rhs = device::upload(queue, &self.A)?;
+                device::welford(&loader, queue, program, &buffer_rhs)
+            }
+            Backend::Host => Ok(host::welford(&self.A)),
+        }
+    }
 
-        buffer_rhs
-            .read(&mut result)
-            .len(1)
-            .enq()
-            .expect("read from out");
+    /// Fallible version of `&self + rhs`.
+    pub fn try_add(&self, rhs: &Matrix<Vec<T>>) -> Result<Matrix<Vec<T>>, MatrixError> {
+        self.basic_op(&rhs.A, "add")
+    }
+
+    /// Fallible version of `&self - rhs`.
+    pub fn try_sub(&self, rhs: &Matrix<Vec<T>>) -> Result<Matrix<Vec<T>>, MatrixError> {
+        self.basic_op(&rhs.A, "sub")
+    }
+
+    /// Fallible version of `&self * rhs`.
+    pub fn try_mul(&self, rhs: &Matrix<Vec<T>>) -> Result<Matrix<Vec<T>>, MatrixError> {
+        self.basic_op(&rhs.A, "mul")
+    }
+
+    /// Fallible version of `&self / rhs`.
+    pub fn try_div(&self, rhs: &Matrix<Vec<T>>) -> Result<Matrix<Vec<T>>, MatrixError> {
+        self.basic_op(&rhs.A, "div")
+    }
+
+    /// Fallible version of `self += rhs`.
+    pub fn try_add_assign(&mut self, rhs: &[T]) -> Result<(), MatrixError> {
+        self.assign_op(rhs, "add_assign")
+    }
+
+    /// Fallible version of `self -= rhs`.
+    pub fn try_sub_assign(&mut self, rhs: &[T]) -> Result<(), MatrixError> {
+        self.assign_op(rhs, "sub_assign")
+    }
+
+    /// Fallible version of `self *= rhs`.
+    pub fn try_mul_assign(&mut self, rhs: &[T]) -> Result<(), MatrixError> {
+        self.assign_op(rhs, "mul_assign")
+    }
 
-        Matrix {
+    /// Fallible version of `self /= rhs`.
+    pub fn try_div_assign(&mut self, rhs: &[T]) -> Result<(), MatrixError> {
+        self.assign_op(rhs, "div_assign")
+    }
+
+    /// Sums up all elements. (Fallible version of `Matrix<T> += &self`)
+    pub fn try_sum(&self) -> Result<Matrix<T>, MatrixError> {
+        self.down_op("add_down")
+    }
+
+    /// Multiplies all elements. (Fallible version of `Matrix<T> *= &self`)
+    pub fn try_product(&self) -> Result<Matrix<T>, MatrixError> {
+        self.down_op("mul_down")
+    }
+
+    /// Fallible version of `min`.
+    pub fn try_min(&self) -> Result<Matrix<T>, MatrixError> {
+        self.down_op("min_down")
+    }
+
+    /// Fallible version of `max`.
+    pub fn try_max(&self) -> Result<Matrix<T>, MatrixError> {
+        self.down_op("max_down")
+    }
+
+    /// Fallible version of `argmin`.
+    pub fn try_argmin(&self) -> Result<usize, MatrixError> {
+        self.arg_down_op("min_arg_down")
+    }
+
+    /// Fallible version of `argmax`.
+    pub fn try_argmax(&self) -> Result<usize, MatrixError> {
+        self.arg_down_op("max_arg_down")
+    }
+
+    /// Fallible version of `mean`.
+    pub fn try_mean(&self) -> Result<Matrix<T>, MatrixError> {
+        // Divides before narrowing, so the mean doesn't overflow with the sum.
+        let sum = self.down_op_acc("add_down")?;
+
+        Ok(Matrix {
             loader: self.loader.clone(),
-            A: result[0],
+            A: T::from_f64(sum / self.A.len() as f64),
+        })
+    }
+
+    // Variance with ddof degrees of freedom, before it is narrowed to T.
+    fn var_acc(&self, ddof: usize) -> Result<f64, MatrixError> {
+        let (_, var) = self.welford()?;
+        let len = self.A.len();
+
+        // Same as numpy, there is no variance without enough degrees of freedom.
+        if ddof < len {
+            Ok(var * len as f64 / (len - ddof) as f64)
+        } else {
+            Ok(f64::NAN)
         }
     }
+
+    /// Fallible version of `var`.
+    pub fn try_var(&self, ddof: usize) -> Result<Matrix<T>, MatrixError> {
+        Ok(Matrix {
+            loader: self.loader.clone(),
+            A: T::from_f64(self.var_acc(ddof)?),
+        })
+    }
+
+    /// Fallible version of `std`.
+    pub fn try_std(&self, ddof: usize) -> Result<Matrix<T>, MatrixError> {
+        // The variance can overflow T even if the standard deviation doesn't.
+        Ok(Matrix {
+            loader: self.loader.clone(),
+            A: T::from_f64(self.var_acc(ddof)?.sqrt()),
+        })
+    }
+
+    /// Fallible version of `norm_l1`.
+    pub fn try_norm_l1(&self) -> Result<Matrix<T>, MatrixError> {
+        self.down_op("norm_l1_down")
+    }
+
+    /// Fallible version of `norm_inf`.
+    pub fn try_norm_inf(&self) -> Result<Matrix<T>, MatrixError> {
+        self.down_op("norm_inf_down")
+    }
+
+    /// Fallible version of `norm_l2`.
+    pub fn try_norm_l2(&self) -> Result<Matrix<T>, MatrixError> {
+        let scale = self.try_norm_inf()?.A;
+
+        // Scaling by the largest element avoids overflows and underflows of the squares.
+        if scale.to_f64() == 0.0 || !scale.to_f64().is_finite() {
+            return Ok(Matrix {
+                loader: self.loader.clone(),
+                A: scale,
+            });
+        }
+
+        let loader = self.loader.clone().ok_or(MatrixError::NoLoader)?;
+
+        let sum = match &loader.backend {
+            Backend::OpenCl { queue, program, .. } => {
+                let buffer_rhs = device::upload(queue, &self.A)?;
+
+                device::fused_reduce(
+                    &loader,
+                    queue,
+                    program,
+                    "nrm2_down",
+                    &[KernelArg::Scalar(scale), KernelArg::Buffer(&buffer_rhs)],
+                    self.A.len(),
+                    "add_down_acc",
+                )?
+            }
+            Backend::Host => host::nrm2_scaled(scale, &self.A),
+        };
+
+        // The sum of the squares can overflow T, so it is only narrowed after the sqrt.
+        Ok(Matrix {
+            loader: self.loader.clone(),
+            A: T::from_f64(scale.to_f64() * sum.sqrt()),
+        })
+    }
+
+    /// Fallible version of `permute`.
+    pub fn try_permute(
+        &self,
+        shape: &[usize],
+        axes: &[usize],
+    ) -> Result<Matrix<Vec<T>>, MatrixError> {
+        device::permute_check(self.A.len(), shape, axes)?;
+
+        let loader = self.loader.clone().ok_or(MatrixError::NoLoader)?;
+
+        let data = match &loader.backend {
+            Backend::OpenCl { queue, program, .. } => {
+                let buffer_rhs = device::upload(queue, &self.A)?;
+                device::download(&device::permute(
+                    &loader,
+                    queue,
+                    program,
+                    &buffer_rhs,
+                    shape,
+                    axes,
+                )?)?
+            }
+            Backend::Host => host::permute(&self.A, shape, axes),
+        };
+
+        Ok(Matrix {
+            loader: self.loader.clone(),
+            A: data,
+        })
+    }
+
+    /// Sums up all elements.
+    pub fn sum(&self) -> Matrix<T> {
+        self.try_sum().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Multiplies all elements.
+    pub fn product(&self) -> Matrix<T> {
+        self.try_product().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Smallest element. NaNs are ignored.
+    pub fn min(&self) -> Matrix<T> {
+        self.try_min().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Largest element. NaNs are ignored.
+    pub fn max(&self) -> Matrix<T> {
+        self.try_max().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Index of the smallest element. (The first one, if there are several)
+    ///
+    /// NaNs are ignored. If all elements are NaN, the index is 0.
+    pub fn argmin(&self) -> usize {
+        self.try_argmin().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Index of the largest element. (The first one, if there are several)
+    ///
+    /// NaNs are ignored. If all elements are NaN, the index is 0.
+    pub fn argmax(&self) -> usize {
+        self.try_argmax().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Arithmetic mean of all elements.
+    pub fn mean(&self) -> Matrix<T> {
+        self.try_mean().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Variance of all elements, divided by `len - ddof`. (NaN if ddof >= len)
+    ///
+    /// Uses Welford's algorithm, so it is stable even if the mean is much larger than
+    /// the variance.
+    pub fn var(&self, ddof: usize) -> Matrix<T> {
+        self.try_var(ddof).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Standard deviation of all elements. (See var)
+    pub fn std(&self, ddof: usize) -> Matrix<T> {
+        self.try_std(ddof).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Sum of the absolute values of all elements.
+    pub fn norm_l1(&self) -> Matrix<T> {
+        self.try_norm_l1().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Euclidean norm of all elements.
+    pub fn norm_l2(&self) -> Matrix<T> {
+        self.try_norm_l2().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Largest absolute value of all elements.
+    pub fn norm_inf(&self) -> Matrix<T> {
+        self.try_norm_inf().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Reorders the axes of the row-major tensor of `shape`, so that axis i of the
+    /// result is axis axes[i] of self.
+    ///
+    /// ```ignore
+    /// // 2 x 3 x 4 -> 4 x 2 x 3
+    /// let b = a.permute(&[2, 3, 4], &[2, 0, 1]);
+    /// ```
+    pub fn permute(&self, shape: &[usize], axes: &[usize]) -> Matrix<Vec<T>> {
+        self.try_permute(shape, axes)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+// Implementation of Matrix<Vec<T>> = f(Matrix<Vec<T>>)
+macro_rules! unary_fn_impl {
+    ($fn: ident, $try_fn: ident, $kernel: ident, $doc: literal) => {
+        impl<T> Matrix<Vec<T>>
+        where
+            T: HostPrm,
+        {
+            #[doc = $doc]
+            pub fn $fn(&self) -> Matrix<Vec<T>> {
+                self.$try_fn().unwrap_or_else(|e| panic!("{}", e))
+            }
+
+            #[doc = std::concat!("Fallible version of `", std::stringify!($fn), "`.")]
+            pub fn $try_fn(&self) -> Result<Matrix<Vec<T>>, MatrixError> {
+                self.unary_op(std::stringify!($kernel))
+            }
+        }
+    };
 }
 
-// Implementation of Matrix<Vec<T>> = Matrix<Vec<T>> @ Matrix<Vec<T>>
+unary_fn_impl!(sqrt, try_sqrt, math_sqrt, "Square root of every element.");
+unary_fn_impl!(
+    rsqrt,
+    try_rsqrt,
+    math_rsqrt,
+    "Inverse square root of every element."
+);
+unary_fn_impl!(exp, try_exp, math_exp, "e to the power of every element.");
+unary_fn_impl!(
+    exp2,
+    try_exp2,
+    math_exp2,
+    "2 to the power of every element."
+);
+unary_fn_impl!(
+    log,
+    try_log,
+    math_log,
+    "Natural logarithm of every element."
+);
+unary_fn_impl!(
+    log2,
+    try_log2,
+    math_log2,
+    "Base 2 logarithm of every element."
+);
+unary_fn_impl!(sin, try_sin, math_sin, "Sine of every element.");
+unary_fn_impl!(cos, try_cos, math_cos, "Cosine of every element.");
+unary_fn_impl!(tan, try_tan, math_tan, "Tangent of every element.");
+unary_fn_impl!(
+    tanh,
+    try_tanh,
+    math_tanh,
+    "Hyperbolic tangent of every element."
+);
+unary_fn_impl!(abs, try_abs, math_abs, "Absolute value of every element.");
+unary_fn_impl!(floor, try_floor, math_floor, "Rounds every element down.");
+unary_fn_impl!(ceil, try_ceil, math_ceil, "Rounds every element up.");
+unary_fn_impl!(
+    round,
+    try_round,
+    math_round,
+    "Rounds every element to the nearest integer. (Halfway cases away from zero)"
+);
+unary_fn_impl!(
+    sign,
+    try_sign,
+    math_sign,
+    "1, -1 or 0 depending on the sign of every element. (0 for NaN)"
+);
+unary_fn_impl!(recip, try_recip, math_recip, "Reciprocal of every element.");
+
+// Implementation of Matrix<Vec<T>> = f(Matrix<Vec<T>>, Matrix<Vec<T>>)
+macro_rules! binary_fn_impl {
+    ($fn: ident, $try_fn: ident, $kernel: ident, $doc: literal) => {
+        impl<T> Matrix<Vec<T>>
+        where
+            T: HostPrm,
+        {
+            #[doc = $doc]
+            pub fn $fn(&self, rhs: &Matrix<Vec<T>>) -> Matrix<Vec<T>> {
+                self.$try_fn(rhs).unwrap_or_else(|e| panic!("{}", e))
+            }
+
+            #[doc = std::concat!("Fallible version of `", std::stringify!($fn), "`.")]
+            pub fn $try_fn(&self, rhs: &Matrix<Vec<T>>) -> Result<Matrix<Vec<T>>, MatrixError> {
+                self.basic_op(&rhs.A, std::stringify!($kernel))
+            }
+        }
+    };
+}
+
+binary_fn_impl!(pow, try_pow, math_pow, "Every element to the power of rhs.");
+binary_fn_impl!(atan2, try_atan2, math_atan2, "Arc tangent of self / rhs.");
+binary_fn_impl!(
+    hypot,
+    try_hypot,
+    math_hypot,
+    "Square root of self^2 + rhs^2, without overflows."
+);
+binary_fn_impl!(fmod, try_fmod, math_fmod, "Remainder of self / rhs.");
+binary_fn_impl!(
+    fmin,
+    try_fmin,
+    math_fmin,
+    "Elementwise minimum. (A NaN operand is ignored)"
+);
+binary_fn_impl!(
+    fmax,
+    try_fmax,
+    math_fmax,
+    "Elementwise maximum. (A NaN operand is ignored)"
+);
+binary_fn_impl!(
+    copysign,
+    try_copysign,
+    math_copysign,
+    "Magnitude of every element with the sign of rhs."
+);
+
+// Implementation of Expr = Matrix<Vec<T>> @ Matrix<Vec<T>> (See Expr)
 macro_rules! normal_oper_impl {
-    ($op: ident, $kernel: ident) => {
-        impl<T> ops::$op<&Matrix<Vec<T>>> for &Matrix<Vec<T>>
+    ($op: ident, $fn: ident, $variant: ident) => {
+        impl<'a, T> ops::$op<&'a Matrix<Vec<T>>> for &'a Matrix<Vec<T>>
         where
-            T: ocl::OclPrm,
+            T: HostPrm,
         {
-            type Output = Matrix<Vec<T>>;
+            type Output = Expr<'a, T>;
 
-            fn $kernel(self, rhs: &Matrix<Vec<T>>) -> Self::Output {
-                self.basic_op(rhs, std::stringify!($kernel))
+            fn $fn(self, rhs: &'a Matrix<Vec<T>>) -> Self::Output {
+                Expr::binary(Expr::Host(self), BinaryOp::$variant, Expr::Host(rhs))
             }
         }
     };
 }
 
-normal_oper_impl!(Add, add);
-normal_oper_impl!(Sub, sub);
-normal_oper_impl!(Mul, mul);
-normal_oper_impl!(Div, div);
+normal_oper_impl!(Add, add, Add);
+normal_oper_impl!(Sub, sub, Sub);
+normal_oper_impl!(Mul, mul, Mul);
+normal_oper_impl!(Div, div, Div);
 
-// Implementation of Matrix<Vec<T>> = Matrix<Vec<T>> @ T
+// Implementation of Expr = Matrix<Vec<T>> @ T
 macro_rules! scalar_oper_impl {
-    ($op: ident, $kernel: ident) => {
-        impl<T> ops::$op<T> for &Matrix<Vec<T>>
+    ($op: ident, $fn: ident, $variant: ident) => {
+        impl<'a, T> ops::$op<T> for &'a Matrix<Vec<T>>
         where
-            T: ocl::OclPrm,
+            T: HostPrm,
         {
-            type Output = Matrix<Vec<T>>;
+            type Output = Expr<'a, T>;
 
-            fn $kernel(self, rhs: T) -> Self::Output {
-                let temp = Matrix {
-                    loader: self.loader.clone(),
-                    A: vec![rhs; self.A.len()],
-                };
+            fn $fn(self, rhs: T) -> Self::Output {
+                Expr::binary(Expr::Host(self), BinaryOp::$variant, Expr::Scalar(rhs))
+            }
+        }
+    };
+}
 
-                self.basic_op(&temp, std::stringify!($kernel))
+scalar_oper_impl!(Add, add, Add);
+scalar_oper_impl!(Sub, sub, Sub);
+scalar_oper_impl!(Mul, mul, Mul);
+scalar_oper_impl!(Div, div, Div);
+
+// Implementation of Expr = T @ Matrix<Vec<T>>
+// (Has to be implemented for every type, because T is foreign)
+macro_rules! rscalar_oper_impl {
+    ($t: ty) => {
+        rscalar_oper_impl!($t, Add, add, Add);
+        rscalar_oper_impl!($t, Sub, sub, Sub);
+        rscalar_oper_impl!($t, Mul, mul, Mul);
+        rscalar_oper_impl!($t, Div, div, Div);
+    };
+    ($t: ty, $op: ident, $fn: ident, $variant: ident) => {
+        impl<'a> ops::$op<&'a Matrix<Vec<$t>>> for $t {
+            type Output = Expr<'a, $t>;
+
+            fn $fn(self, rhs: &'a Matrix<Vec<$t>>) -> Self::Output {
+                Expr::binary(Expr::Scalar(self), BinaryOp::$variant, Expr::Host(rhs))
             }
         }
     };
 }
 
-scalar_oper_impl!(Add, add);
-scalar_oper_impl!(Sub, sub);
-scalar_oper_impl!(Mul, mul);
-scalar_oper_impl!(Div, div);
+rscalar_oper_impl!(half::f16);
+rscalar_oper_impl!(f32);
+rscalar_oper_impl!(f64);
 
 // Implementation of Matrix<Vec<T>> = Matrix<Vec<T>> @ [T]
 macro_rules! normal_oper_ext_impl {
     ($op: ident, $kernel: ident) => {
         impl<T> ops::$op<&[T]> for &Matrix<Vec<T>>
         where
-            T: ocl::OclPrm,
+            T: HostPrm,
         {
             type Output = Matrix<Vec<T>>;
 
             fn $kernel(self, rhs: &[T]) -> Self::Output {
-                let temp = Matrix {
-                    loader: self.loader.clone(),
-                    A: Vec::from(rhs),
-                };
-
-                self.basic_op(&temp, std::stringify!($kernel))
+                self.basic_op(rhs, std::stringify!($kernel))
+                    .unwrap_or_else(|e| panic!("{}", e))
             }
         }
     };
@@ -226,15 +708,58 @@ normal_oper_ext_impl!(Sub, sub);
 normal_oper_ext_impl!(Mul, mul);
 normal_oper_ext_impl!(Div, div);
 
+// Implementation of Matrix<Vec<T>> @= Matrix<Vec<T>>, Matrix<Vec<T>> @= [T] and
+// Matrix<Vec<T>> @= T
+macro_rules! assign_oper_impl {
+    ($op: ident, $opfn: ident, $kernel: ident) => {
+        impl<T> ops::$op<&Matrix<Vec<T>>> for Matrix<Vec<T>>
+        where
+            T: HostPrm,
+        {
+            fn $opfn(&mut self, rhs: &Matrix<Vec<T>>) {
+                self.assign_op(&rhs.A, std::stringify!($kernel))
+                    .unwrap_or_else(|e| panic!("{}", e));
+            }
+        }
+
+        impl<T> ops::$op<&[T]> for Matrix<Vec<T>>
+        where
+            T: HostPrm,
+        {
+            fn $opfn(&mut self, rhs: &[T]) {
+                self.assign_op(rhs, std::stringify!($kernel))
+                    .unwrap_or_else(|e| panic!("{}", e));
+            }
+        }
+
+        impl<T> ops::$op<T> for Matrix<Vec<T>>
+        where
+            T: HostPrm,
+        {
+            fn $opfn(&mut self, rhs: T) {
+                self.assign_scalar_op(rhs, std::concat!(std::stringify!($kernel), "_scalar"))
+                    .unwrap_or_else(|e| panic!("{}", e));
+            }
+        }
+    };
+}
+
+assign_oper_impl!(AddAssign, add_assign, add_assign);
+assign_oper_impl!(SubAssign, sub_assign, sub_assign);
+assign_oper_impl!(MulAssign, mul_assign, mul_assign);
+assign_oper_impl!(DivAssign, div_assign, div_assign);
+
 // Implementation of Matrix<T> @= Matrix<Vec<T>>
 macro_rules! assign_down_scalar_impl {
     ($op: ident, $opfn: ident, $kernel: ident) => {
         impl<T> ops::$op<&Matrix<Vec<T>>> for Matrix<T>
         where
-            T: ocl::OclPrm,
+            T: HostPrm,
         {
             fn $opfn(&mut self, rhs: &Matrix<Vec<T>>) {
-                *self = rhs.down_op(std::stringify!($kernel));
+                *self = rhs
+                    .down_op(std::stringify!($kernel))
+                    .unwrap_or_else(|e| panic!("{}", e));
             }
         }
     };
@@ -1,16 +1,16 @@
 #[cfg(test)]
-mod matrix_tests {
+pub(crate) mod matrix_tests {
     use half::f16;
     use log::{info, warn};
     use oorandom;
     use simplelog::{ColorChoice, Config, LevelFilter, TermLogger, TerminalMode};
     use std::ops::*;
-    use std::path::PathBuf;
     use std::sync::Arc;
     use std::time::Instant;
 
-    use crate::loader::KernelLoader;
-    use crate::Matrix;
+    use crate::host::HostPrm;
+    use crate::loader::{DeviceSelector, KernelLoader};
+    use crate::{Matrix, MatrixError};
     use matrix_macro::matrix_new;
 
     const TXTSHIFT: &str = "\x1b[100G";
@@ -33,7 +33,37 @@ mod matrix_tests {
         println!("");
     }
 
-    fn vec_ops<T, const VAL_LEN: usize>()
+    // A loader for T on the host backend or on the default device.
+    pub fn loader<T: HostPrm>(host: bool) -> Arc<KernelLoader> {
+        Arc::new(
+            if host {
+                KernelLoader::new_host::<T>()
+            } else {
+                KernelLoader::new::<T>(DeviceSelector::Auto, false, false, 16)
+            }
+            .unwrap(),
+        )
+    }
+
+    pub fn values<T: HostPrm>(a: &[T]) -> Vec<f64> {
+        a.iter().map(|a| a.to_f64()).collect()
+    }
+
+    // Compares every element relative to the magnitude of the expected one.
+    pub fn check(name: &str, out: &[f64], expected: &[f64], tolerance: f64) {
+        assert_eq!(out.len(), expected.len());
+        for (out, expected) in out.iter().zip(expected) {
+            assert!(
+                (out - expected).abs() <= tolerance * expected.abs().max(1.0),
+                "{}: {} != {}",
+                name,
+                out,
+                expected
+            );
+        }
+    }
+
+    fn vec_ops<T, const VAL_LEN: usize>(host: bool)
     where
         T: Add<Output = T>
             + AddAssign
@@ -41,16 +71,14 @@ mod matrix_tests {
             + Mul<Output = T>
             + MulAssign
             + Div<Output = T>
-            + ocl::OclPrm
+            + HostPrm
             + std::convert::From<u8>
             + std::convert::Into<f64>,
     {
         setup();
         let start = Instant::now();
 
-        let loader = Arc::new(
-            KernelLoader::new::<T>(&PathBuf::from("./kernels"), false, false, 16).unwrap(),
-        );
+        let loader = loader::<T>(host);
 
         let mut lhs = matrix_new!(loader.clone(), T, 1, VAL_LEN);
         let mut rhs = matrix_new!(loader.clone(), T, 1, VAL_LEN);
@@ -75,7 +103,7 @@ mod matrix_tests {
 
         macro_rules! normal_op_test {
             ($op: ident, $name: literal, $rhs: expr, $chill: expr) => {
-                result = lhs.$op(&rhs);
+                result = lhs.$op(&rhs).into();
                 info!("{:?}{}:{}", result, TXTSHIFT, $name);
 
                 for i in 0..result.A.len() {
@@ -114,7 +142,7 @@ mod matrix_tests {
         info!("{:?}{}:Matrix<T> += Matrix<Vec<T>>", result, TXTSHIFT);
         assert_eq!(result_scalar.A, temp);
 
-        result = &lhs + rhs_scalar;
+        result = (&lhs + rhs_scalar).into();
         info!(
             "{:?}{}:Matrix<Vec<T>> = Matrix<Vec<T>> + T",
             result, TXTSHIFT
@@ -126,7 +154,7 @@ mod matrix_tests {
         normal_op_test!(sub, "Matrix<Vec<T>> - Matrix<Vec<T>>", rhs, false);
         normal_op_test!(sub, "Matrix<Vec<T>> - [T]", rhs.A[..], false);
 
-        result = &lhs - rhs_scalar;
+        result = (&lhs - rhs_scalar).into();
         info!(
             "{:?}{}:Matrix<Vec<T>> = Matrix<Vec<T>> - T",
             result, TXTSHIFT
@@ -138,7 +166,7 @@ mod matrix_tests {
         normal_op_test!(mul, "Matrix<Vec<T>> * Matrix<Vec<T>>", rhs, false);
         normal_op_test!(mul, "Matrix<Vec<T>> * [T]", rhs.A[..], false);
 
-        result = &lhs * rhs_scalar;
+        result = (&lhs * rhs_scalar).into();
         info!(
             "{:?}{}:Matrix<Vec<T>> = Matrix<Vec<T>> * T",
             result, TXTSHIFT
@@ -159,7 +187,7 @@ mod matrix_tests {
         normal_op_test!(div, "Matrix<Vec<T>> / Matrix<Vec<T>>", rhs, true);
         normal_op_test!(div, "Matrix<Vec<T>> / [T]", rhs.A[..], true);
 
-        result = &lhs / rhs_scalar;
+        result = (&lhs / rhs_scalar).into();
         info!(
             "{:?}{}:Matrix<Vec<T>> = Matrix<Vec<T>> / T",
             result, TXTSHIFT
@@ -184,22 +212,661 @@ mod matrix_tests {
     // Test all possible matrix types.
     #[test]
     fn vec_ops_f16() {
-        vec_ops::<f16, 10>();
+        vec_ops::<f16, 10>(false);
     }
 
     #[test]
     fn vec_ops_f32() {
-        vec_ops::<f32, 10>();
+        vec_ops::<f32, 10>(false);
     }
 
     #[test]
     fn vec_ops_f64() {
-        vec_ops::<f64, 10>();
+        vec_ops::<f64, 10>(false);
     }
 
     // Check for some nice index, or off-by-one errors.
     #[test]
     fn vec_ops_f32_large() {
-        vec_ops::<f32, 100>();
+        vec_ops::<f32, 100>(false);
+    }
+
+    // Vectors much larger than a single work-group.
+    fn vec_ops_ndrange(host: bool) {
+        setup();
+        let start = Instant::now();
+
+        let loader = loader::<f32>(host);
+
+        let len = (1 << 20) + 3;
+        let mut rng = oorandom::Rand32::new(10);
+
+        let lhs = Matrix {
+            loader: Some(loader.clone()),
+            A: (0..len)
+                .map(|_| (rng.rand_u32() % 10) as f32)
+                .collect::<Vec<f32>>(),
+        };
+        let rhs = Matrix {
+            loader: Some(loader.clone()),
+            A: (0..len)
+                .map(|_| (rng.rand_u32() % 10) as f32)
+                .collect::<Vec<f32>>(),
+        };
+
+        info!("Global work size: {:?}", loader.global_work_size(len));
+
+        let result = (&lhs * &rhs).eval().unwrap();
+        for i in 0..len {
+            assert_eq!(result.A[i], lhs.A[i] * rhs.A[i], "[{}]", i);
+        }
+
+        timer_end(start);
+    }
+
+    #[test]
+    fn vec_ops_f32_ndrange() {
+        vec_ops_ndrange(false);
+    }
+
+    #[test]
+    fn vec_ops_host_f32_ndrange() {
+        vec_ops_ndrange(true);
+    }
+
+    // In-place operations against matrices, slices and scalars.
+    fn vec_assign(host: bool) {
+        setup();
+        let start = Instant::now();
+
+        let loader = loader::<f32>(host);
+
+        let mut rng = oorandom::Rand32::new(10);
+        let mut random = |len: usize| Matrix {
+            loader: Some(loader.clone()),
+            A: (0..len)
+                .map(|_| (rng.rand_u32() % 10 + 1) as f32)
+                .collect::<Vec<f32>>(),
+        };
+
+        let (a, b) = (random(1000), random(1000));
+
+        let mut result = a.clone();
+        result += &b;
+        assert_eq!(result.A, (&a + &b).eval().unwrap().A);
+
+        result -= &b.A[..];
+        assert_eq!(result.A, a.A);
+
+        result *= 4.0;
+        assert_eq!(result.A, (&a * 4.0).eval().unwrap().A);
+
+        result /= &b;
+        assert_eq!(result.A, (&a * 4.0 / &b).eval().unwrap().A);
+
+        let err = result.try_add_assign(&b.A[..3]).unwrap_err();
+        info!("{}", err);
+        assert!(matches!(
+            err,
+            MatrixError::SizeMismatch { lhs: 1000, rhs: 3 }
+        ));
+
+        timer_end(start);
+    }
+
+    #[test]
+    fn vec_assign_f32() {
+        vec_assign(false);
+    }
+
+    #[test]
+    fn vec_assign_host_f32() {
+        vec_assign(true);
+    }
+
+    // Scalar kernels, also with the scalar on the left side.
+    fn vec_scalar(host: bool) {
+        setup();
+        let start = Instant::now();
+
+        let loader = loader::<f32>(host);
+
+        let mut rng = oorandom::Rand32::new(10);
+
+        // Powers of two, so that every division is exact.
+        let a = Matrix {
+            loader: Some(loader.clone()),
+            A: (0..1000)
+                .map(|_| (1 << (rng.rand_u32() % 4)) as f32)
+                .collect::<Vec<f32>>(),
+        };
+        let da = a.to_device().unwrap();
+
+        let result = (10.0 - &a).eval().unwrap();
+        let device_result = (10.0 - &da).eval_device().unwrap();
+        for i in 0..a.A.len() {
+            assert_eq!(result.A[i], 10.0 - a.A[i]);
+        }
+        assert_eq!(device_result.A.host(), &result.A[..]);
+
+        let result = (16.0 / &a).eval().unwrap();
+        let device_result = (16.0 / &da).eval_device().unwrap();
+        for i in 0..a.A.len() {
+            assert_eq!(result.A[i], 16.0 / a.A[i]);
+        }
+        assert_eq!(device_result.A.host(), &result.A[..]);
+
+        let doubled: Vec<f32> = a.A.iter().map(|a| a * 2.0).collect();
+        assert_eq!((2.0 * &a).eval().unwrap().A, doubled);
+        assert_eq!(
+            (&da + 1.0).eval_device().unwrap().A.host(),
+            &(1.0 + &a).eval().unwrap().A[..]
+        );
+
+        timer_end(start);
+    }
+
+    #[test]
+    fn vec_scalar_f32() {
+        vec_scalar(false);
+    }
+
+    #[test]
+    fn vec_scalar_host_f32() {
+        vec_scalar(true);
+    }
+
+    // Math functions against f64 references. The accuracy of OpenCL builtins is
+    // only guaranteed up to a few ulp, so they are compared with a tolerance.
+    fn vec_math<T: HostPrm>(host: bool, tolerance: f64) {
+        setup();
+        let start = Instant::now();
+
+        let loader = loader::<T>(host);
+
+        let mut rng = oorandom::Rand32::new(10);
+        let mut random = |min: f64, max: f64| Matrix {
+            loader: Some(loader.clone()),
+            A: (0..1000)
+                .map(|_| T::from_f64(min + rng.rand_float() as f64 * (max - min)))
+                .collect::<Vec<T>>(),
+        };
+
+        let positive = random(0.5, 4.0);
+        let signed = random(-4.0, 4.0);
+
+        let check = |name: &str, result: Matrix<Vec<T>>, expected: &dyn Fn(usize) -> f64| {
+            for (i, out) in result.A.iter().enumerate() {
+                let out = out.to_f64();
+                let expected = expected(i);
+
+                assert!(
+                    (out - expected).abs() <= tolerance * expected.abs().max(1.0),
+                    "{}[{}]: {} != {}",
+                    name,
+                    i,
+                    out,
+                    expected
+                );
+            }
+            info!("{}{}ok", name, TXTSHIFT);
+        };
+
+        let p = |i: usize| positive.A[i].to_f64();
+        let s = |i: usize| signed.A[i].to_f64();
+
+        check("sqrt", positive.sqrt(), &|i| p(i).sqrt());
+        check("rsqrt", positive.rsqrt(), &|i| 1.0 / p(i).sqrt());
+        check("exp", signed.exp(), &|i| s(i).exp());
+        check("exp2", signed.exp2(), &|i| s(i).exp2());
+        check("log", positive.log(), &|i| p(i).ln());
+        check("log2", positive.log2(), &|i| p(i).log2());
+        check("sin", signed.sin(), &|i| s(i).sin());
+        check("cos", signed.cos(), &|i| s(i).cos());
+        check("tan", positive.tan(), &|i| p(i).tan());
+        check("tanh", signed.tanh(), &|i| s(i).tanh());
+        check("abs", signed.abs(), &|i| s(i).abs());
+        check("floor", signed.floor(), &|i| s(i).floor());
+        check("ceil", signed.ceil(), &|i| s(i).ceil());
+        check("round", signed.round(), &|i| s(i).round());
+        check("sign", signed.sign(), &|i| s(i).signum());
+        check("recip", positive.recip(), &|i| 1.0 / p(i));
+
+        check("pow", positive.pow(&signed), &|i| p(i).powf(s(i)));
+        check("atan2", signed.atan2(&positive), &|i| s(i).atan2(p(i)));
+        check("hypot", signed.hypot(&positive), &|i| s(i).hypot(p(i)));
+        check("fmod", signed.fmod(&positive), &|i| s(i) % p(i));
+        check("fmin", signed.fmin(&positive), &|i| s(i).min(p(i)));
+        check("fmax", signed.fmax(&positive), &|i| s(i).max(p(i)));
+        check("copysign", positive.copysign(&signed), &|i| {
+            p(i).copysign(s(i))
+        });
+
+        timer_end(start);
+    }
+
+    #[test]
+    fn vec_math_f32() {
+        vec_math::<f32>(false, 1e-5);
+    }
+
+    #[test]
+    fn vec_math_f64() {
+        vec_math::<f64>(false, 1e-12);
+    }
+
+    #[test]
+    fn vec_math_host_f32() {
+        vec_math::<f32>(true, 1e-6);
+    }
+
+    // Reductions of all kinds of lengths, including non-powers-of-two.
+    fn vec_reduce<T>(host: bool)
+    where
+        T: HostPrm + Div<Output = T> + std::convert::From<u8> + std::convert::Into<f64>,
+    {
+        setup();
+        let start = Instant::now();
+
+        let loader = loader::<T>(host);
+
+        let mut rng = oorandom::Rand32::new(10);
+
+        for len in [
+            1,
+            2,
+            3,
+            7,
+            255,
+            256,
+            257,
+            1000,
+            4097,
+            65537,
+            1 << 20,
+            3_000_001,
+        ] {
+            // Small integers, so that every summation order is exact.
+            let data = Matrix {
+                loader: Some(loader.clone()),
+                A: (0..len)
+                    .map(|_| T::from((rng.rand_u32() % 4) as u8))
+                    .collect::<Vec<T>>(),
+            };
+
+            let expected: f64 = data.A.iter().map(|a| (*a).into()).sum();
+            let sum: f64 = data.try_sum().unwrap().A.into();
+
+            info!("len: {}{}sum: {}", len, TXTSHIFT, sum);
+            assert_eq!(sum, expected, "sum of {} elements", len);
+
+            let mean = data.mean().A.to_f64();
+            assert_eq!(mean, T::from_f64(expected / len as f64).to_f64());
+
+            // Lots of ties, which have to go to the first index.
+            let values: Vec<f64> = data.A.iter().map(|a| (*a).into()).collect();
+            let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+            let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+
+            assert_eq!(data.min().A.to_f64(), min, "min of {} elements", len);
+            assert_eq!(data.max().A.to_f64(), max, "max of {} elements", len);
+            assert_eq!(
+                data.argmin(),
+                values.iter().position(|a| *a == min).unwrap()
+            );
+            assert_eq!(
+                data.argmax(),
+                values.iter().position(|a| *a == max).unwrap()
+            );
+
+            if len > 1000 {
+                continue;
+            }
+
+            // Powers of two, so that every multiplication order is exact.
+            let factors = [T::from(1) / T::from(2), T::from(1), T::from(2)];
+            let data = Matrix {
+                loader: Some(loader.clone()),
+                A: (0..len)
+                    .map(|_| factors[rng.rand_u32() as usize % factors.len()])
+                    .collect::<Vec<T>>(),
+            };
+
+            let expected: f64 = data.A.iter().map(|a| (*a).into()).product();
+            let product: f64 = data.try_product().unwrap().A.into();
+
+            assert_eq!(product, expected, "product of {} elements", len);
+        }
+
+        timer_end(start);
+    }
+
+    #[test]
+    fn vec_reduce_f32() {
+        vec_reduce::<f32>(false);
+    }
+
+    #[test]
+    fn vec_reduce_f64() {
+        vec_reduce::<f64>(false);
+    }
+
+    #[test]
+    fn vec_reduce_host_f32() {
+        vec_reduce::<f32>(true);
+    }
+
+    #[test]
+    fn vec_reduce_host_f64() {
+        vec_reduce::<f64>(true);
+    }
+
+    // Statistics against f64 references. The mean is much larger than the spread,
+    // which breaks the naive sum of squares formula.
+    fn vec_stats<T: HostPrm>(host: bool, tolerance: f64) {
+        setup();
+        let start = Instant::now();
+
+        let loader = loader::<T>(host);
+
+        let mut rng = oorandom::Rand32::new(10);
+
+        for len in [1, 2, 255, 10007, 100_003] {
+            let data = Matrix {
+                loader: Some(loader.clone()),
+                A: (0..len)
+                    .map(|_| T::from_f64(100.0 + rng.rand_float() as f64 * 8.0 - 4.0))
+                    .collect::<Vec<T>>(),
+            };
+            let values: Vec<f64> = data.A.iter().map(|a| a.to_f64()).collect();
+
+            let mean = values.iter().sum::<f64>() / len as f64;
+            let m2: f64 = values.iter().map(|a| (a - mean).powi(2)).sum();
+
+            let check = |name: &str, out: T, expected: f64| {
+                let out = out.to_f64();
+                info!(
+                    "len: {}{}{}: {} (expected {})",
+                    len, TXTSHIFT, name, out, expected
+                );
+
+                assert!(
+                    (out - expected).abs() <= tolerance * expected.abs().max(1.0),
+                    "{} of {} elements: {} != {}",
+                    name,
+                    len,
+                    out,
+                    expected
+                );
+            };
+
+            // The sum overflows f16, but the mean must not.
+            check("mean", data.mean().A, mean);
+            check("var", data.var(0).A, m2 / len as f64);
+            check("std", data.std(0).A, (m2 / len as f64).sqrt());
+            if len > 1 {
+                check("var ddof=1", data.var(1).A, m2 / (len - 1) as f64);
+                check("std ddof=1", data.std(1).A, (m2 / (len - 1) as f64).sqrt());
+            } else {
+                assert!(data.var(1).A.to_f64().is_nan());
+            }
+
+            // The sum of the scaled squares doesn't fit into f16 for more than 65504
+            // elements, only the norm itself does.
+            check(
+                "norm_l2 unshifted",
+                data.norm_l2().A,
+                values.iter().map(|a| a * a).sum::<f64>().sqrt(),
+            );
+
+            // Shifted, so that there are negative elements, and scaled down so that
+            // the norms fit into f16.
+            let shifted = ((&data - T::from_f64(100.0)) / T::from_f64(256.0))
+                .eval()
+                .unwrap();
+            let values: Vec<f64> = shifted.A.iter().map(|a| a.to_f64()).collect();
+
+            check(
+                "norm_l1",
+                shifted.norm_l1().A,
+                values.iter().map(|a| a.abs()).sum(),
+            );
+            check(
+                "norm_l2",
+                shifted.norm_l2().A,
+                values.iter().map(|a| a * a).sum::<f64>().sqrt(),
+            );
+            check(
+                "norm_inf",
+                shifted.norm_inf().A,
+                values.iter().fold(0.0, |a, b| a.max(b.abs())),
+            );
+        }
+
+        timer_end(start);
+    }
+
+    #[test]
+    fn vec_stats_f16() {
+        vec_stats::<f16>(false, 2e-2);
+    }
+
+    #[test]
+    fn vec_stats_f32() {
+        vec_stats::<f32>(false, 1e-4);
+    }
+
+    #[test]
+    fn vec_stats_f64() {
+        vec_stats::<f64>(false, 1e-10);
+    }
+
+    #[test]
+    fn vec_stats_host_f32() {
+        vec_stats::<f32>(true, 1e-4);
+    }
+
+    // A spread over 256, so that the variance overflows f16, but the standard
+    // deviation doesn't.
+    #[test]
+    fn vec_stats_wide_f16() {
+        setup();
+
+        let loader = loader::<f16>(false);
+
+        let mut rng = oorandom::Rand32::new(10);
+
+        let data = Matrix {
+            loader: Some(loader.clone()),
+            A: (0..10007)
+                .map(|_| f16::from_f64(rng.rand_float() as f64 * 2000.0 - 1000.0))
+                .collect::<Vec<f16>>(),
+        };
+        let values: Vec<f64> = data.A.iter().map(|a| a.to_f64()).collect();
+
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let var = values.iter().map(|a| (a - mean).powi(2)).sum::<f64>() / values.len() as f64;
+
+        assert!(data.var(0).A.is_infinite());
+
+        let std = data.std(0).A.to_f64();
+        assert!(
+            (std - var.sqrt()).abs() <= 2e-3 * var.sqrt(),
+            "{} != {}",
+            std,
+            var.sqrt()
+        );
+    }
+
+    // NaNs are never picked by min, max, argmin or argmax.
+    #[test]
+    fn vec_reduce_nan() {
+        setup();
+
+        let loader = loader::<f32>(true);
+
+        let data = Matrix {
+            loader: Some(loader.clone()),
+            A: vec![f32::NAN, 3.0, -1.0, f32::NAN, 5.0, -1.0, 5.0],
+        };
+
+        assert_eq!(data.min().A, -1.0);
+        assert_eq!(data.max().A, 5.0);
+        assert_eq!(data.argmin(), 2);
+        assert_eq!(data.argmax(), 4);
+
+        let nan = Matrix {
+            loader: Some(loader.clone()),
+            A: vec![f32::NAN; 3],
+        };
+        assert_eq!(nan.argmin(), 0);
+        assert!(matches!(
+            matrix_new!(loader.clone(), f32, 1).try_argmax(),
+            Err(MatrixError::Empty)
+        ));
+    }
+
+    fn vec_permute<T: HostPrm>(host: bool) {
+        setup();
+        let start = Instant::now();
+
+        let loader = loader::<T>(host);
+
+        let cases: &[(&[usize], &[usize])] = &[
+            (&[2, 3, 4], &[0, 1, 2]),
+            (&[2, 3, 4], &[0, 2, 1]),
+            (&[2, 3, 4], &[1, 0, 2]),
+            (&[2, 3, 4], &[1, 2, 0]),
+            (&[2, 3, 4], &[2, 0, 1]),
+            (&[2, 3, 4], &[2, 1, 0]),
+            (&[37, 53], &[1, 0]),
+            (&[3, 1, 50, 70], &[3, 0, 2, 1]),
+            (&[5, 40, 33, 2], &[1, 3, 0, 2]),
+        ];
+
+        for (shape, axes) in cases {
+            let len = shape.iter().product::<usize>();
+
+            // Every element is its own index, which f16 can hold exactly up to 2048.
+            let a = Matrix {
+                loader: Some(loader.clone()),
+                A: (0..len)
+                    .map(|i| T::from_f64((i % 2048) as f64))
+                    .collect::<Vec<T>>(),
+            };
+
+            // Scatter every input element to its output position.
+            let out_shape = axes.iter().map(|a| shape[*a]).collect::<Vec<usize>>();
+            let mut expected = vec![0.0; len];
+            for (i, value) in a.A.iter().enumerate() {
+                let mut rest = i;
+                let mut index = vec![0; shape.len()];
+                for (axis, dim) in shape.iter().enumerate().rev() {
+                    index[axis] = rest % dim;
+                    rest /= dim;
+                }
+
+                let offset = axes
+                    .iter()
+                    .zip(&out_shape)
+                    .fold(0, |offset, (axis, dim)| offset * dim + index[*axis]);
+                expected[offset] = value.to_f64();
+            }
+
+            let out = a.permute(shape, axes);
+            info!("{:?} {:?}", shape, axes);
+            assert_eq!(
+                out.A.iter().map(|a| a.to_f64()).collect::<Vec<f64>>(),
+                expected
+            );
+        }
+
+        let a = Matrix {
+            loader: Some(loader.clone()),
+            A: vec![T::from_f64(1.0); 24],
+        };
+        assert!(matches!(
+            a.try_permute(&[2, 3, 4], &[0, 0, 1]),
+            Err(MatrixError::InvalidAxes { dims: 3, .. })
+        ));
+        assert!(matches!(
+            a.try_permute(&[2, 3, 4], &[0, 1]),
+            Err(MatrixError::InvalidAxes { dims: 3, .. })
+        ));
+        assert!(matches!(
+            a.try_permute(&[2, 3, 5], &[0, 1, 2]),
+            Err(MatrixError::SizeMismatch { lhs: 30, rhs: 24 })
+        ));
+
+        timer_end(start);
+    }
+
+    #[test]
+    fn vec_permute_f16() {
+        vec_permute::<f16>(false);
+    }
+
+    #[test]
+    fn vec_permute_f32() {
+        vec_permute::<f32>(false);
+    }
+
+    #[test]
+    fn vec_permute_host_f64() {
+        vec_permute::<f64>(true);
+    }
+
+    #[test]
+    fn vec_errors() {
+        setup();
+
+        let loader = loader::<f32>(true);
+
+        let lhs = Matrix {
+            loader: Some(loader.clone()),
+            A: vec![1.0f32; 4],
+        };
+        let rhs = Matrix {
+            loader: Some(loader.clone()),
+            A: vec![1.0f32; 3],
+        };
+        let empty: Matrix<Vec<f32>> = matrix_new!(loader.clone(), f32, 1);
+        let unloaded = Matrix {
+            loader: None,
+            A: vec![1.0f32; 4],
+        };
+
+        let err = lhs.try_add(&rhs).unwrap_err();
+        info!("{}", err);
+        assert!(matches!(err, MatrixError::SizeMismatch { lhs: 4, rhs: 3 }));
+
+        assert!(matches!(empty.try_sum(), Err(MatrixError::Empty)));
+        assert!(matches!(empty.try_mul(&empty), Err(MatrixError::Empty)));
+        assert!(matches!(unloaded.try_div(&lhs), Err(MatrixError::NoLoader)));
+
+        assert_eq!(lhs.try_sub(&lhs).unwrap().A, vec![0.0; 4]);
+        assert_eq!(lhs.try_sum().unwrap().A, 4.0);
+        assert_eq!(lhs.try_product().unwrap().A, 1.0);
+    }
+
+    // The same checks on the host backend.
+    #[test]
+    fn vec_ops_host_f16() {
+        vec_ops::<f16, 10>(true);
+    }
+
+    #[test]
+    fn vec_ops_host_f32() {
+        vec_ops::<f32, 10>(true);
+    }
+
+    #[test]
+    fn vec_ops_host_f64() {
+        vec_ops::<f64, 10>(true);
+    }
+
+    #[test]
+    fn vec_ops_host_f32_large() {
+        vec_ops::<f32, 100>(true);
     }
 }